@@ -0,0 +1,95 @@
+use crate::blocks::{Block, BlockType, Link, UnsureLink};
+use crate::Rai;
+use anyhow::anyhow;
+use std::convert::TryFrom;
+
+/// A version of the rules an account chain follows.
+///
+/// Accounts are upgraded by epoch blocks, which are state blocks that keep the balance and
+/// representative the same, have one of the epoch links, and are signed by the epoch signer of the
+/// network instead of the account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Epoch {
+    /// Accounts that haven't been upgraded.
+    V0 = 0,
+    V1 = 1,
+    V2 = 2,
+}
+
+impl Epoch {
+    /// The epoch an epoch block with this link upgrades to.
+    pub fn from_link(link: &Link) -> Option<Self> {
+        let bytes = link.as_bytes();
+        [Self::V1, Self::V2]
+            .iter()
+            .find(|epoch| epoch.link().as_ref().map(Link::as_bytes) == Some(bytes))
+            .copied()
+    }
+
+    /// The epoch that `block` upgrades its account to, if it's an epoch block.
+    ///
+    /// Only a state block that keeps the balance of its previous block can be an epoch block. A
+    /// send to the account that happens to match an epoch link is still a send.
+    pub fn upgrade_of(block: &Block, previous_balance: &Rai) -> Option<Self> {
+        if block.block_type() != &BlockType::State || block.balance() != previous_balance {
+            return None;
+        }
+        Self::from_link(block.link())
+    }
+
+    /// The link of an epoch block upgrading to this epoch, which is a message padded with zeros.
+    pub fn link(&self) -> Option<Link> {
+        let message: &[u8] = match self {
+            Self::V0 => return None,
+            Self::V1 => b"epoch v1 block",
+            Self::V2 => b"epoch v2 block",
+        };
+        let mut link = [0u8; Link::LEN];
+        link[..message.len()].copy_from_slice(message);
+        // The message always fits in a link.
+        Some(Link::Unsure(UnsureLink::try_from(&link[..]).unwrap()))
+    }
+
+    /// The epoch an account can be upgraded to from this one.
+    pub fn next(&self) -> Option<Self> {
+        match self {
+            Self::V0 => Some(Self::V1),
+            Self::V1 => Some(Self::V2),
+            Self::V2 => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for Epoch {
+    type Error = anyhow::Error;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        Ok(match v {
+            0 => Self::V0,
+            1 => Self::V1,
+            2 => Self::V2,
+            v => return Err(anyhow!("Unknown epoch: {}", v)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn links() {
+        // From the epoch blocks of the live network.
+        let v1 = Link::from_str("65706F636820763120626C6F636B000000000000000000000000000000000000")
+            .unwrap();
+        let v2 = Link::from_str("65706F636820763220626C6F636B000000000000000000000000000000000000")
+            .unwrap();
+        assert_eq!(Epoch::from_link(&v1), Some(Epoch::V1));
+        assert_eq!(Epoch::from_link(&v2), Some(Epoch::V2));
+        assert_eq!(Epoch::from_link(&Link::Nothing), None);
+        assert_eq!(Epoch::V0.next(), Some(Epoch::V1));
+        assert_eq!(Epoch::V2.next(), None);
+    }
+}
@@ -1,6 +1,7 @@
 //! Handling, creating and parsing blocks.
 mod block_hash;
 mod change_block;
+mod epoch;
 mod open_block;
 mod receive_block;
 mod send_block;
@@ -20,13 +21,14 @@ use anyhow::{anyhow, Context};
 pub use block_hash::BlockHash;
 pub use change_block::ChangeBlock;
 use core::convert::TryFrom;
+pub use epoch::Epoch;
 pub use open_block::OpenBlock;
 pub use receive_block::ReceiveBlock;
 pub use send_block::SendBlock;
 use serde;
 use serde::{Deserialize, Serialize};
 pub(crate) use state_block::deserialize_to_unsure_link;
-pub use state_block::{Link, StateBlock, Subtype};
+pub use state_block::{Link, StateBlock, Subtype, UnsureLink};
 use strum_macros::EnumString;
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, EnumString)]
@@ -56,6 +58,27 @@ impl BlockType {
     }
 }
 
+#[cfg(feature = "node")]
+impl Wire for BlockType {
+    fn serialize(&self) -> Vec<u8> {
+        vec![self.as_u8()]
+    }
+
+    fn deserialize(_: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        BlockType::try_from(data[0])
+    }
+
+    fn len(_: Option<&Header>) -> anyhow::Result<usize>
+    where
+        Self: Sized,
+    {
+        Ok(1)
+    }
+}
+
 impl TryFrom<u8> for BlockType {
     type Error = anyhow::Error;
 
@@ -85,10 +108,89 @@ pub enum BlockHolder {
     State(StateBlock),
 }
 
+impl BlockHolder {
+    /// The hash of the block, which only depends on fields that are part of the wire format.
+    pub fn hash(&self) -> BlockHash {
+        match self {
+            BlockHolder::Send(b) => hash_block(&[
+                b.previous.as_bytes(),
+                b.destination.as_bytes(),
+                b.balance.to_vec().as_slice(),
+            ]),
+            BlockHolder::Receive(b) => hash_block(&[b.previous.as_bytes(), b.source.as_bytes()]),
+            BlockHolder::Open(b) => hash_block(&[
+                b.source.as_bytes(),
+                b.representative.as_bytes(),
+                b.account.as_bytes(),
+            ]),
+            BlockHolder::Change(b) => {
+                hash_block(&[b.previous.as_bytes(), b.representative.as_bytes()])
+            }
+            BlockHolder::State(b) => {
+                let mut preamble = [0u8; 32];
+                preamble[31] = BlockType::State as u8;
+                hash_block(&[
+                    &preamble,
+                    b.account.as_bytes(),
+                    b.previous.as_bytes(),
+                    b.representative.as_bytes(),
+                    b.balance.to_vec().as_slice(),
+                    b.link.as_bytes(),
+                ])
+            }
+        }
+    }
+
+    /// The previous block hash, or the account for the first block of an account. This is also
+    /// what the work of the block is generated for.
+    pub fn root(&self) -> BlockHash {
+        match self {
+            BlockHolder::Send(b) => b.previous.to_owned(),
+            BlockHolder::Receive(b) => b.previous.to_owned(),
+            BlockHolder::Change(b) => b.previous.to_owned(),
+            // These unwraps should never fail because a public key is the same length as a hash.
+            BlockHolder::Open(b) => BlockHash::try_from(b.account.as_bytes()).unwrap(),
+            BlockHolder::State(b) => {
+                if b.previous == BlockHash::zero() {
+                    BlockHash::try_from(b.account.as_bytes()).unwrap()
+                } else {
+                    b.previous.to_owned()
+                }
+            }
+        }
+    }
+
+    pub fn work(&self) -> Option<&Work> {
+        match self {
+            BlockHolder::Send(b) => b.work.as_ref(),
+            BlockHolder::Receive(b) => b.work.as_ref(),
+            BlockHolder::Open(b) => b.work.as_ref(),
+            BlockHolder::Change(b) => b.work.as_ref(),
+            BlockHolder::State(b) => b.work.as_ref(),
+        }
+    }
+
+    pub fn block_type(&self) -> BlockType {
+        match self {
+            BlockHolder::Send(_) => BlockType::Send,
+            BlockHolder::Receive(_) => BlockType::Receive,
+            BlockHolder::Open(_) => BlockType::Open,
+            BlockHolder::Change(_) => BlockType::Change,
+            BlockHolder::State(_) => BlockType::State,
+        }
+    }
+}
+
 #[cfg(feature = "node")]
 impl Wire for BlockHolder {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        match self {
+            BlockHolder::Send(b) => Wire::serialize(b),
+            BlockHolder::Receive(b) => Wire::serialize(b),
+            BlockHolder::Open(b) => Wire::serialize(b),
+            BlockHolder::Change(b) => Wire::serialize(b),
+            BlockHolder::State(b) => Wire::serialize(b),
+        }
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -105,11 +207,18 @@ impl Wire for BlockHolder {
             .block_type()
             .context(context)?
         {
+            BlockType::Send => BlockHolder::Send(Wire::deserialize(header, data).context(context)?),
+            BlockType::Receive => {
+                BlockHolder::Receive(Wire::deserialize(header, data).context(context)?)
+            }
+            BlockType::Open => BlockHolder::Open(Wire::deserialize(header, data).context(context)?),
+            BlockType::Change => {
+                BlockHolder::Change(Wire::deserialize(header, data).context(context)?)
+            }
             BlockType::State => {
                 BlockHolder::State(Wire::deserialize(header, data).context(context)?)
             }
-            BlockType::Send => BlockHolder::Send(Wire::deserialize(header, data).context(context)?),
-            _ => todo!(),
+            block_type => return Err(anyhow!("{:?} is not a block", block_type)).context(context),
         };
         Ok(holder)
     }
@@ -120,9 +229,12 @@ impl Wire for BlockHolder {
     {
         debug_assert!(header.is_some());
         match header.as_ref().unwrap().ext().block_type()? {
-            BlockType::State => StateBlock::len(header),
             BlockType::Send => SendBlock::len(header),
-            _ => todo!(),
+            BlockType::Receive => ReceiveBlock::len(header),
+            BlockType::Open => OpenBlock::len(header),
+            BlockType::Change => ChangeBlock::len(header),
+            BlockType::State => StateBlock::len(header),
+            block_type => Err(anyhow!("{:?} is not a block", block_type)),
         }
     }
 }
@@ -252,6 +364,41 @@ impl Block {
         b
     }
 
+    pub fn from_receive_block(
+        receive_block: &ReceiveBlock,
+        account: &Public,
+        representative: &Public,
+        balance: &Rai,
+    ) -> Self {
+        let mut b = Self::new(
+            BlockType::Receive,
+            account.to_owned(),
+            Previous::Block(receive_block.previous.to_owned()),
+            representative.to_owned(),
+            balance.to_owned(),
+            Link::Source(receive_block.source.to_owned()),
+            ValidationState::Valid,
+        );
+        b.signature = receive_block.signature.to_owned();
+        b.work = receive_block.work.to_owned();
+        b
+    }
+
+    pub fn from_change_block(change_block: &ChangeBlock, account: &Public, balance: &Rai) -> Self {
+        let mut b = Self::new(
+            BlockType::Change,
+            account.to_owned(),
+            Previous::Block(change_block.previous.to_owned()),
+            change_block.representative.to_owned(),
+            balance.to_owned(),
+            Link::Nothing,
+            ValidationState::Valid,
+        );
+        b.signature = change_block.signature.to_owned();
+        b.work = change_block.work.to_owned();
+        b
+    }
+
     pub fn from_state_block(state_block: &StateBlock) -> Self {
         let mut b = Self::new(
             BlockType::State,
@@ -267,6 +414,110 @@ impl Block {
         b
     }
 
+    /// A state block with its link worked out by comparing its balance against the balance of the
+    /// previous block, which is zero for the first block of an account.
+    pub fn from_state_block_with_previous_balance(
+        state_block: &StateBlock,
+        previous_balance: &Rai,
+        state: ValidationState,
+    ) -> anyhow::Result<Self> {
+        let previous = if state_block.previous == BlockHash::zero() {
+            Previous::Open
+        } else {
+            Previous::Block(state_block.previous.to_owned())
+        };
+
+        let link_bytes = state_block.link.as_bytes();
+        let link = if &state_block.balance > previous_balance {
+            Link::Source(BlockHash::try_from(link_bytes)?)
+        } else if &state_block.balance < previous_balance {
+            Link::DestinationAccount(Public::try_from(link_bytes)?)
+        } else if link_bytes == [0u8; Link::LEN] {
+            Link::Nothing
+        } else {
+            state_block.link.to_owned()
+        };
+
+        let mut b = Self::new(
+            BlockType::State,
+            state_block.account.to_owned(),
+            previous,
+            state_block.representative.to_owned(),
+            state_block.balance.to_owned(),
+            link,
+            state,
+        );
+        b.signature = state_block.signature.to_owned();
+        b.work = state_block.work.to_owned();
+        Ok(b)
+    }
+
+    /// Convert back into the block type specific structure, e.g. to send over the network.
+    pub fn to_holder(&self) -> anyhow::Result<BlockHolder> {
+        let previous_hash = || match &self.previous {
+            Previous::Block(hash) => Ok(hash.to_owned()),
+            Previous::Open => Err(anyhow!(
+                "{:?} block without a previous block",
+                self.block_type
+            )),
+        };
+
+        let holder = match self.block_type {
+            BlockType::Send => {
+                let mut b = SendBlock::new(
+                    previous_hash()?,
+                    self.destination()?.to_owned(),
+                    self.balance.to_owned(),
+                );
+                b.work = self.work.to_owned();
+                b.signature = self.signature.to_owned();
+                BlockHolder::Send(b)
+            }
+            BlockType::Receive => {
+                let mut b = ReceiveBlock::new(previous_hash()?, self.source()?.to_owned());
+                b.work = self.work.to_owned();
+                b.signature = self.signature.to_owned();
+                BlockHolder::Receive(b)
+            }
+            BlockType::Change => {
+                let mut b = ChangeBlock::new(previous_hash()?, self.representative.to_owned());
+                b.work = self.work.to_owned();
+                b.signature = self.signature.to_owned();
+                BlockHolder::Change(b)
+            }
+            BlockType::Open => {
+                let mut b = OpenBlock::new(
+                    self.source()?.to_owned(),
+                    self.representative.to_owned(),
+                    self.account.to_owned(),
+                );
+                b.work = self.work.to_owned();
+                b.signature = self.signature.to_owned();
+                BlockHolder::Open(b)
+            }
+            BlockType::State => {
+                let previous = BlockHash::try_from(self.previous.to_bytes().as_slice())?;
+                let mut b = StateBlock::new(
+                    self.account.to_owned(),
+                    previous,
+                    self.representative.to_owned(),
+                    self.balance.to_owned(),
+                    self.link.to_owned(),
+                );
+                b.work = self.work.to_owned();
+                b.signature = self.signature.to_owned();
+                BlockHolder::State(b)
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Converting a {:?} block is not supported yet",
+                    self.block_type
+                ))
+            }
+        };
+        Ok(holder)
+    }
+
     pub fn hash(&self) -> anyhow::Result<&BlockHash> {
         match &self.hash {
             Some(block_hash) => Ok(&block_hash),
@@ -371,13 +622,31 @@ impl Block {
         &self.balance
     }
 
+    pub fn validation_state(&self) -> &ValidationState {
+        &self.state
+    }
+
     pub fn previous(&self) -> &Previous {
         &self.previous
     }
 
+    pub fn link(&self) -> &Link {
+        &self.link
+    }
+
+    /// The root is what blocks competing for the same spot in an account chain have in common:
+    /// the previous block hash, or the account for the first block of an account.
+    pub fn root(&self) -> BlockHash {
+        match &self.previous {
+            Previous::Block(hash) => hash.to_owned(),
+            // This unwrap should never fail because a public key is the same length as a hash.
+            Previous::Open => BlockHash::try_from(self.account.as_bytes()).unwrap(),
+        }
+    }
+
     /// For an open or recv block, get the sender's block hash, otherwise Err.
     pub fn source(&self) -> anyhow::Result<&BlockHash> {
-        if self.block_type != BlockType::Open {
+        if self.block_type != BlockType::Open && self.block_type != BlockType::Receive {
             return Err(anyhow!(
                 "Source requested for a {:?} block",
                 self.block_type
@@ -440,4 +709,84 @@ mod tests {
         assert!(a.contains(r#"work": "62F"#));
         assert!(a.contains(r#"signature": "9F"#));
     }
+
+    /// A real open block from the live network, as it's sent on the network.
+    #[cfg(feature = "node")]
+    #[test]
+    fn open_wire_fixture() {
+        use super::{Block, BlockHolder, BlockType, Previous};
+        use crate::encoding::to_hex;
+        use crate::node::header::{Extensions, MessageType};
+        use crate::node::{Header, Wire};
+        use crate::Rai;
+
+        let fixture = concat!(
+            "A170D51B94E00371ACE76E35AC81DC9405D5D04D4CEBC399AEACE07AE05DD293",
+            "2399A083C600AA0572F5E36247D978FCFC840405F8D4B6D33161C0066A55F431",
+            "059F68AAB29DE0D3A27443625C7EA9CDDB6517A8B76FE37727EF6A4D76832AD5",
+            "E950FFDF0C9C4DAF43C27AE3993378E4D8AD6FA591C24497C53E07A3BC804685",
+            "39B0A467992A916F0DDA6F267AD764A3C1A5BDBD8F489DFAE8175EEE0E337402",
+            "B1A152A497C097E9",
+        );
+        let header = Header::new(
+            Network::Live,
+            MessageType::Publish,
+            *Extensions::new().set_block_type(&BlockType::Open),
+        );
+        let data = hex::decode(fixture).unwrap();
+        assert_eq!(BlockHolder::len(Some(&header)).unwrap(), data.len());
+
+        let holder = BlockHolder::deserialize(Some(&header), &data).unwrap();
+        let open = match &holder {
+            BlockHolder::Open(open) => open,
+            _ => panic!("Expected an open block"),
+        };
+        let block = Block::from_open_block(open, &Previous::Open, &Rai::zero());
+        assert_eq!(
+            block.hash().unwrap().as_hex(),
+            "90D0C16AC92DD35814E84BFBCC739A039615D0A42A76EF44ADAEF1D99E9F8A35"
+        );
+        assert!(block.verify_signature(block.account()).is_ok());
+        assert_eq!(&holder.hash(), block.hash().unwrap());
+        assert_eq!(block.to_holder().unwrap(), holder);
+        assert_eq!(to_hex(&holder.serialize()), fixture);
+    }
+
+    #[cfg(feature = "node")]
+    #[test]
+    fn legacy_round_trip() {
+        use super::{Block, BlockHash, BlockHolder, ChangeBlock, ReceiveBlock};
+        use crate::node::header::{Extensions, MessageType};
+        use crate::node::{Header, Wire};
+        use crate::{Rai, Seed, Work};
+
+        let private = Seed::random().derive(0);
+        let account = private.to_public().unwrap();
+        let previous = BlockHash::zero();
+        let representative = Seed::random().derive(0).to_public().unwrap();
+
+        let receive = ReceiveBlock::new(previous.clone(), BlockHash::zero());
+        let change = ChangeBlock::new(previous, representative.clone());
+        let blocks = vec![
+            Block::from_receive_block(&receive, &account, &representative, &Rai::from(5)),
+            Block::from_change_block(&change, &account, &Rai::from(5)),
+        ];
+
+        for mut block in blocks {
+            block.sign(private.clone()).unwrap();
+            block.set_work(Work::zero());
+
+            let holder = block.to_holder().unwrap();
+            let header = Header::new(
+                Network::Live,
+                MessageType::Publish,
+                *Extensions::new().set_block_type(&holder.block_type()),
+            );
+            let data = holder.serialize();
+            assert_eq!(BlockHolder::len(Some(&header)).unwrap(), data.len());
+            let decoded = BlockHolder::deserialize(Some(&header), &data).unwrap();
+            assert_eq!(decoded, holder);
+            assert_eq!(&decoded.hash(), block.hash().unwrap());
+        }
+    }
 }
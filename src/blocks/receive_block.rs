@@ -1,11 +1,78 @@
+#[cfg(feature = "node")]
+use crate::node::Header;
+
+#[cfg(feature = "node")]
+use crate::node::Wire;
+
 use crate::blocks::BlockHash;
-use crate::{Public, Signature, Work};
+use crate::bytes::Bytes;
+use crate::{Signature, Work};
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ReceiveBlock {
-    previous: BlockHash,
-    source: Public,
+    /// The hash of the previous block in this account.
+    pub previous: BlockHash,
+
+    /// The hash of the send block that is being received.
+    pub source: BlockHash,
+
     pub work: Option<Work>,
     pub signature: Option<Signature>,
 }
+
+impl ReceiveBlock {
+    pub const LEN: usize = 136;
+
+    pub fn new(previous: BlockHash, source: BlockHash) -> Self {
+        Self {
+            previous,
+            source,
+            work: None,
+            signature: None,
+        }
+    }
+}
+
+#[cfg(feature = "node")]
+impl Wire for ReceiveBlock {
+    fn serialize(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.previous.as_bytes());
+        v.extend_from_slice(self.source.as_bytes());
+        v.extend_from_slice(
+            self.signature
+                .as_ref()
+                .unwrap_or(&Signature::zero())
+                .as_bytes(),
+        );
+        v.extend_from_slice(&self.work.as_ref().unwrap_or(&Work::zero()).to_le_bytes());
+        v
+    }
+
+    fn deserialize(_: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut data = Bytes::new(data);
+        let previous = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
+        let source = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
+        let signature = Some(Signature::try_from(data.slice(Signature::LEN)?)?);
+        let work = Some(Work::from_le_slice(data.slice(Work::LEN)?)?);
+
+        Ok(Self {
+            previous,
+            source,
+            work,
+            signature,
+        })
+    }
+
+    fn len(_: Option<&Header>) -> anyhow::Result<usize>
+    where
+        Self: Sized,
+    {
+        Ok(Self::LEN)
+    }
+}
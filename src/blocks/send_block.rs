@@ -47,7 +47,18 @@ impl SendBlock {
 #[cfg(feature = "node")]
 impl Wire for SendBlock {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.previous.as_bytes());
+        v.extend_from_slice(self.destination.as_bytes());
+        v.extend_from_slice(&self.balance.to_vec());
+        v.extend_from_slice(
+            self.signature
+                .as_ref()
+                .unwrap_or(&Signature::zero())
+                .as_bytes(),
+        );
+        v.extend_from_slice(&self.work.as_ref().unwrap_or(&Work::zero()).to_le_bytes());
+        v
     }
 
     fn deserialize(_: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -58,8 +69,8 @@ impl Wire for SendBlock {
         let previous = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
         let destination = Public::try_from(data.slice(Public::LEN)?)?;
         let balance = Rai::try_from(data.slice(Rai::LEN)?)?;
-        let work = Some(Work::try_from(data.slice(Work::LEN)?)?);
         let signature = Some(Signature::try_from(data.slice(Signature::LEN)?)?);
+        let work = Some(Work::from_le_slice(data.slice(Work::LEN)?)?);
 
         Ok(Self {
             previous,
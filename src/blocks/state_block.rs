@@ -81,7 +81,21 @@ impl StateBlock {
 #[cfg(feature = "node")]
 impl Wire for StateBlock {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.account.as_bytes());
+        v.extend_from_slice(self.previous.as_bytes());
+        v.extend_from_slice(self.representative.as_bytes());
+        v.extend_from_slice(&self.balance.to_vec());
+        v.extend_from_slice(self.link.as_bytes());
+        v.extend_from_slice(
+            self.signature
+                .as_ref()
+                .unwrap_or(&Signature::zero())
+                .as_bytes(),
+        );
+        // Unlike legacy blocks, work is big endian in state blocks.
+        v.extend_from_slice(self.work.as_ref().unwrap_or(&Work::zero()).as_bytes());
+        v
     }
 
     fn deserialize(_header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -161,6 +175,44 @@ mod tests {
             .unwrap()
         )
     }
+
+    /// The same block as above, as it's sent on the network.
+    #[cfg(feature = "node")]
+    #[test]
+    fn wire_fixture() {
+        use crate::blocks::BlockType;
+        use crate::encoding::to_hex;
+        use crate::network::Network;
+        use crate::node::header::{Extensions, MessageType};
+        use crate::node::{Header, Wire};
+
+        let fixture = concat!(
+            "8AD883D7DE7C3B15B26BC69400AC5DC7CCE4ABD973DD9876420E1A361C3C2EFC",
+            "7837C80964CAD551DEABE162C7FC4BB58688A0C6EB6D9907C0D2A7C74A33C7EB",
+            "8AD883D7DE7C3B15B26BC69400AC5DC7CCE4ABD973DD9876420E1A361C3C2EFC",
+            "00000022393B53A953F69AABEDF83727",
+            "0399B19B022D260F3DDFBA26D0306D423F1890D3AE06136FAB16802D1F2B87A7",
+            "BCF9F123138355AE9E741912D319FF48E5FCCA39D9E5DD74411D32C69B1C7501",
+            "A0BF001C45D4F68CB561B902A42711E6166B9018E76C50CC868EF2E32B78F200",
+            "D4757052401B9E08",
+        );
+        let header = Header::new(
+            Network::Live,
+            MessageType::Publish,
+            *Extensions::new().set_block_type(&BlockType::State),
+        );
+        let data = hex::decode(fixture).unwrap();
+        assert_eq!(StateBlock::len(Some(&header)).unwrap(), data.len());
+
+        let state_block = StateBlock::deserialize(Some(&header), &data).unwrap();
+        let block = Block::from_state_block(&state_block);
+        assert_eq!(
+            block.hash().unwrap().as_hex(),
+            "6F050D3D0B19C2C206046AAE2D46661B57E1B7D890DE8398D203A025E29A4AD9"
+        );
+        assert!(block.verify_signature(block.account()).is_ok());
+        assert_eq!(to_hex(&state_block.serialize()), fixture);
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
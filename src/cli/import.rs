@@ -0,0 +1,38 @@
+use crate::cli::NetworkOpts;
+use clap::Clap;
+use std::path::PathBuf;
+
+/// Import the ledger of a nano-node `data.ldb` file, instead of bootstrapping from the network.
+#[derive(Clap)]
+pub(crate) struct ImportOpts {
+    /// Path to the `data.ldb` file. The nano-node should be stopped while this runs.
+    path: PathBuf,
+
+    #[clap(flatten)]
+    network: NetworkOpts,
+
+    /// Directory of the ledger database to import into. It should not have a ledger yet.
+    /// Defaults to the same directory as the node.
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+}
+
+#[cfg(feature = "node")]
+impl ImportOpts {
+    pub async fn handle(&self) -> anyhow::Result<()> {
+        use crate::cli::data_dir;
+        use crate::node::{import_ldb, SledDiskState};
+        use std::sync::Arc;
+        use tokio::sync::Mutex;
+
+        let network = self.network.network()?;
+        let state = SledDiskState::new(network, &data_dir(&self.data_dir)?)?;
+        let state: crate::node::ArcState = Arc::new(Mutex::new(state));
+        let summary = import_ldb(&self.path, network, &state).await?;
+        println!(
+            "Imported {} accounts, {} blocks, {} pending and {} confirmation heights",
+            summary.accounts, summary.blocks, summary.pending, summary.confirmation_heights
+        );
+        Ok(())
+    }
+}
@@ -1,13 +1,16 @@
+use crate::cli::import::ImportOpts;
 use crate::cli::pcap::PcapDumpOpts;
+use crate::cli::snapshot::SnapshotOpts;
 use crate::cli::unit::UnitOpts;
 use crate::cli::vanity::VanityOpts;
 use crate::cli::verify::VerifyOpts;
 use crate::cli::wallet::WalletOpts;
 use crate::cli::work::WorkOpts;
 use crate::debug::parse_pcap_log_file_to_csv;
-use crate::network::Network;
+use crate::network::{DevGenesis, Network};
 use crate::node::Node;
 use crate::rpc::client::RPCClientOpts;
+use crate::Private;
 use address::AddressOpts;
 use anyhow::{anyhow, Context};
 use clap::Clap;
@@ -24,11 +27,13 @@ use tracing::Level;
 use tracing_subscriber::EnvFilter;
 
 mod address;
+mod import;
 mod pcap;
 mod phrase;
 mod private;
 mod public;
 mod seed;
+mod snapshot;
 mod unit;
 mod vanity;
 mod verify;
@@ -55,6 +60,12 @@ enum Command {
     /// Launches a node
     Node(NodeOpts),
 
+    /// Import a ledger from a nano-node data.ldb file.
+    Import(ImportOpts),
+
+    /// Export or import a ledger snapshot.
+    Snapshot(SnapshotOpts),
+
     /// Conversion between units, e.g. Rai to Nano
     Unit(UnitOpts),
 
@@ -95,11 +106,62 @@ enum Command {
     Debug(DebugOpts),
 }
 
+// This is used with `#[clap(flatten)]` by every command that opens a ledger.
+#[derive(Clap)]
+pub(crate) struct NetworkOpts {
+    /// Which network the ledger belongs to: live, beta or test.
+    #[clap(long, default_value = "live")]
+    network: Network,
+
+    /// Use a private dev network instead, whose genesis account is this private key. Every node
+    /// of the dev network needs the same key.
+    #[clap(long)]
+    dev_genesis: Option<Private>,
+}
+
+impl NetworkOpts {
+    pub(crate) fn network(&self) -> anyhow::Result<Network> {
+        Ok(match &self.dev_genesis {
+            Some(private) => Network::Dev(DevGenesis::new(private)?),
+            None => self.network,
+        })
+    }
+}
+
 #[derive(Clap)]
 struct NodeOpts {
+    #[clap(flatten)]
+    network: NetworkOpts,
+
     /// Comma separated list of IP:PORT pairs. Overrides default initial nodes.
     #[clap(short, long)]
     override_peers: Option<Vec<String>>,
+
+    /// Download the ledger from the first peer by comparing frontiers.
+    #[clap(long)]
+    bootstrap: bool,
+
+    /// IP:PORT to accept connections from other peers on. Defaults to the port of the network on
+    /// all interfaces.
+    #[clap(long)]
+    listen: Option<SocketAddr>,
+
+    /// Don't accept connections from other peers.
+    #[clap(long)]
+    no_listen: bool,
+
+    /// Maximum number of peers connected to us at the same time.
+    #[clap(long, default_value = "64")]
+    max_inbound: usize,
+
+    /// Directory to keep the ledger database in. Defaults to a `feeless` directory in the data
+    /// directory of the user, e.g. `~/.local/share/feeless` on Linux.
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Keep the ledger in memory instead of on disk. Nothing is kept after the node stops.
+    #[clap(long)]
+    memory: bool,
 }
 
 #[derive(Clap)]
@@ -137,8 +199,22 @@ pub async fn run() -> anyhow::Result<()> {
     match opts.command {
         #[cfg(feature = "node")]
         Command::Node(o) => {
-            let mut node = Node::new(Network::Live);
+            let network = o.network.network()?;
+            let mut node = if o.memory {
+                Node::new(network)
+            } else {
+                Node::with_data_dir(network, &data_dir(&o.data_dir)?)?
+            };
             node.enable_rpc_server().await?;
+            if o.bootstrap {
+                node.enable_bootstrap();
+            }
+            if o.no_listen {
+                node.set_listen_addr(None);
+            } else if let Some(listen) = o.listen {
+                node.set_listen_addr(Some(listen));
+            }
+            node.set_max_inbound(o.max_inbound);
             if let Some(str_addrs) = o.override_peers {
                 let mut socket_addrs = vec![];
                 for str_addr in str_addrs {
@@ -156,6 +232,16 @@ pub async fn run() -> anyhow::Result<()> {
         #[cfg(not(feature = "node"))]
         Command::Node(_) => panic!("Compile with the `node` feature to enable this."),
 
+        #[cfg(feature = "node")]
+        Command::Import(o) => o.handle().await,
+        #[cfg(not(feature = "node"))]
+        Command::Import(_) => panic!("Compile with the `node` feature to enable this."),
+
+        #[cfg(feature = "node")]
+        Command::Snapshot(o) => o.handle().await,
+        #[cfg(not(feature = "node"))]
+        Command::Snapshot(_) => panic!("Compile with the `node` feature to enable this."),
+
         #[cfg(feature = "pcap")]
         Command::Pcap(o) => o.handle().await,
         #[cfg(not(feature = "pcap"))]
@@ -238,3 +324,26 @@ where
         }
     }
 }
+
+/// The directory given with `--data_dir`, or else the default directory of the ledger database
+/// for this user.
+#[cfg(feature = "node")]
+fn data_dir(data_dir: &Option<PathBuf>) -> anyhow::Result<PathBuf> {
+    if let Some(data_dir) = data_dir {
+        return Ok(data_dir.to_owned());
+    }
+    let var = |name: &str| {
+        env::var_os(name)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = if cfg!(windows) {
+        var("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        var("XDG_DATA_HOME").or_else(|| var("HOME").map(|home| home.join(".local").join("share")))
+    };
+    let base = base.ok_or_else(|| anyhow!("Could not find a data directory, set --data_dir"))?;
+    Ok(base.join("feeless"))
+}
@@ -0,0 +1,77 @@
+use crate::cli::NetworkOpts;
+use clap::Clap;
+use std::path::PathBuf;
+
+/// Export the ledger to a snapshot file, or start a ledger from one.
+#[derive(Clap)]
+pub(crate) struct SnapshotOpts {
+    #[clap(subcommand)]
+    command: SnapshotCommand,
+}
+
+#[derive(Clap)]
+enum SnapshotCommand {
+    /// Write the ledger to a snapshot file.
+    Export(SnapshotFileOpts),
+
+    /// Load a snapshot file into a ledger, which should not have any blocks yet.
+    Import(SnapshotFileOpts),
+}
+
+#[derive(Clap)]
+struct SnapshotFileOpts {
+    /// Path to the snapshot file.
+    path: PathBuf,
+
+    #[clap(flatten)]
+    network: NetworkOpts,
+
+    /// Directory of the ledger database.
+    /// Defaults to the same directory as the node.
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+}
+
+#[cfg(feature = "node")]
+impl SnapshotOpts {
+    pub async fn handle(&self) -> anyhow::Result<()> {
+        use crate::cli::data_dir;
+        use crate::node::{export_snapshot, import_snapshot, ArcState, SledDiskState};
+        use anyhow::Context;
+        use std::fs::File;
+        use std::sync::Arc;
+        use tokio::sync::Mutex;
+
+        let (opts, summary) = match &self.command {
+            SnapshotCommand::Export(o) => {
+                let network = o.network.network()?;
+                let state: ArcState = Arc::new(Mutex::new(SledDiskState::new(
+                    network,
+                    &data_dir(&o.data_dir)?,
+                )?));
+                let file =
+                    File::create(&o.path).with_context(|| format!("Creating {:?}", &o.path))?;
+                (o, export_snapshot(network, &state, file).await?)
+            }
+            SnapshotCommand::Import(o) => {
+                let network = o.network.network()?;
+                let state: ArcState = Arc::new(Mutex::new(SledDiskState::new(
+                    network,
+                    &data_dir(&o.data_dir)?,
+                )?));
+                let file = File::open(&o.path).with_context(|| format!("Opening {:?}", &o.path))?;
+                (o, import_snapshot(network, &state, file).await?)
+            }
+        };
+        println!(
+            "{:?}: {} accounts, {} blocks, {} pending, {} rep weights and {} confirmation heights",
+            opts.path,
+            summary.accounts,
+            summary.blocks,
+            summary.pending,
+            summary.rep_weights,
+            summary.confirmation_heights
+        );
+        Ok(())
+    }
+}
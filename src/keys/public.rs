@@ -23,6 +23,11 @@ impl Public {
     pub const LEN: usize = 32;
     const ADDRESS_CHECKSUM_LEN: usize = 5;
 
+    /// An all zero public key, which is used as a placeholder or terminator in some messages.
+    pub fn zero() -> Self {
+        Self([0u8; Self::LEN])
+    }
+
     fn dalek_key(&self) -> Result<ed25519_dalek::PublicKey, Error> {
         Ok(
             ed25519_dalek::PublicKey::from_bytes(&self.0).map_err(|e| Error::SignatureError {
@@ -43,6 +43,9 @@
 #[cfg(feature = "node")]
 mod node;
 
+#[cfg(feature = "simulation")]
+pub use node::simulation;
+
 #[cfg(feature = "pcap")]
 mod pcap;
 
@@ -1,15 +1,83 @@
-use crate::blocks::{Block, BlockHash, OpenBlock, Previous};
-use crate::Rai;
+use crate::blocks::{Block, BlockHash, Epoch, OpenBlock, Previous, Subtype};
+use crate::{Address, Difficulty, Private, Public, Rai, Signature, Subject, Work, DEFAULT_PORT};
 use anyhow::anyhow;
 use std::convert::TryFrom;
+use std::fmt::{Debug, Display, Formatter};
 use std::str::FromStr;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
-#[repr(u8)]
 pub enum Network {
-    Test = 0x41,
-    Beta = 0x42,
-    Live = 0x43,
+    Test,
+    Beta,
+    Live,
+
+    /// A private network for trying things out, e.g. a cluster of nodes on one machine. Only
+    /// feeless knows about it.
+    Dev(DevGenesis),
+}
+
+/// The genesis block of a [Network::Dev] network, which is made from a private key.
+///
+/// Only what's needed to rebuild the block is kept, so that [Network] can stay [Copy].
+#[derive(Clone, Copy, PartialEq)]
+pub struct DevGenesis {
+    account: [u8; Public::LEN],
+    signature: [u8; Signature::LEN],
+    work: [u8; Work::LEN],
+}
+
+impl DevGenesis {
+    /// Sign the genesis block of the account of `private`, and generate its work.
+    ///
+    /// The same key always gives the same genesis block, so every node of a dev network just needs
+    /// to be given the same key.
+    pub fn new(private: &Private) -> anyhow::Result<Self> {
+        let account = private.to_public()?;
+        let mut genesis = Self {
+            account: <[u8; Public::LEN]>::try_from(account.as_bytes())?,
+            signature: [0u8; Signature::LEN],
+            work: [0u8; Work::LEN],
+        };
+        let hash = Network::Dev(genesis).genesis_hash();
+        let signature = private.sign(hash.as_bytes())?;
+        genesis.signature = <[u8; Signature::LEN]>::try_from(signature.as_bytes())?;
+        let threshold = Network::Dev(genesis).work_threshold(Epoch::V0, &Subtype::Open);
+        // Count up from zero instead of starting at random, so every node makes the same block.
+        let subject = Subject::Public(account);
+        let mut nonce = 0u64;
+        let work = loop {
+            let work = Work::try_from(&nonce.to_be_bytes()[..])?;
+            if work.verify(&subject, &threshold)? {
+                break work;
+            }
+            nonce += 1;
+        };
+        genesis.work = <[u8; Work::LEN]>::try_from(work.as_bytes())?;
+        Ok(genesis)
+    }
+
+    pub fn account(&self) -> Public {
+        // The length is always right.
+        Public::try_from(&self.account[..]).unwrap()
+    }
+
+    fn open_block(&self) -> anyhow::Result<OpenBlock> {
+        let account = self.account();
+        let mut block = OpenBlock::new(
+            BlockHash::try_from(account.as_bytes())?,
+            account.to_owned(),
+            account,
+        );
+        block.signature = Some(Signature::try_from(&self.signature[..])?);
+        block.work = Some(Work::try_from(&self.work[..])?);
+        Ok(block)
+    }
+}
+
+impl Debug for DevGenesis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DevGenesis({})", self.account().to_address())
+    }
 }
 
 fn live_genesis_block() -> OpenBlock {
@@ -27,11 +95,45 @@ fn live_genesis_block() -> OpenBlock {
     ).unwrap()
 }
 
+fn beta_genesis_block() -> OpenBlock {
+    serde_json::from_str(
+    r#"
+        {
+            "type": "open",
+            "source": "259A43ABDB779E97452E188BA3EB951B41C961D3318CA6B925380F4D99F0577A",
+            "representative": "nano_1betagoxpxwykx4kw86dnhosc8t3s7ix8eeentwkcg1hbpez1outjrcyg4n1",
+            "account": "nano_1betagoxpxwykx4kw86dnhosc8t3s7ix8eeentwkcg1hbpez1outjrcyg4n1",
+            "work": "79D4E27DC873C6F2",
+            "signature": "4BD7F96F9ED2721BCEE5EAED400EA50AD00524C629AE55E9AFF11220D2C1B00C3D4B3BB770BF67D4F8658023B677F91110193B6C101C2666931F57046A6DB806"
+        }
+        "#
+    ).unwrap()
+}
+
+/// The private key of the test genesis account is public, so anyone can start a test network.
+fn test_genesis_block() -> OpenBlock {
+    serde_json::from_str(
+    r#"
+        {
+            "type": "open",
+            "source": "B0311EA55708D6A53C75CDBF88300259C6D018522FE3D4D0A242E431F9E8B6D0",
+            "representative": "nano_3e3j5tkog48pnny9dmfzj1r16pg8t1e76dz5tmac6iq689wyjfpiij4txtdo",
+            "account": "nano_3e3j5tkog48pnny9dmfzj1r16pg8t1e76dz5tmac6iq689wyjfpiij4txtdo",
+            "work": "7B42A00EE91D5810",
+            "signature": "ECDA914373A2F0CA1296475BAEE40500A7F0A7AD72A5A80C81D7FAB7F6C802B2CC7DB50F5DD0FB25B2EF11761FA7344A158DD5A700B21BD47DE5BD0F63153A02"
+        }
+        "#
+    ).unwrap()
+}
+
 impl Network {
     pub fn genesis_block(&self) -> Block {
         let open_block = match self {
             Self::Live => live_genesis_block(),
-            _ => todo!(),
+            Self::Beta => beta_genesis_block(),
+            Self::Test => test_genesis_block(),
+            // Made from bytes of the right length, so this can't fail.
+            Self::Dev(genesis) => genesis.open_block().unwrap(),
         };
 
         // Give the genesis block the maximum u128 value.
@@ -41,33 +143,133 @@ impl Network {
     }
 
     pub fn genesis_hash(&self) -> BlockHash {
+        let hash = match self {
+            Self::Dev(_) => return self.genesis_block().hash().unwrap().to_owned(),
+            Self::Live => "991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948",
+            Self::Beta => "01A92459E69440D5C1088D3B31F4CA678BE944BAB3776C2E6B7665E9BD99BD5A",
+            Self::Test => "04270D7F11C4B2B472F2854C5A59F2A7E84226CE9ED799DE75744BD7D85FC9D9",
+        };
+        BlockHash::from_str(hash).unwrap()
+    }
+
+    /// The online weight used for quorum when fewer representatives than this are seen voting.
+    pub fn online_weight_minimum(&self) -> Rai {
+        // 60 million Nano, which nano-node uses on every network.
+        Rai::from(60_000_000 * 10u128.pow(30))
+    }
+
+    /// The minimum proof of work difficulty of a block in an account chain at `epoch`.
+    ///
+    /// Since epoch 2, receiving needs less work than sending or changing. The beta and test
+    /// networks need less work than the live network, so blocks can be made quickly, and the dev
+    /// network needs hardly any.
+    pub fn work_threshold(&self, epoch: Epoch, subtype: &Subtype) -> Difficulty {
+        let (epoch_1, epoch_2, epoch_2_receive) = match self {
+            Self::Live => (
+                Difficulty::epoch_1(),
+                Difficulty::normal(),
+                Difficulty::receive(),
+            ),
+            Self::Beta => (
+                Difficulty::new(0xfffff00000000000),
+                Difficulty::new(0xfffff00000000000),
+                Difficulty::new(0xffffe00000000000),
+            ),
+            Self::Test => (
+                Difficulty::new(0xfe00000000000000),
+                Difficulty::new(0xffc0000000000000),
+                Difficulty::new(0xf000000000000000),
+            ),
+            Self::Dev(_) => (
+                Difficulty::new(0xf000000000000000),
+                Difficulty::new(0xf000000000000000),
+                Difficulty::new(0xe000000000000000),
+            ),
+        };
+        if epoch < Epoch::V2 {
+            return epoch_1;
+        }
+        match subtype {
+            Subtype::Receive | Subtype::Open => epoch_2_receive,
+            Subtype::Send | Subtype::Change | Subtype::Epoch => epoch_2,
+        }
+    }
+
+    /// The account that signs the epoch blocks upgrading accounts to `epoch`.
+    pub fn epoch_signer(&self, epoch: Epoch) -> Option<Public> {
+        match (self, epoch) {
+            (_, Epoch::V0) => None,
+            (Self::Live, Epoch::V2) => Some(
+                Address::from_str(
+                    "nano_3qb6o6i1tkzr6jwr5s7eehfxwg9x6eemitdinbpi7u8bjjwsgqfj4wzser3x",
+                )
+                .unwrap()
+                .to_public(),
+            ),
+            _ => Some(self.genesis_block().account().to_owned()),
+        }
+    }
+
+    /// The TCP port nodes on this network listen on, unless they are told otherwise.
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Self::Live => DEFAULT_PORT,
+            Self::Beta => 54000,
+            Self::Test => 17075,
+            Self::Dev(_) => 47000,
+        }
+    }
+
+    /// Where to look up peers to connect to. Dev networks are private so there's no host, and
+    /// peers have to be given instead.
+    pub fn peering_host(&self) -> Option<String> {
+        let host = match self {
+            Self::Live => "peering.nano.org",
+            Self::Beta => "peering-beta.nano.org",
+            Self::Test => "peering-test.nano.org",
+            Self::Dev(_) => return None,
+        };
+        Some(format!("{}:{}", host, self.default_port()))
+    }
+
+    /// The byte in the header of every message that says which network it's for.
+    pub fn id(&self) -> u8 {
         match self {
-            Self::Live => BlockHash::from_str(
-                "991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948",
-            )
-            .unwrap(),
-            _ => todo!(),
+            Self::Test => 0x41,
+            Self::Beta => 0x42,
+            Self::Live => 0x43,
+            Self::Dev(_) => 0x44,
         }
     }
 
-    pub fn peering_host(&self) -> &str {
+    /// The name used on the command line and for the ledger database.
+    pub fn name(&self) -> &'static str {
         match self {
-            Self::Live => "peering.nano.org:7075",
-            _ => todo!(),
+            Self::Test => "test",
+            Self::Beta => "beta",
+            Self::Live => "live",
+            Self::Dev(_) => "dev",
         }
     }
 }
 
-impl TryFrom<u8> for Network {
-    type Error = anyhow::Error;
+impl Display for Network {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The dev network can't be parsed, since it also needs a genesis key.
+impl FromStr for Network {
+    type Err = anyhow::Error;
 
-    fn try_from(v: u8) -> Result<Self, Self::Error> {
-        use Network::*;
-        Ok(match v {
-            0x41 => Test,
-            0x42 => Beta,
-            0x43 => Live,
-            v => return Err(anyhow!("Unknown network: {} ({:X})", v, v)),
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "live" => Self::Live,
+            "beta" => Self::Beta,
+            "test" => Self::Test,
+            "dev" => return Err(anyhow!("The dev network needs a genesis key")),
+            s => return Err(anyhow!("Unknown network: {}", s)),
         })
     }
 }
@@ -75,6 +277,7 @@ impl TryFrom<u8> for Network {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Seed;
 
     #[test]
     fn hash_live_genesis_block() {
@@ -83,4 +286,88 @@ mod tests {
         let hash = block.hash().unwrap();
         assert_eq!(hash, &net.genesis_hash());
     }
+
+    #[test]
+    fn genesis_blocks() {
+        for net in &[Network::Live, Network::Beta, Network::Test] {
+            let block = net.genesis_block();
+            assert_eq!(block.hash().unwrap(), &net.genesis_hash());
+            block.verify_signature(block.account()).unwrap();
+            let work = block.work().unwrap();
+            let threshold = net.work_threshold(Epoch::V0, &Subtype::Open);
+            assert!(work
+                .verify(&Subject::Hash(block.root()), &threshold)
+                .unwrap());
+        }
+    }
+
+    #[test]
+    fn dev_genesis() {
+        let private = Seed::random().derive(0);
+        let net = Network::Dev(DevGenesis::new(&private).unwrap());
+        let block = net.genesis_block();
+        assert_eq!(block.account(), &private.to_public().unwrap());
+        assert_eq!(block.hash().unwrap(), &net.genesis_hash());
+        block.verify_signature(block.account()).unwrap();
+        let threshold = net.work_threshold(Epoch::V0, &Subtype::Open);
+        assert!(block
+            .work()
+            .unwrap()
+            .verify(&Subject::Hash(block.root()), &threshold)
+            .unwrap());
+
+        // Every node given the same key agrees on the network.
+        assert_eq!(Network::Dev(DevGenesis::new(&private).unwrap()), net);
+        let other = Network::Dev(DevGenesis::new(&Seed::random().derive(0)).unwrap());
+        assert_ne!(other.genesis_hash(), net.genesis_hash());
+    }
+
+    #[test]
+    fn peering() {
+        let dev = Network::Dev(DevGenesis::new(&Seed::random().derive(0)).unwrap());
+        let expected = [
+            (Network::Live, 7075, Some("peering.nano.org:7075")),
+            (Network::Beta, 54000, Some("peering-beta.nano.org:54000")),
+            (Network::Test, 17075, Some("peering-test.nano.org:17075")),
+            (dev, 47000, None),
+        ];
+        for (net, port, host) in &expected {
+            assert_eq!(net.default_port(), *port);
+            assert_eq!(net.peering_host().as_deref(), *host);
+        }
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!(Network::from_str("beta").unwrap(), Network::Beta);
+        assert!(Network::from_str("nope").is_err());
+    }
+
+    #[test]
+    fn live_work_thresholds() {
+        let net = Network::Live;
+        assert_eq!(
+            net.work_threshold(Epoch::V1, &Subtype::Receive),
+            Difficulty::epoch_1()
+        );
+        assert_eq!(
+            net.work_threshold(Epoch::V2, &Subtype::Receive),
+            Difficulty::receive()
+        );
+        assert_eq!(
+            net.work_threshold(Epoch::V2, &Subtype::Epoch),
+            Difficulty::normal()
+        );
+    }
+
+    #[test]
+    fn live_epoch_signers() {
+        let net = Network::Live;
+        assert_eq!(net.epoch_signer(Epoch::V0), None);
+        assert_eq!(
+            net.epoch_signer(Epoch::V1).as_ref(),
+            Some(net.genesis_block().account())
+        );
+        assert!(net.epoch_signer(Epoch::V2).is_some());
+    }
 }
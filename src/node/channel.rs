@@ -1,36 +1,78 @@
 use crate::network::Network;
-use crate::node::controller::{Controller, Packet};
+use crate::node::controller::{Controller, Flood, Packet};
 use crate::node::state::ArcState;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::warn;
 
+/// Run a controller over a TCP connection to a peer.
+///
+/// `inbound` is for connections the peer made to us, see [Controller::inbound]. Realtime
+/// connections take part in `flood`, which the node shares between its connections.
 pub async fn network_channel(
     network: Network,
     state: ArcState,
     stream: TcpStream,
+    bootstrap: bool,
+    inbound: bool,
+    flood: broadcast::Sender<Flood>,
 ) -> anyhow::Result<()> {
     // TODO: How would this fail?
     let peer_addr = stream.peer_addr().unwrap();
 
-    let (controller, tx, mut rx) = Controller::new_with_channels(network, state, peer_addr);
+    let (mut controller, tx, rx) = Controller::new_with_channels(network, state, peer_addr);
+    controller.bootstrap = bootstrap;
+    controller.inbound = inbound;
+    if !bootstrap {
+        controller.set_flood(&flood);
+    }
+    stream_channel(controller, tx, rx, stream).await
+}
+
+/// Run a controller over any kind of stream, e.g. the in-memory streams of a simulation.
+///
+/// `tx` and `rx` are the channels returned with the controller from
+/// [Controller::new_with_channels]. Returns once the controller has quit.
+pub async fn stream_channel<S>(
+    controller: Controller,
+    tx: Sender<Packet>,
+    mut rx: Receiver<Packet>,
+    stream: S,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let peer_addr = controller.peer_addr().to_owned();
 
     // We don't `await` here since the controller will quit when the incoming channel drops.
     tokio::spawn(controller.run());
 
-    let (mut in_stream, mut out_stream) = stream.into_split();
+    let (mut in_stream, mut out_stream) = tokio::io::split(stream);
 
     // Handle reads in a separate task.
     tokio::spawn(async move {
         let mut buffer: [u8; 10240] = [0; 10240];
         loop {
-            let bytes = in_stream
-                .read(&mut buffer)
-                .await
-                .expect("Could not read from peer");
+            let bytes = match in_stream.read(&mut buffer).await {
+                // The peer has closed the connection.
+                Ok(0) => return,
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    warn!("Could not read from {:?}: {:?}", peer_addr, err);
+                    return;
+                }
+            };
 
-            tx.send(Packet::new(Vec::from(&buffer[0..bytes])))
+            // The controller has quit, e.g. after finishing a bootstrap.
+            if tx
+                .send(Packet::new(Vec::from(&buffer[0..bytes])))
                 .await
-                .expect("Could not send to controller");
+                .is_err()
+            {
+                return;
+            }
         }
     });
 
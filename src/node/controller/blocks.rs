@@ -1,10 +1,61 @@
-use crate::blocks::{Block, BlockHash, BlockType, Previous};
+use crate::blocks::{
+    Block, BlockHash, BlockHolder, BlockType, Epoch, Link, Previous, Subtype, ValidationState,
+};
 use crate::node::controller::Controller;
-use crate::node::messages::confirm_ack::{Confirm, ConfirmAck};
-use crate::{Public, Rai, Signature};
+use crate::node::header::MessageType;
+use crate::node::messages::confirm_ack::ConfirmAck;
+use crate::node::state::{BlockChange, LedgerUpdate, Pending};
+use crate::node::timestamp::Timestamp;
+use crate::{Public, Rai, Subject};
 use anyhow::{anyhow, Context};
+use std::convert::TryFrom;
+use std::time::SystemTime;
 use tracing::{debug, instrument, warn};
 
+/// Why a vote from a peer was not counted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VoteRejection {
+    /// The signature doesn't match the representative and the contents of the vote.
+    InvalidSignature,
+
+    /// The representative has no voting weight, so the vote wouldn't count towards any election.
+    NoWeight,
+
+    /// The representative has already sent a vote for the block with the same or a newer
+    /// timestamp, or a final vote.
+    Replay {
+        hash: BlockHash,
+        previous: Timestamp,
+    },
+}
+
+/// Counts of the votes received from a peer.
+#[derive(Debug, Clone, Default)]
+pub struct VoteStats {
+    /// Block hashes that were counted.
+    pub accepted: usize,
+
+    pub invalid_signature: usize,
+
+    pub no_weight: usize,
+
+    /// Block hashes that were left out of a vote as replays.
+    pub replayed: usize,
+
+    pub last_rejection: Option<VoteRejection>,
+}
+
+impl VoteStats {
+    fn reject(&mut self, rejection: VoteRejection) {
+        match &rejection {
+            VoteRejection::InvalidSignature => self.invalid_signature += 1,
+            VoteRejection::NoWeight => self.no_weight += 1,
+            VoteRejection::Replay { .. } => self.replayed += 1,
+        }
+        self.last_rejection = Some(rejection);
+    }
+}
+
 struct AccountDelta {
     from: Public,
     to: Public,
@@ -12,54 +63,124 @@ struct AccountDelta {
 }
 
 impl Controller {
+    /// Count a vote from a representative, once it has passed [Controller::validate_vote], and
+    /// flood it to the other peers.
     #[instrument(skip(self))]
     pub async fn add_vote(&mut self, confirm_ack: &ConfirmAck) -> anyhow::Result<()> {
         let context = || format!("Adding vote {:?}", &confirm_ack);
 
-        let hashes = if let Confirm::VoteByHash(hashes) = &confirm_ack.confirm {
-            hashes
-        } else {
-            return Err(anyhow!("Confirm::Block not implemented")).with_context(context);
-        };
+        let hashes = self
+            .validate_vote(confirm_ack)
+            .await
+            .with_context(context)?;
+        if hashes.is_empty() {
+            return Ok(());
+        }
 
-        for hash in hashes {
-            self.validate_vote(hash, &confirm_ack.account, &confirm_ack.signature)
-                .await
-                .with_context(context)?;
+        self.state
+            .lock()
+            .await
+            .set_rep_last_vote(&confirm_ack.account, SystemTime::now())
+            .await
+            .with_context(context)?;
 
-            self.state
-                .lock()
+        // The votes for a block are dropped once its election is over, so a vote for a block
+        // that's already in the ledger without an election isn't stored or flooded again.
+        let mut unsettled = vec![];
+        for hash in hashes {
+            let mut state = self.state.lock().await;
+            let settled = state
+                .election_root_for_block(&hash)
                 .await
-                .add_vote(hash, &confirm_ack.account)
+                .with_context(context)?
+                .is_none()
+                && state
+                    .get_block_by_hash(&hash)
+                    .await
+                    .with_context(context)?
+                    .is_some();
+            if settled {
+                continue;
+            }
+            state
+                .add_vote(&hash, &confirm_ack.account, &confirm_ack.timestamp)
                 .await
                 .with_context(context)?;
-
-            // self.check_votes(&confirm_ack.confirm)
-            //     .await
-            //     .with_context(context)?;
+            self.vote_stats.accepted += 1;
+            unsettled.push(hash);
         }
+        if unsettled.is_empty() {
+            return Ok(());
+        }
+        self.flood(
+            MessageType::ConfirmAck,
+            confirm_ack.extensions(),
+            confirm_ack,
+        );
 
+        self.tally_votes(&unsettled).await.with_context(context)?;
         Ok(())
     }
 
+    /// Returns the hashes of the vote that should be counted.
+    ///
+    /// A vote is rejected as a whole when its signature is bad, or when its representative has no
+    /// weight. A hash is left out when the representative's vote for that block is final, or isn't
+    /// older than this one, so a vote can't be replayed. Votes for different blocks are checked
+    /// separately, since the tally only counts the newest vote of each representative anyway.
     #[instrument(skip(self))]
     pub async fn validate_vote(
         &mut self,
-        hash: &BlockHash,
-        representative: &Public,
-        signature: &Signature,
-    ) -> anyhow::Result<()> {
-        warn!("TODO validate vote");
-        Ok(())
+        confirm_ack: &ConfirmAck,
+    ) -> anyhow::Result<Vec<BlockHash>> {
+        if let Err(err) = confirm_ack.verify_signature() {
+            warn!("Rejecting vote: {:?}", err);
+            self.vote_stats.reject(VoteRejection::InvalidSignature);
+            return Ok(vec![]);
+        }
+
+        if self.rep_weight(&confirm_ack.account).await? == Rai::zero() {
+            debug!(
+                "Ignoring vote from {:?} without weight",
+                &confirm_ack.account
+            );
+            self.vote_stats.reject(VoteRejection::NoWeight);
+            return Ok(vec![]);
+        }
+
+        let mut hashes = vec![];
+        for hash in confirm_ack.confirm.hashes() {
+            let previous = self
+                .state
+                .lock()
+                .await
+                .latest_vote_timestamp(&hash, &confirm_ack.account)
+                .await?;
+            if let Some(previous) = previous {
+                if previous.is_final() || confirm_ack.timestamp <= previous {
+                    warn!("Rejecting replayed vote for {:?}", &hash);
+                    self.vote_stats
+                        .reject(VoteRejection::Replay { hash, previous });
+                    continue;
+                }
+            }
+            hashes.push(hash);
+        }
+        Ok(hashes)
+    }
+
+    pub fn vote_stats(&self) -> &VoteStats {
+        &self.vote_stats
     }
 
     /// Add a block that has been deemed valid by ORV.
     ///
     /// Before adding a block we need to make sure it:
-    /// * Doesn't already exist.
-    /// * Verify the work.
-    /// * Verify the signature.
-    /// * Handle the specific block type appropriately.
+    /// * Doesn't already exist, and doesn't fork a block in the ledger.
+    /// * Follows the rules of its block type, see [Controller::validate_block].
+    /// * Has a valid signature, from the epoch signer for epoch blocks.
+    /// * Isn't a legacy block in an account upgraded past epoch 0.
+    /// * Has enough work for its subtype and epoch.
     ///
     /// After adding we need to update any representative weights.
     pub async fn add_elected_block(&mut self, block: &Block) -> anyhow::Result<()> {
@@ -82,89 +203,434 @@ impl Controller {
             return Err(anyhow!("Block already exists")).with_context(context);
         }
 
-        let context = || format!("Block {:?}", block);
-        block
-            .verify_signature(&block.account())
-            .context("Incorrect signature")
+        if self
+            .ledger_block_for_root(block)
+            .await
+            .with_context(context)?
+            .is_some()
+        {
+            return Err(anyhow!("Fork of a block already in the ledger")).with_context(context);
+        }
+
+        let (_, epoch) = self
+            .check_elected_block(block)
+            .await
             .with_context(context)?;
 
-        let work = block.work();
-        if work.is_none() {
-            return Err(anyhow!("Work is missing from block")).with_context(context);
+        let update = self.add_update(block, epoch).await.with_context(context)?;
+        self.state
+            .lock()
+            .await
+            .apply_update(&update)
+            .await
+            .with_context(context)
+    }
+
+    /// The checks of [Controller::add_elected_block] that don't depend on whether the block forks
+    /// the ledger, so the winner of an election can be checked before a losing fork is rolled
+    /// back for it.
+    ///
+    /// Returns the subtype of the block, and the epoch of its account once it's added.
+    pub(crate) async fn check_elected_block(
+        &self,
+        block: &Block,
+    ) -> anyhow::Result<(Subtype, Epoch)> {
+        let subtype = self.validate_block(block).await?;
+        let epoch = self.epoch_after(block, &subtype).await?;
+
+        let signer = if subtype == Subtype::Epoch {
+            self.network
+                .epoch_signer(epoch)
+                .ok_or_else(|| anyhow!("No epoch signer for {:?}", epoch))?
+        } else {
+            block.account().to_owned()
+        };
+        block
+            .verify_signature(&signer)
+            .context("Incorrect signature")?;
+
+        if block.block_type() != &BlockType::State && epoch > Epoch::V0 {
+            return Err(anyhow!("Legacy block in an account at {:?}", epoch));
         }
-        // TODO: Verify work
 
-        // TODO: For now just assume this is a send block
-        match block.block_type() {
-            BlockType::Send => {
-                dbg!(block);
+        let work = block
+            .work()
+            .ok_or_else(|| anyhow!("Work is missing from block"))?;
+        if self.validate_work {
+            let threshold = self.network.work_threshold(epoch, &subtype);
+            if !work.verify(&Subject::Hash(block.root()), &threshold)? {
+                return Err(anyhow!("Not enough work for a {:?}", subtype));
+            }
+        }
 
-                let previous_hash = match block.previous() {
-                    Previous::Block(h) => h,
-                    Previous::Open => {
-                        return Err(anyhow!("Send block has a blank previous block hash"))
-                            .with_context(context)
-                    }
+        Ok((subtype, epoch))
+    }
+
+    /// The changes to the ledger for adding a block: the send it makes pending or the pending send
+    /// it receives, and the representative weights it moves.
+    async fn add_update(&self, block: &Block, epoch: Epoch) -> anyhow::Result<LedgerUpdate> {
+        let mut update = LedgerUpdate {
+            block: BlockChange::Add(block.to_owned(), epoch),
+            add_pending: vec![],
+            remove_pending: vec![],
+            rep_weights: self.rep_weights_after_add(block).await?,
+        };
+        match block.link() {
+            Link::DestinationAccount(destination) => {
+                let previous = match block.previous() {
+                    Previous::Block(previous) => self.get_block(previous).await?,
+                    Previous::Open => return Err(anyhow!("Send block has no previous block")),
                 };
+                let pending = Pending {
+                    send_hash: block.hash()?.to_owned(),
+                    source: block.account().to_owned(),
+                    amount: previous
+                        .balance()
+                        .checked_sub(block.balance())
+                        .ok_or_else(|| anyhow!("Send block increases the balance"))?,
+                };
+                update.add_pending.push((destination.to_owned(), pending));
+            }
+            Link::Source(source) => {
+                update
+                    .remove_pending
+                    .push((block.account().to_owned(), source.to_owned()));
+            }
+            _ => {}
+        }
+        Ok(update)
+    }
 
-                let prev_block = self
-                    .state
-                    .lock()
-                    .await
-                    .get_block_by_hash(previous_hash)
-                    .await
-                    .context("Previous block")
-                    .with_context(context)?
-                    .ok_or_else(|| anyhow!("Could not find previous block"))
-                    .with_context(context)?;
-                let prev_balance = prev_block.balance();
-
-                if block.balance() >= prev_balance {
-                    return Err(anyhow!(
-                        "Can not increase balance in a send block. Prev: {:?}",
-                        prev_block
-                    ))
-                    .with_context(context);
-                }
+    /// The changes to the ledger for rolling back a block, undoing [Controller::add_update].
+    pub(crate) async fn remove_update(&self, block: &Block) -> anyhow::Result<LedgerUpdate> {
+        let mut update = LedgerUpdate {
+            block: BlockChange::Remove(block.to_owned()),
+            add_pending: vec![],
+            remove_pending: vec![],
+            rep_weights: self.rep_weights_after_remove(block).await?,
+        };
+        match block.link() {
+            Link::DestinationAccount(destination) => {
+                update
+                    .remove_pending
+                    .push((destination.to_owned(), block.hash()?.to_owned()));
+            }
+            Link::Source(source) => {
+                let send = self.get_block(source).await?;
+                let pending = Pending {
+                    send_hash: source.to_owned(),
+                    source: send.account().to_owned(),
+                    amount: self.send_amount(source).await?,
+                };
+                update
+                    .add_pending
+                    .push((block.account().to_owned(), pending));
+            }
+            _ => {}
+        }
+        Ok(update)
+    }
 
-                let _to_account = block.destination().with_context(context)?;
-                let _amount = prev_balance
-                    .checked_sub(block.balance())
-                    .ok_or_else(|| {
-                        anyhow!(
-                            "Subtracting prev_balance {:?} and new balance {:?}",
-                            prev_balance,
-                            block.balance()
-                        )
-                    })
-                    .with_context(context)?;
+    /// Check the block against the ledger, according to the rules of its block type:
+    /// * A send has to reduce the balance.
+    /// * A receive or open has to credit exactly the amount of a send to this account, which
+    ///   hasn't been received yet.
+    /// * A change has to keep the balance.
+    /// * A state block is one of the above, depending on how its balance changes.
+    ///
+    /// Returns the subtype of the block.
+    pub async fn validate_block(&self, block: &Block) -> anyhow::Result<Subtype> {
+        let previous = match block.previous() {
+            Previous::Block(hash) => Some(self.get_block(hash).await.context("Previous block")?),
+            Previous::Open => None,
+        };
+        let previous_balance = previous
+            .as_ref()
+            .map(|p| p.balance().to_owned())
+            .unwrap_or_else(Rai::zero);
+        if let Some(previous) = &previous {
+            if previous.account() != block.account() {
+                return Err(anyhow!(
+                    "Previous block belongs to {:?}",
+                    previous.account()
+                ));
             }
-            BlockType::Open => {
-                dbg!(block);
+        }
 
+        let balance = block.balance();
+        match block.block_type() {
+            BlockType::Send => {
+                if previous.is_none() {
+                    return Err(anyhow!("Send block has a blank previous block hash"));
+                }
+                if balance >= &previous_balance {
+                    return Err(anyhow!("Can not increase balance in a send block"));
+                }
+                block.destination()?;
+                Ok(Subtype::Send)
+            }
+            BlockType::Receive => {
+                if previous.is_none() {
+                    return Err(anyhow!("Receive block has a blank previous block hash"));
+                }
+                self.validate_receive(block, block.source()?, &previous_balance)
+                    .await?;
+                Ok(Subtype::Receive)
+            }
+            BlockType::Open => {
+                if previous.is_some() {
+                    return Err(anyhow!("Open block has a previous block"));
+                }
                 // If the block is the genesis block, we basically just trust the balance.
                 if !block.is_genesis(&self.network)? {
-                    // TODO: Make sure the balance in the open block matches the amount in the
-                    // send block.
+                    self.validate_receive(block, block.source()?, &previous_balance)
+                        .await?;
                 }
+                Ok(Subtype::Open)
             }
-            _ => todo!(),
+            BlockType::Change => {
+                if previous.is_none() {
+                    return Err(anyhow!("Change block has a blank previous block hash"));
+                }
+                if balance != &previous_balance {
+                    return Err(anyhow!("Can not change the balance in a change block"));
+                }
+                Ok(Subtype::Change)
+            }
+            BlockType::State => {
+                if let Some(upgrade) = Epoch::upgrade_of(block, &previous_balance) {
+                    self.validate_epoch(block, previous.as_ref(), upgrade)
+                        .await?;
+                    Ok(Subtype::Epoch)
+                } else if balance < &previous_balance {
+                    match block.link() {
+                        Link::DestinationAccount(_) => Ok(Subtype::Send),
+                        link => Err(anyhow!("Send has an invalid link: {:?}", link)),
+                    }
+                } else if balance > &previous_balance {
+                    let source = match block.link() {
+                        Link::Source(source) => source,
+                        link => return Err(anyhow!("Receive has an invalid link: {:?}", link)),
+                    };
+                    self.validate_receive(block, source, &previous_balance)
+                        .await?;
+                    if previous.is_none() {
+                        Ok(Subtype::Open)
+                    } else {
+                        Ok(Subtype::Receive)
+                    }
+                } else {
+                    if previous.is_none() {
+                        return Err(anyhow!("Can not open an account without receiving"));
+                    }
+                    match block.link() {
+                        Link::Nothing => Ok(Subtype::Change),
+                        link => Err(anyhow!("Change has an invalid link: {:?}", link)),
+                    }
+                }
+            }
+            block_type => Err(anyhow!("{:?} is not a block", block_type)),
         }
+    }
 
-        self.state
+    /// Make sure `source` is an unreceived send to the account of `block`, and that `block`
+    /// credits exactly the amount that was sent.
+    async fn validate_receive(
+        &self,
+        block: &Block,
+        source: &BlockHash,
+        previous_balance: &Rai,
+    ) -> anyhow::Result<()> {
+        let pending = self
+            .state
             .lock()
             .await
-            .add_block(block)
-            .await
-            .with_context(context)?;
+            .get_pending(block.account(), source)
+            .await?;
+        let amount = match pending {
+            Some(pending) => pending.amount,
+            None => self.received_in_fork(block, source).await?.ok_or_else(|| {
+                anyhow!(
+                    "Source block {:?} is not a pending send to this account",
+                    source
+                )
+            })?,
+        };
+
+        let received = block
+            .balance()
+            .checked_sub(previous_balance)
+            .ok_or_else(|| anyhow!("Balance went down in a receive"))?;
+        if received != amount {
+            return Err(anyhow!("Received {:?} but {:?} was sent", received, amount));
+        }
+        Ok(())
+    }
 
-        // self.balance_rep_weights(block)
-        //     .await
-        //     .with_context(context)?;
+    /// Add a block from a bootstrap straight to the ledger, then process the blocks waiting on it.
+    pub async fn add_bootstrapped_block(&mut self, holder: &BlockHolder) -> anyhow::Result<()> {
+        let block = self.fill_block(holder).await?;
+        self.add_elected_block(&block).await?;
+        self.release_unchecked(&holder.hash()).await
+    }
 
+    /// Process the unchecked blocks that were waiting for this block to be added to the ledger.
+    pub async fn release_unchecked(&mut self, hash: &BlockHash) -> anyhow::Result<()> {
+        let released = self.state.lock().await.take_unchecked(hash).await?;
+        for holder in released {
+            debug!("Releasing unchecked block {:?}", holder);
+            self.handle_published_block(&holder).await?;
+        }
         Ok(())
     }
 
+    /// The previous or source block that is needed before this block can be processed, if it's
+    /// missing from the ledger.
+    pub async fn missing_dependency(
+        &self,
+        holder: &BlockHolder,
+    ) -> anyhow::Result<Option<BlockHash>> {
+        let previous = match holder {
+            BlockHolder::Send(b) => Some(&b.previous),
+            BlockHolder::Receive(b) => Some(&b.previous),
+            BlockHolder::Change(b) => Some(&b.previous),
+            BlockHolder::Open(_) => None,
+            BlockHolder::State(b) if b.previous == BlockHash::zero() => None,
+            BlockHolder::State(b) => Some(&b.previous),
+        };
+        let previous = match previous {
+            Some(hash) => {
+                let block = self.state.lock().await.get_block_by_hash(hash).await?;
+                match block {
+                    Some(block) => Some(block),
+                    None => return Ok(Some(hash.to_owned())),
+                }
+            }
+            None => None,
+        };
+
+        let source = match holder {
+            BlockHolder::Receive(b) => Some(b.source.to_owned()),
+            BlockHolder::Open(b) => Some(b.source.to_owned()),
+            BlockHolder::State(b) => {
+                // A state block is only a receive if the balance has gone up.
+                let previous_balance = previous
+                    .as_ref()
+                    .map(|p| p.balance().to_owned())
+                    .unwrap_or_else(Rai::zero);
+                if b.balance > previous_balance {
+                    Some(BlockHash::try_from(b.link.as_bytes())?)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        if let Some(source) = source {
+            let block = self.state.lock().await.get_block_by_hash(&source).await?;
+            if block.is_none() {
+                return Ok(Some(source));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Turn a block from the network into a [Block] by looking up the information that isn't
+    /// part of its wire format, e.g. the account and representative of a legacy send block.
+    ///
+    /// The dependencies of the block (previous and source) need to be in the ledger already.
+    pub async fn fill_block(&self, holder: &BlockHolder) -> anyhow::Result<Block> {
+        let context = || format!("Filling block {:?}", holder);
+
+        let block = match holder {
+            BlockHolder::Send(send) => {
+                let previous = self.get_block(&send.previous).await.with_context(context)?;
+                Block::from_send_block(send, previous.account(), previous.representative())
+            }
+            BlockHolder::Receive(receive) => {
+                let previous = self
+                    .get_block(&receive.previous)
+                    .await
+                    .with_context(context)?;
+                let amount = self
+                    .send_amount(&receive.source)
+                    .await
+                    .with_context(context)?;
+                let balance = previous
+                    .balance()
+                    .checked_add(&amount)
+                    .ok_or_else(|| anyhow!("Balance overflow"))
+                    .with_context(context)?;
+                Block::from_receive_block(
+                    receive,
+                    previous.account(),
+                    previous.representative(),
+                    &balance,
+                )
+            }
+            BlockHolder::Open(open) => {
+                let amount = self.send_amount(&open.source).await.with_context(context)?;
+                Block::from_open_block(open, &Previous::Open, &amount)
+            }
+            BlockHolder::Change(change) => {
+                let previous = self
+                    .get_block(&change.previous)
+                    .await
+                    .with_context(context)?;
+                Block::from_change_block(change, previous.account(), previous.balance())
+            }
+            BlockHolder::State(state) => {
+                let previous_balance = if state.previous == BlockHash::zero() {
+                    Rai::zero()
+                } else {
+                    self.get_block(&state.previous)
+                        .await
+                        .with_context(context)?
+                        .balance()
+                        .to_owned()
+                };
+                // The link can only be worked out by comparing against the previous balance.
+                Block::from_state_block_with_previous_balance(
+                    state,
+                    &previous_balance,
+                    ValidationState::Published,
+                )
+                .with_context(context)?
+            }
+        };
+
+        Ok(block)
+    }
+
+    /// The amount that was sent in a send block, based on the balance of its previous block.
+    pub async fn send_amount(&self, send_hash: &BlockHash) -> anyhow::Result<Rai> {
+        let context = || format!("Send amount for {:?}", send_hash);
+        let send = self.get_block(send_hash).await.with_context(context)?;
+        let previous_hash = match send.previous() {
+            Previous::Block(h) => h,
+            Previous::Open => {
+                return Err(anyhow!("Source block has no previous block")).with_context(context)
+            }
+        };
+        let previous = self.get_block(previous_hash).await.with_context(context)?;
+
+        previous
+            .balance()
+            .checked_sub(send.balance())
+            .ok_or_else(|| anyhow!("Source block is not a send"))
+            .with_context(context)
+    }
+
+    /// Get a block from the ledger that is expected to exist.
+    pub(crate) async fn get_block(&self, hash: &BlockHash) -> anyhow::Result<Block> {
+        self.state
+            .lock()
+            .await
+            .get_block_by_hash(hash)
+            .await?
+            .ok_or_else(|| anyhow!("Could not find block {:?}", hash))
+    }
+
     pub async fn get_latest_block(&self, account: &Public) -> anyhow::Result<Option<Block>> {
         let block_hash = self
             .state
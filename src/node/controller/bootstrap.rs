@@ -0,0 +1,440 @@
+use crate::blocks::{Block, BlockHash, BlockHolder, BlockType, Previous};
+use crate::node::controller::{Controller, Incoming};
+use crate::node::header::{Extensions, MessageType};
+use crate::node::messages::bulk_pull::BulkPull;
+use crate::node::messages::bulk_pull_account::{
+    BulkPullAccount, BulkPullAccountFlags, BulkPullAccountFrontier, BulkPullAccountResponse,
+    PendingEntry,
+};
+use crate::node::messages::bulk_push::BulkPush;
+use crate::node::messages::frontier_req::FrontierReq;
+use crate::node::messages::frontier_resp::FrontierResp;
+use crate::{Public, Rai};
+use anyhow::{anyhow, Context};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, instrument, warn};
+
+/// How often to log progress while receiving frontiers.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The most blocks a single bulk pull can send before the peer is dropped. They're all held in
+/// memory until the pull ends, since they arrive newest first.
+pub(crate) const MAX_PULLED_BLOCKS: usize = 65536;
+
+/// An account that we're behind on, to be pulled from the peer's frontier back to `end`.
+#[derive(Debug)]
+pub struct Pull {
+    pub account: Public,
+
+    /// Our frontier of the account, or zero if we don't have the account.
+    pub end: BlockHash,
+}
+
+/// An account where the peer is behind us, to be pushed from our frontier back to `end`.
+#[derive(Debug)]
+pub struct Push {
+    pub account: Public,
+
+    /// The peer's frontier of the account.
+    pub end: BlockHash,
+}
+
+/// Progress of a legacy bootstrap. The peer's frontiers are compared against ours, then the
+/// accounts we're behind on are pulled one by one.
+#[derive(Debug, Clone)]
+pub struct BootstrapProgress {
+    pub started: Instant,
+    last_report: Instant,
+
+    /// Number of frontiers received from the peer.
+    pub frontiers: usize,
+
+    /// Accounts where we have the same frontier as the peer.
+    pub up_to_date: usize,
+
+    /// Accounts where the peer is missing some of our blocks, which are pushed after pulling.
+    pub ahead: usize,
+
+    /// Accounts where we are missing blocks, which are scheduled to be pulled.
+    pub behind: usize,
+
+    pub pulls_completed: usize,
+    pub blocks_pulled: usize,
+    pub blocks_pushed: usize,
+}
+
+impl BootstrapProgress {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            last_report: now,
+            frontiers: 0,
+            up_to_date: 0,
+            ahead: 0,
+            behind: 0,
+            pulls_completed: 0,
+            blocks_pulled: 0,
+            blocks_pushed: 0,
+        }
+    }
+
+    /// Log the progress if it hasn't been logged for a while.
+    fn report(&mut self) {
+        if self.last_report.elapsed() >= PROGRESS_INTERVAL {
+            self.last_report = Instant::now();
+            info!("Bootstrap progress: {:?}", self);
+        }
+    }
+}
+
+enum FrontierStatus {
+    UpToDate,
+    Ahead,
+    Behind(BlockHash),
+}
+
+impl Controller {
+    /// Start a legacy bootstrap by requesting every frontier from the peer.
+    ///
+    /// The frontiers are streamed back without headers, which are handled by
+    /// `handle_frontier_resp`.
+    #[instrument(skip(self))]
+    pub async fn send_frontier_req(&mut self) -> anyhow::Result<()> {
+        self.send_header(MessageType::FrontierReq, Extensions::new())
+            .await?;
+        self.send(&FrontierReq::all()).await?;
+
+        self.pulls.clear();
+        self.pushes.clear();
+        self.bootstrap_progress = Some(BootstrapProgress::new());
+        self.incoming_kind = Incoming::Frontiers;
+        Ok(())
+    }
+
+    pub fn bootstrap_progress(&self) -> Option<&BootstrapProgress> {
+        self.bootstrap_progress.as_ref()
+    }
+
+    pub async fn handle_frontier_resp(
+        &mut self,
+        frontier_resp: FrontierResp,
+    ) -> anyhow::Result<()> {
+        if frontier_resp.is_end() {
+            self.incoming_kind = Incoming::Messages;
+            if let Some(progress) = &self.bootstrap_progress {
+                info!("Received all frontiers: {:?}", progress);
+            }
+            return self.send_next_pull().await;
+        }
+
+        // We didn't request these frontiers, e.g. when replaying a pcap dump.
+        if self.bootstrap_progress.is_none() {
+            return Ok(());
+        }
+
+        let status = self
+            .frontier_status(&frontier_resp)
+            .await
+            .with_context(|| format!("Comparing frontier {:?}", frontier_resp))?;
+
+        let progress = self.bootstrap_progress.as_mut().unwrap();
+        progress.frontiers += 1;
+        match status {
+            FrontierStatus::UpToDate => progress.up_to_date += 1,
+            FrontierStatus::Ahead => {
+                progress.ahead += 1;
+                self.pushes.push(Push {
+                    account: frontier_resp.account,
+                    end: frontier_resp.frontier_hash,
+                });
+            }
+            FrontierStatus::Behind(end) => {
+                progress.behind += 1;
+                self.pulls.push_back(Pull {
+                    account: frontier_resp.account,
+                    end,
+                });
+            }
+        }
+
+        progress.report();
+
+        Ok(())
+    }
+
+    async fn frontier_status(
+        &self,
+        frontier_resp: &FrontierResp,
+    ) -> anyhow::Result<FrontierStatus> {
+        let state = self.state.lock().await;
+        let ours = state
+            .get_latest_block_hash_for_account(&frontier_resp.account)
+            .await?;
+
+        if ours.as_ref() == Some(&frontier_resp.frontier_hash) {
+            return Ok(FrontierStatus::UpToDate);
+        }
+
+        // If we already have their frontier block, we must have more blocks than them.
+        if state
+            .get_block_by_hash(&frontier_resp.frontier_hash)
+            .await?
+            .is_some()
+        {
+            return Ok(FrontierStatus::Ahead);
+        }
+
+        Ok(FrontierStatus::Behind(ours.unwrap_or_else(BlockHash::zero)))
+    }
+
+    /// Pull the next account that is behind, if any. Once every pull is done, push the accounts
+    /// that the peer is behind on.
+    async fn send_next_pull(&mut self) -> anyhow::Result<()> {
+        match self.pulls.pop_front() {
+            Some(pull) => self.send_bulk_pull(&pull.account, &pull.end).await,
+            None => {
+                if let Some(progress) = &self.bootstrap_progress {
+                    info!("Bootstrap pulls completed: {:?}", progress);
+                }
+                self.send_scheduled_pushes().await
+            }
+        }
+    }
+
+    async fn send_scheduled_pushes(&mut self) -> anyhow::Result<()> {
+        if self.pushes.is_empty() {
+            return Ok(());
+        }
+
+        let mut blocks = vec![];
+        for push in self.pushes.drain(..).collect::<Vec<_>>() {
+            let bulk_pull = BulkPull::account(&push.account, push.end.to_owned());
+            blocks.extend(
+                self.bulk_pull_chain(&bulk_pull)
+                    .await
+                    .with_context(|| format!("Collecting blocks to push for {:?}", push))?,
+            );
+        }
+
+        self.send_bulk_push(&blocks).await?;
+
+        if let Some(progress) = self.bootstrap_progress.as_mut() {
+            progress.blocks_pushed += blocks.len();
+            info!("Bootstrap pushes completed: {:?}", progress);
+        }
+        Ok(())
+    }
+
+    /// Push blocks to the peer. Each chain should be ordered from the newest block backwards, the
+    /// same as a `BulkPull` response.
+    #[instrument(skip(self, blocks))]
+    pub async fn send_bulk_push(&mut self, blocks: &[Block]) -> anyhow::Result<()> {
+        self.send_header(MessageType::BulkPush, Extensions::new())
+            .await?;
+        self.send(&BulkPush).await?;
+        for block in blocks {
+            let holder = block.to_holder()?;
+            self.send(&holder.block_type()).await?;
+            self.send(&holder).await?;
+        }
+        self.send(&BlockType::NotABlock).await?;
+        Ok(())
+    }
+
+    /// Request the chain of `account` from its frontier back to `end`.
+    ///
+    /// The response blocks are streamed back without headers, which are handled by
+    /// `recv_streamed_block`.
+    #[instrument(skip(self))]
+    pub async fn send_bulk_pull(
+        &mut self,
+        account: &Public,
+        end: &BlockHash,
+    ) -> anyhow::Result<()> {
+        self.send_header(MessageType::BulkPull, Extensions::new())
+            .await?;
+        self.send(&BulkPull::account(account, end.to_owned()))
+            .await?;
+
+        self.streamed_blocks.clear();
+        self.incoming_kind = Incoming::BulkPull(account.to_owned());
+        Ok(())
+    }
+
+    /// Receive one block of a bulk pull response or a bulk push. When a bulk pull has ended, the
+    /// blocks are processed.
+    ///
+    /// Pushed blocks weren't asked for, so they're processed as they arrive, the same as published
+    /// blocks. This way they are validated, and parked as unchecked until their dependencies
+    /// arrive, rather than being kept in memory for as long as the peer keeps pushing.
+    pub(crate) async fn recv_streamed_block(&mut self) -> anyhow::Result<()> {
+        let message_type = match &self.incoming_kind {
+            Incoming::BulkPull(_) => MessageType::BulkPull,
+            Incoming::BulkPush => MessageType::BulkPush,
+            _ => unreachable!("Receiving streamed blocks outside of a bulk pull or push"),
+        };
+
+        let block_type = self.recv::<BlockType>(None).await?;
+        if block_type == BlockType::NotABlock {
+            let incoming_kind = std::mem::replace(&mut self.incoming_kind, Incoming::Messages);
+            return match incoming_kind {
+                Incoming::BulkPull(account) => self.handle_pulled_blocks(&account).await,
+                _ => Ok(()),
+            };
+        }
+
+        let mut header = self.header;
+        header.reset(message_type, *Extensions::new().set_block_type(&block_type));
+        let block = self
+            .recv::<BlockHolder>(Some(&header))
+            .await
+            .with_context(|| format!("Receiving {:?} block", message_type))?;
+        if message_type == MessageType::BulkPush {
+            debug!("Pushed {:?}", block);
+            return self.handle_published_block(&block).await;
+        }
+        if self.streamed_blocks.len() >= self.max_pulled_blocks {
+            return Err(anyhow!(
+                "Bulk pull sent more than {} blocks",
+                self.max_pulled_blocks
+            ));
+        }
+        self.streamed_blocks.push(block);
+        Ok(())
+    }
+
+    /// Add the blocks of a finished bulk pull to the ledger.
+    ///
+    /// They are received from the frontier backwards, so they need to be added in reverse.
+    async fn handle_pulled_blocks(&mut self, account: &Public) -> anyhow::Result<()> {
+        let blocks: Vec<BlockHolder> = self.streamed_blocks.drain(..).rev().collect();
+        debug!("Pulled {} blocks for {:?}", blocks.len(), account);
+
+        if let Some(progress) = self.bootstrap_progress.as_mut() {
+            progress.pulls_completed += 1;
+            progress.blocks_pulled += blocks.len();
+            progress.report();
+        }
+
+        for holder in blocks {
+            // The rest of the chain depends on this block, so there's no point continuing.
+            if let Err(err) = self.add_bootstrapped_block(&holder).await {
+                warn!("Could not add pulled block for {:?}: {:?}", account, err);
+                break;
+            }
+        }
+
+        self.send_next_pull().await
+    }
+
+    /// Find the blocks to respond with for a `BulkPull`, from the newest block backwards.
+    pub(crate) async fn bulk_pull_chain(&self, bulk_pull: &BulkPull) -> anyhow::Result<Vec<Block>> {
+        let state = self.state.lock().await;
+
+        // `start` can be either a block hash or an account.
+        let mut current = if state.get_block_by_hash(&bulk_pull.start).await?.is_some() {
+            Some(bulk_pull.start.to_owned())
+        } else {
+            state
+                .get_latest_block_hash_for_account(&bulk_pull.start_account())
+                .await?
+        };
+
+        let mut blocks = vec![];
+        while let Some(hash) = current {
+            if hash == bulk_pull.end {
+                break;
+            }
+            if let Some(count) = bulk_pull.count {
+                if count != 0 && blocks.len() >= count as usize {
+                    break;
+                }
+            }
+
+            let block = match state.get_block_by_hash(&hash).await? {
+                Some(block) => block,
+                None => {
+                    debug!("Missing block {:?} while serving bulk pull", hash);
+                    break;
+                }
+            };
+
+            current = match block.previous() {
+                Previous::Block(previous) => Some(previous.to_owned()),
+                Previous::Open => None,
+            };
+            blocks.push(block);
+        }
+
+        Ok(blocks)
+    }
+
+    /// Request the frontier, balance and pending sends of an account, e.g. to find receivable
+    /// sends without a full ledger.
+    ///
+    /// The response is streamed back without headers. Once it has been received, it's available
+    /// from `bulk_pull_account_response`.
+    #[instrument(skip(self))]
+    pub async fn send_bulk_pull_account(
+        &mut self,
+        account: &Public,
+        minimum_amount: Rai,
+        flags: BulkPullAccountFlags,
+    ) -> anyhow::Result<()> {
+        let bulk_pull_account = BulkPullAccount::new(account.to_owned(), minimum_amount, flags);
+        self.send_header(MessageType::BulkPullAccount, Extensions::new())
+            .await?;
+        self.send(&bulk_pull_account).await?;
+        self.start_bulk_pull_account_response(&bulk_pull_account);
+        Ok(())
+    }
+
+    pub(crate) fn start_bulk_pull_account_response(&mut self, bulk_pull_account: &BulkPullAccount) {
+        self.bulk_pull_account_response = Some(BulkPullAccountResponse {
+            account: bulk_pull_account.account.to_owned(),
+            frontier: BulkPullAccountFrontier::new(BlockHash::zero(), Rai::zero()),
+            pending: vec![],
+        });
+        self.incoming_kind = Incoming::BulkPullAccountFrontier(bulk_pull_account.flags);
+    }
+
+    /// The response of the last `BulkPullAccount`, if it has been completely received.
+    pub fn bulk_pull_account_response(&self) -> Option<&BulkPullAccountResponse> {
+        match self.incoming_kind {
+            Incoming::BulkPullAccountFrontier(_) | Incoming::BulkPullAccountPending(_) => None,
+            _ => self.bulk_pull_account_response.as_ref(),
+        }
+    }
+
+    /// Receive the next part of a bulk pull account response.
+    pub(crate) async fn recv_bulk_pull_account_response(&mut self) -> anyhow::Result<()> {
+        match self.incoming_kind {
+            Incoming::BulkPullAccountFrontier(flags) => {
+                let frontier = self.recv::<BulkPullAccountFrontier>(None).await?;
+                self.bulk_pull_account_response.as_mut().unwrap().frontier = frontier;
+                self.incoming_kind = Incoming::BulkPullAccountPending(flags);
+            }
+            Incoming::BulkPullAccountPending(flags) => {
+                let data = self.recv_buf(flags.entry_len()).await?;
+                let entry = PendingEntry::deserialize(flags, &data)
+                    .context("Receiving bulk pull account pending entry")?;
+                if entry.is_end() {
+                    self.incoming_kind = Incoming::Messages;
+                    debug!(
+                        "Bulk pull account response: {:?}",
+                        self.bulk_pull_account_response
+                    );
+                } else {
+                    self.bulk_pull_account_response
+                        .as_mut()
+                        .unwrap()
+                        .pending
+                        .push(entry);
+                }
+            }
+            _ => unreachable!("Receiving bulk pull account response outside of a request"),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use crate::blocks::{Block, BlockHash, Link, Previous};
+use crate::node::controller::Controller;
+use crate::node::state::ConfirmationHeight;
+use anyhow::{anyhow, Context};
+use tracing::debug;
+
+impl Controller {
+    /// Mark a confirmed block as final, along with every block before it in its account chain.
+    ///
+    /// A cemented block can't be rolled back. The send blocks received by those blocks are
+    /// cemented first, so a cemented block never depends on a block that isn't.
+    ///
+    /// Returns the hashes of the newly cemented blocks, in the order they were cemented.
+    pub async fn cement(&mut self, hash: &BlockHash) -> anyhow::Result<Vec<BlockHash>> {
+        let context = || format!("Cementing {:?}", hash);
+        let mut cemented = vec![];
+        let mut targets = vec![hash.to_owned()];
+
+        while let Some(target) = targets.last().cloned() {
+            let block = self.get_block(&target).await.with_context(context)?;
+            let account = block.account().to_owned();
+            let confirmed = self
+                .state
+                .lock()
+                .await
+                .confirmation_height(&account)
+                .await
+                .with_context(context)?;
+
+            let height = self.block_height(&target).await.with_context(context)?;
+            let base_height = confirmed.as_ref().map(|c| c.height).unwrap_or(0);
+            if height <= base_height {
+                targets.pop();
+                continue;
+            }
+
+            // The blocks that aren't cemented yet, from the target back.
+            let mut chain = vec![block];
+            while (chain.len() as u64) < height - base_height {
+                let previous = match chain[chain.len() - 1].previous() {
+                    Previous::Block(previous) => previous.to_owned(),
+                    Previous::Open => {
+                        return Err(anyhow!("Account chain ends below its confirmation height"))
+                            .with_context(context)
+                    }
+                };
+                chain.push(self.get_block(&previous).await.with_context(context)?);
+            }
+
+            let mut dependencies = vec![];
+            for block in &chain {
+                if let Some(source) = self.uncemented_source(block).await? {
+                    dependencies.push(source);
+                }
+            }
+            if !dependencies.is_empty() {
+                targets.extend(dependencies);
+                continue;
+            }
+
+            self.state
+                .lock()
+                .await
+                .set_confirmation_height(
+                    &account,
+                    &ConfirmationHeight {
+                        height,
+                        frontier: target.to_owned(),
+                    },
+                )
+                .await
+                .with_context(context)?;
+            debug!("Cemented {:?} up to height {}", &account, height);
+            for block in chain.iter().rev() {
+                cemented.push(block.hash()?.to_owned());
+            }
+            targets.pop();
+        }
+
+        Ok(cemented)
+    }
+
+    /// The send block received by `block`, if it's in the ledger and not cemented yet.
+    async fn uncemented_source(&self, block: &Block) -> anyhow::Result<Option<BlockHash>> {
+        let source = match block.link() {
+            Link::Source(source) => source,
+            _ => return Ok(None),
+        };
+        // E.g. the genesis block, which has no real source.
+        if self
+            .state
+            .lock()
+            .await
+            .get_block_by_hash(source)
+            .await?
+            .is_none()
+        {
+            return Ok(None);
+        }
+        Ok(if self.is_cemented(source).await? {
+            None
+        } else {
+            Some(source.to_owned())
+        })
+    }
+
+    pub async fn is_cemented(&self, hash: &BlockHash) -> anyhow::Result<bool> {
+        let block = self.get_block(hash).await?;
+        let confirmed = self
+            .state
+            .lock()
+            .await
+            .confirmation_height(block.account())
+            .await?;
+        Ok(match confirmed {
+            Some(confirmed) => self.block_height(hash).await? <= confirmed.height,
+            None => false,
+        })
+    }
+
+    /// The position of a block in its account chain, where the open block is 1.
+    pub async fn block_height(&self, hash: &BlockHash) -> anyhow::Result<u64> {
+        self.state
+            .lock()
+            .await
+            .block_height(hash)
+            .await?
+            .ok_or_else(|| anyhow!("Could not find block {:?}", hash))
+    }
+}
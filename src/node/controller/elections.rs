@@ -0,0 +1,311 @@
+use crate::blocks::{BlockHash, BlockHolder, Epoch, Subtype};
+use crate::node::controller::Controller;
+use crate::node::election::{Election, MAX_CANDIDATES};
+use crate::node::header::MessageType;
+use crate::node::messages::publish::Publish;
+use crate::{Rai, Subject};
+use anyhow::{anyhow, Context};
+use std::collections::{HashMap, VecDeque};
+use std::time::SystemTime;
+use tracing::{debug, info, warn};
+
+impl Controller {
+    /// Start an election for a block published by a peer, or add it to the election for its root.
+    ///
+    /// Blocks that are already in the ledger, or that couldn't be added to it because of their
+    /// work, signature or contents, are ignored.
+    /// Blocks with a missing previous or source block are parked as unchecked, and processed once
+    /// that block has been confirmed. New candidates are flooded to the other peers, up to
+    /// [MAX_CANDIDATES] for a root.
+    ///
+    /// Elections that have expired without reaching quorum are dropped first.
+    pub async fn handle_published_block(&mut self, holder: &BlockHolder) -> anyhow::Result<()> {
+        self.remove_expired_elections().await?;
+
+        let mut queue = VecDeque::new();
+        queue.push_back(holder.to_owned());
+        while let Some(holder) = queue.pop_front() {
+            if let Some(confirmed) = self.process_published_block(&holder).await? {
+                let mut state = self.state.lock().await;
+                queue.extend(state.take_unchecked(&confirmed).await?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the hash of the block if it was confirmed straight away.
+    async fn process_published_block(
+        &mut self,
+        holder: &BlockHolder,
+    ) -> anyhow::Result<Option<BlockHash>> {
+        let context = || format!("Published block {:?}", holder);
+        if self
+            .block_exists(&holder.hash())
+            .await
+            .with_context(context)?
+        {
+            return Ok(None);
+        }
+
+        if self.validate_work {
+            // The lowest threshold of any block on the network, as a cheap check before the block
+            // is stored. The threshold for the specific block type is checked when it's added to
+            // the ledger.
+            let subject = Subject::Hash(holder.root());
+            let threshold = self.network.work_threshold(Epoch::V2, &Subtype::Receive);
+            let enough_work = match holder.work() {
+                Some(work) => work.verify(&subject, &threshold)?,
+                None => false,
+            };
+            if !enough_work {
+                warn!(
+                    "Ignoring published block with insufficient work: {:?}",
+                    holder
+                );
+                return Ok(None);
+            }
+        }
+
+        if let Some(dependency) = self
+            .missing_dependency(holder)
+            .await
+            .with_context(context)?
+        {
+            debug!("Block is waiting for {:?}", dependency);
+            self.state
+                .lock()
+                .await
+                .add_unchecked(&dependency, holder)
+                .await
+                .with_context(context)?;
+            return Ok(None);
+        }
+
+        let block = self.fill_block(holder).await.with_context(context)?;
+        // The same checks as the winner of the election, so an epoch block is checked against the
+        // epoch signer rather than the account.
+        if let Err(err) = self.check_elected_block(&block).await {
+            warn!("Ignoring invalid published block: {:?}", err);
+            return Ok(None);
+        }
+
+        let root = block.root();
+        let contender = self
+            .ledger_block_for_root(&block)
+            .await
+            .with_context(context)?;
+        let (election, new_candidate) = {
+            let mut state = self.state.lock().await;
+            let (election, new_candidate) =
+                match state.election(&root).await.with_context(context)? {
+                    Some(mut election) => {
+                        // A candidate can be published again once a missing dependency has arrived,
+                        // so it's still tallied below.
+                        let added = election.add_candidate(block).with_context(context)?;
+                        if !added && election.candidates.len() >= MAX_CANDIDATES {
+                            debug!("Election for {:?} has too many candidates", &root);
+                        }
+                        (election, added)
+                    }
+                    None => match contender {
+                        // The ledger already has a different block for this root.
+                        Some(contender) => {
+                            warn!("Fork detected for root {:?}", &root);
+                            let mut election = Election::new(contender);
+                            election.add_candidate(block).with_context(context)?;
+                            (election, true)
+                        }
+                        None => (Election::new(block), true),
+                    },
+                };
+            state.set_election(&election).await.with_context(context)?;
+            (election, new_candidate)
+        };
+        if new_candidate {
+            let publish = Publish::new(holder.to_owned());
+            self.flood(MessageType::Publish, publish.extensions(), &publish);
+        }
+        debug!(
+            "Election for {:?} has {} candidates",
+            root,
+            election.candidates.len()
+        );
+
+        // Votes might have arrived before the block did.
+        self.tally_election(&root).await.with_context(context)
+    }
+
+    /// Count the votes of an active election, confirming the winning block if it reaches quorum.
+    ///
+    /// Only the latest vote of each representative for the root is counted, so a representative
+    /// can change their mind. Returns the hash of the confirmed block.
+    pub async fn tally_election(&mut self, root: &BlockHash) -> anyhow::Result<Option<BlockHash>> {
+        let context = || format!("Tallying election {:?}", root);
+        let election = match self.state.lock().await.election(root).await? {
+            Some(election) => election,
+            None => return Ok(None),
+        };
+
+        let tally = self.tally(&election).await.with_context(context)?;
+        let quorum = self.quorum_delta().await.with_context(context)?;
+        let winner = tally
+            .into_iter()
+            .filter(|(_, weight)| weight >= &quorum)
+            .max_by(|a, b| a.1.to_u128().cmp(&b.1.to_u128()))
+            .map(|(hash, _)| hash);
+        let winner = match winner {
+            Some(winner) => winner,
+            None => return Ok(None),
+        };
+
+        let block = election
+            .candidate(&winner)
+            .ok_or_else(|| anyhow!("Winner is not a candidate"))
+            .with_context(context)?
+            .to_owned();
+
+        if self.block_exists(&winner).await.with_context(context)? {
+            // The block we already had has won.
+            info!("Confirmed {:?}", &winner);
+            self.remove_election(root).await.with_context(context)?;
+            self.cement(&winner).await.with_context(context)?;
+            return Ok(Some(winner));
+        }
+
+        // The election is kept until the winner is in the ledger, so its votes still count once
+        // the block it depends on has arrived and it's published again from unchecked.
+        let holder = block.to_holder().with_context(context)?;
+        if let Some(dependency) = self
+            .missing_dependency(&holder)
+            .await
+            .with_context(context)?
+        {
+            debug!("Confirmed {:?} is waiting for {:?}", &winner, &dependency);
+            self.state
+                .lock()
+                .await
+                .add_unchecked(&dependency, &holder)
+                .await
+                .with_context(context)?;
+            return Ok(None);
+        }
+
+        // A losing fork that we had added to the ledger needs to go, with anything built on it.
+        let mut losers = vec![];
+        for hash in election.candidate_hashes()? {
+            if hash != winner && self.block_exists(&hash).await.with_context(context)? {
+                if self.is_cemented(&hash).await.with_context(context)? {
+                    warn!("Not replacing cemented {:?} with {:?}", &hash, &winner);
+                    self.remove_election(root).await.with_context(context)?;
+                    return Ok(None);
+                }
+                losers.push(hash);
+            }
+        }
+
+        // Check the winner before anything is rolled back, so a winner that can't be added leaves
+        // the ledger as it is.
+        if let Err(err) = self.check_elected_block(&block).await {
+            warn!("Could not add confirmed block {:?}: {:?}", &winner, err);
+            return Ok(None);
+        }
+        for hash in losers {
+            let removed = self.rollback(&hash).await.with_context(context)?;
+            info!("Rolled back {} blocks for {:?}", removed.len(), &winner);
+        }
+
+        if let Err(err) = self.add_elected_block(&block).await {
+            warn!("Could not add confirmed block {:?}: {:?}", &winner, err);
+            return Ok(None);
+        }
+        info!("Confirmed {:?}", &winner);
+        self.remove_election(root).await.with_context(context)?;
+        self.cement(&winner).await.with_context(context)?;
+        Ok(Some(winner))
+    }
+
+    /// End the election for `root`, dropping the votes for its candidates since they won't be
+    /// tallied again.
+    async fn remove_election(&self, root: &BlockHash) -> anyhow::Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(election) = state.election(root).await? {
+            for hash in election.candidate_hashes()? {
+                state.remove_votes(&hash).await?;
+            }
+        }
+        state.remove_election(root).await
+    }
+
+    /// Drop the elections that haven't reached quorum in time. Their blocks can start a new
+    /// election if they're published again.
+    pub(crate) async fn remove_expired_elections(&self) -> anyhow::Result<()> {
+        let expired = self
+            .state
+            .lock()
+            .await
+            .expired_elections(SystemTime::now())
+            .await?;
+        for root in expired {
+            info!("Election for {:?} expired", &root);
+            self.remove_election(&root).await?;
+        }
+        Ok(())
+    }
+
+    async fn block_exists(&self, hash: &BlockHash) -> anyhow::Result<bool> {
+        Ok(self
+            .state
+            .lock()
+            .await
+            .get_block_by_hash(hash)
+            .await?
+            .is_some())
+    }
+
+    /// The vote weight of each candidate of the election.
+    pub async fn tally(&self, election: &Election) -> anyhow::Result<HashMap<BlockHash, Rai>> {
+        let state = self.state.lock().await;
+
+        // The latest vote of each representative, across all candidates.
+        let mut latest = HashMap::new();
+        for hash in election.candidate_hashes()? {
+            for (representative, timestamp) in state.votes_for_block(&hash).await? {
+                let is_newer = match latest.get(&representative) {
+                    Some((_, latest_timestamp)) => &timestamp > latest_timestamp,
+                    None => true,
+                };
+                if is_newer {
+                    latest.insert(representative, (hash.to_owned(), timestamp));
+                }
+            }
+        }
+
+        let mut tally: HashMap<BlockHash, Rai> = HashMap::new();
+        for (representative, (hash, _)) in latest {
+            let weight = state.rep_weight(&representative).await?;
+            let total = tally.entry(hash).or_insert_with(Rai::zero);
+            *total = total
+                .checked_add(&weight)
+                .ok_or_else(|| anyhow!("Tally overflow"))?;
+        }
+        Ok(tally)
+    }
+
+    /// Tally the elections of blocks that have just been voted for.
+    pub(crate) async fn tally_votes(&mut self, hashes: &[BlockHash]) -> anyhow::Result<()> {
+        for hash in hashes {
+            let root = self
+                .state
+                .lock()
+                .await
+                .election_root_for_block(hash)
+                .await?;
+            if let Some(root) = root {
+                if let Some(confirmed) = self.tally_election(&root).await? {
+                    self.release_unchecked(&confirmed).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
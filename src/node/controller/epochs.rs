@@ -0,0 +1,106 @@
+use crate::blocks::{Block, BlockHash, Epoch, Link, Previous, Subtype};
+use crate::node::controller::Controller;
+use crate::{Public, Rai};
+use anyhow::anyhow;
+
+impl Controller {
+    /// Epoch blocks upgrade an account one epoch at a time, without changing its balance or
+    /// representative.
+    ///
+    /// An account that hasn't been opened yet can be opened by an epoch block if something is
+    /// pending for it. It then starts at the epoch of the block, with no representative.
+    pub(crate) async fn validate_epoch(
+        &self,
+        block: &Block,
+        previous: Option<&Block>,
+        upgrade: Epoch,
+    ) -> anyhow::Result<()> {
+        match previous {
+            Some(previous) => {
+                if block.balance() != previous.balance() {
+                    return Err(anyhow!("Can not change the balance in an epoch block"));
+                }
+                if block.representative() != previous.representative() {
+                    return Err(anyhow!(
+                        "Can not change the representative in an epoch block"
+                    ));
+                }
+                let epoch = self.block_epoch(previous.hash()?).await?;
+                if epoch.next() != Some(upgrade) {
+                    return Err(anyhow!(
+                        "Can not upgrade an account from {:?} to {:?}",
+                        epoch,
+                        upgrade
+                    ));
+                }
+            }
+            None => {
+                if block.balance() != &Rai::zero() {
+                    return Err(anyhow!("Can not change the balance in an epoch block"));
+                }
+                if block.representative() != &Public::zero() {
+                    return Err(anyhow!("Epoch open block has a representative"));
+                }
+                let pending = self
+                    .state
+                    .lock()
+                    .await
+                    .pending(block.account(), &Rai::zero())
+                    .await?;
+                if pending.is_empty() {
+                    return Err(anyhow!("Epoch open block without anything pending"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The epoch of an account chain as of a block in the ledger. Blocks without a recorded
+    /// epoch, and hashes that aren't in the ledger, count as [Epoch::V0].
+    pub async fn block_epoch(&self, hash: &BlockHash) -> anyhow::Result<Epoch> {
+        Ok(self
+            .state
+            .lock()
+            .await
+            .block_epoch(hash)
+            .await?
+            .unwrap_or(Epoch::V0))
+    }
+
+    /// The epoch of the latest block of an account, or [Epoch::V0] if it hasn't been opened.
+    pub async fn account_epoch(&self, account: &Public) -> anyhow::Result<Epoch> {
+        let latest = self
+            .state
+            .lock()
+            .await
+            .get_latest_block_hash_for_account(account)
+            .await?;
+        match latest {
+            Some(latest) => self.block_epoch(&latest).await,
+            None => Ok(Epoch::V0),
+        }
+    }
+
+    /// The epoch of the account chain once a validated `block` of `subtype` is added to it.
+    ///
+    /// An epoch block sets the epoch. Any other block keeps the epoch of its previous block, and a
+    /// receive also brings the account up to the epoch of the send it receives.
+    pub(crate) async fn epoch_after(
+        &self,
+        block: &Block,
+        subtype: &Subtype,
+    ) -> anyhow::Result<Epoch> {
+        if subtype == &Subtype::Epoch {
+            return Epoch::from_link(block.link())
+                .ok_or_else(|| anyhow!("Epoch block without an epoch link"));
+        }
+        let mut epoch = match block.previous() {
+            Previous::Block(previous) => self.block_epoch(previous).await?,
+            Previous::Open => Epoch::V0,
+        };
+        if let Link::Source(source) = block.link() {
+            epoch = epoch.max(self.block_epoch(source).await?);
+        }
+        Ok(epoch)
+    }
+}
@@ -0,0 +1,184 @@
+use crate::blocks::{Block, BlockHash, Previous};
+use crate::node::controller::Controller;
+use crate::{Public, Rai};
+use anyhow::{anyhow, Context};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, info};
+
+impl Controller {
+    /// The block in the ledger with the same root as `block`, i.e. the block that `block` would
+    /// fork from its account chain.
+    pub async fn ledger_block_for_root(&self, block: &Block) -> anyhow::Result<Option<Block>> {
+        let context = || format!("Ledger block for root of {:?}", block);
+        let head = self
+            .get_latest_block(block.account())
+            .await
+            .with_context(context)?;
+        let mut current = match head {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+
+        // The usual case of a block extending the account chain.
+        if let Previous::Block(previous) = block.previous() {
+            if current.hash()? == previous {
+                return Ok(None);
+            }
+        }
+
+        loop {
+            if current.previous() == block.previous() {
+                return Ok(Some(current));
+            }
+            current = match current.previous() {
+                Previous::Block(hash) => self.get_block(hash).await.with_context(context)?,
+                Previous::Open => return Ok(None),
+            };
+        }
+    }
+
+    /// The amount of `source` if it's received by a block that `block` forks away from, i.e. a
+    /// block after the previous block of `block` in the same account chain.
+    ///
+    /// Rolling back that fork makes the send pending again, so the winner of an election can
+    /// receive it too.
+    pub(crate) async fn received_in_fork(
+        &self,
+        block: &Block,
+        source: &BlockHash,
+    ) -> anyhow::Result<Option<Rai>> {
+        let receiver = self.state.lock().await.block_hash_receiving(source).await?;
+        let receiver = match receiver {
+            Some(receiver) => receiver,
+            None => return Ok(None),
+        };
+        let account = self
+            .state
+            .lock()
+            .await
+            .account_for_block_hash(&receiver)
+            .await?;
+        if account.as_ref() != Some(block.account()) {
+            return Ok(None);
+        }
+        if let Previous::Block(previous) = block.previous() {
+            if self.block_height(&receiver).await? <= self.block_height(previous).await? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.send_amount(source).await?))
+    }
+
+    /// Remove a block and every block after it in its account chain from the ledger.
+    ///
+    /// When a rolled back block is a send that has already been received, the receiving block in
+    /// the other account is rolled back first, along with everything after it.
+    ///
+    /// Nothing is removed if any of those blocks have been cemented.
+    ///
+    /// Returns the hashes of every block that was removed, in the order they were removed.
+    pub async fn rollback(&mut self, hash: &BlockHash) -> anyhow::Result<Vec<BlockHash>> {
+        let context = || format!("Rolling back {:?}", hash);
+        let plan = self.rollback_plan(hash).await.with_context(context)?;
+
+        // Cemented blocks are always at the start of an account chain, so if any block to be
+        // removed is cemented, so is the confirmed frontier of its account.
+        for block in &plan {
+            let confirmation_height = self
+                .state
+                .lock()
+                .await
+                .confirmation_height(block.account())
+                .await
+                .with_context(context)?;
+            if let Some(confirmation_height) = confirmation_height {
+                if &confirmation_height.frontier == block.hash()? {
+                    return Err(anyhow!(
+                        "Block {:?} is cemented",
+                        &confirmation_height.frontier
+                    ))
+                    .with_context(context);
+                }
+            }
+        }
+
+        let mut removed = vec![];
+        for block in plan {
+            let block_hash = block.hash().with_context(context)?.to_owned();
+            let update = self.remove_update(&block).await.with_context(context)?;
+            self.state
+                .lock()
+                .await
+                .apply_update(&update)
+                .await
+                .with_context(context)?;
+            info!("Rolled back {:?}", &block_hash);
+            removed.push(block_hash);
+        }
+        Ok(removed)
+    }
+
+    /// The blocks that rolling back `hash` would remove, in the order they need to be removed,
+    /// without changing the ledger.
+    async fn rollback_plan(&self, hash: &BlockHash) -> anyhow::Result<Vec<Block>> {
+        let mut plan = vec![];
+        let mut removed = HashSet::new();
+        // The head of each account once the blocks in the plan so far are removed.
+        let mut heads: HashMap<Public, Option<Block>> = HashMap::new();
+        let mut targets = vec![hash.to_owned()];
+
+        while let Some(target) = targets.last().cloned() {
+            if removed.contains(&target) {
+                targets.pop();
+                continue;
+            }
+            let account = self
+                .state
+                .lock()
+                .await
+                .account_for_block_hash(&target)
+                .await?;
+            let account = match account {
+                Some(account) => account,
+                None => {
+                    targets.pop();
+                    continue;
+                }
+            };
+
+            let head = match heads.get(&account) {
+                Some(head) => head.to_owned(),
+                None => self.get_latest_block(&account).await?,
+            };
+            let head = head.ok_or_else(|| anyhow!("Account {:?} has no blocks", account))?;
+            let head_hash = head.hash()?.to_owned();
+
+            let receiver = self
+                .state
+                .lock()
+                .await
+                .block_hash_receiving(&head_hash)
+                .await?;
+            if let Some(receiver) = receiver {
+                if !removed.contains(&receiver) {
+                    debug!("Rolling back {:?} which received {:?}", receiver, head_hash);
+                    targets.push(receiver);
+                    continue;
+                }
+            }
+
+            let previous = match head.previous() {
+                Previous::Block(previous) => Some(self.get_block(previous).await?),
+                Previous::Open => None,
+            };
+            heads.insert(account, previous);
+            if head_hash == target {
+                targets.pop();
+            }
+            removed.insert(head_hash);
+            plan.push(head);
+        }
+
+        Ok(plan)
+    }
+}
@@ -7,10 +7,24 @@ impl Controller {
     pub async fn ensure_genesis(&mut self) -> anyhow::Result<()> {
         info!("Ensuring genesis");
         let mut block = self.network.genesis_block();
+        let hash = block.hash().context("Genesis hash")?.to_owned();
+        if self
+            .state
+            .lock()
+            .await
+            .get_block_by_hash(&hash)
+            .await?
+            .is_some()
+        {
+            return Ok(());
+        }
 
         self.add_elected_block(&mut block)
             .await
             .context("Adding genesis block")?;
+        self.cement(&hash)
+            .await
+            .context("Cementing genesis block")?;
 
         Ok(())
     }
@@ -1,6 +1,12 @@
-use super::Controller;
+use super::{Controller, Incoming};
+use crate::blocks::{BlockHash, BlockType};
 use crate::node::cookie::Cookie;
 use crate::node::header::{Extensions, Header, MessageType};
+use crate::node::messages::bulk_pull::BulkPull;
+use crate::node::messages::bulk_pull_account::{
+    BulkPullAccount, BulkPullAccountFrontier, PendingEntry,
+};
+use crate::node::messages::bulk_push::BulkPush;
 use crate::node::messages::confirm_ack::ConfirmAck;
 use crate::node::messages::confirm_req::ConfirmReq;
 use crate::node::messages::frontier_req::FrontierReq;
@@ -10,8 +16,9 @@ use crate::node::messages::keepalive::Keepalive;
 use crate::node::messages::publish::Publish;
 use crate::node::messages::telemetry_ack::TelemetryAck;
 use crate::node::messages::telemetry_req::TelemetryReq;
-use crate::{Public, Seed, Signature};
+use crate::{Public, Rai, Seed, Signature};
 use anyhow::Context;
+use std::time::Duration;
 use tracing::{debug, instrument, trace, warn};
 
 impl Controller {
@@ -67,8 +74,6 @@ impl Controller {
             let response = handshake
                 .response
                 .expect("response is None but is_response is True");
-            let public = response.public;
-            let signature = response.signature;
 
             // TODO: Move to controller
             let cookie = &self
@@ -87,8 +92,8 @@ impl Controller {
             let cookie = cookie.as_ref().unwrap();
 
             if self.validate_handshakes {
-                public
-                    .verify(&cookie.as_bytes(), &signature)
+                response
+                    .verify(cookie)
                     .context("Invalid signature in handshake response")?;
             }
         }
@@ -136,14 +141,9 @@ impl Controller {
     pub async fn handle_publish(
         &mut self,
         _header: &Header,
-        _publish: Publish,
+        publish: Publish,
     ) -> anyhow::Result<()> {
-        // dbg!(publish);
-
-        // self.state.lock().await.add_block(&publish.0).await?;
-        // todo!();
-
-        Ok(())
+        self.handle_published_block(&publish.0).await
     }
 
     pub async fn handle_confirm_req(
@@ -158,29 +158,118 @@ impl Controller {
     pub async fn handle_confirm_ack(
         &mut self,
         _header: &Header,
-        _confirm_ack: ConfirmAck,
+        confirm_ack: ConfirmAck,
+    ) -> anyhow::Result<()> {
+        self.add_vote(&confirm_ack).await
+    }
+
+    pub async fn handle_bulk_pull(
+        &mut self,
+        _header: &Header,
+        bulk_pull: BulkPull,
     ) -> anyhow::Result<()> {
-        // dbg!(confirm_ack);
+        if self.passive {
+            // The rest of this connection will be the peer's blocks without any headers.
+            self.streamed_blocks.clear();
+            self.incoming_kind = Incoming::BulkPull(bulk_pull.start_account());
+            return Ok(());
+        }
+
+        let blocks = self
+            .bulk_pull_chain(&bulk_pull)
+            .await
+            .context("Collecting blocks for bulk pull")?;
+
+        for block in blocks {
+            let holder = block.to_holder()?;
+            self.send(&holder.block_type()).await?;
+            self.send(&holder).await?;
+        }
+        self.send(&BlockType::NotABlock).await?;
+
         Ok(())
     }
 
-    pub async fn handle_frontier_req(
+    pub async fn handle_bulk_pull_account(
         &mut self,
         _header: &Header,
-        _frontier_req: FrontierReq,
+        bulk_pull_account: BulkPullAccount,
     ) -> anyhow::Result<()> {
-        // The rest of this connection will be a bunch of frontiers without any headers.
-        self.frontier_stream = true;
+        if self.passive {
+            // The rest of this connection will be the peer's response without any headers.
+            self.start_bulk_pull_account_response(&bulk_pull_account);
+            return Ok(());
+        }
+
+        let frontier = match self.get_latest_block(&bulk_pull_account.account).await? {
+            Some(block) => {
+                BulkPullAccountFrontier::new(block.hash()?.to_owned(), block.balance().to_owned())
+            }
+            None => BulkPullAccountFrontier::new(BlockHash::zero(), Rai::zero()),
+        };
+        self.send(&frontier).await?;
+
+        let flags = bulk_pull_account.flags;
+        let pending = self
+            .state
+            .lock()
+            .await
+            .pending(
+                &bulk_pull_account.account,
+                &bulk_pull_account.minimum_amount,
+            )
+            .await?;
+        for pending in pending {
+            let entry = PendingEntry {
+                hash_and_amount: Some((pending.send_hash, pending.amount)),
+                source: Some(pending.source),
+            };
+            self.send_raw(entry.serialize(flags)).await?;
+        }
+        self.send_raw(PendingEntry::end(flags).serialize(flags))
+            .await?;
+
+        Ok(())
+    }
 
+    pub async fn handle_bulk_push(
+        &mut self,
+        _header: &Header,
+        _bulk_push: BulkPush,
+    ) -> anyhow::Result<()> {
+        // The rest of this connection will be the peer's blocks without any headers.
+        self.incoming_kind = Incoming::BulkPush;
         Ok(())
     }
 
-    pub async fn handle_frontier_resp(
+    pub async fn handle_frontier_req(
         &mut self,
-        _frontier_resp: FrontierResp,
+        _header: &Header,
+        frontier_req: FrontierReq,
     ) -> anyhow::Result<()> {
-        // dbg!(frontier_resp);
-        // dbg!("----------------------------------------------------------------------");
+        if self.passive {
+            // The rest of this connection will be a bunch of frontiers without any headers.
+            self.incoming_kind = Incoming::Frontiers;
+            return Ok(());
+        }
+
+        let max_age = match frontier_req.age {
+            u32::MAX => None,
+            age => Some(Duration::from_secs(age as u64)),
+        };
+        let frontiers = self
+            .state
+            .lock()
+            .await
+            .frontiers(&frontier_req.start, max_age, frontier_req.count as usize)
+            .await
+            .context("Collecting frontiers for frontier req")?;
+
+        for (account, frontier_hash) in frontiers {
+            self.send(&FrontierResp::new(account, frontier_hash))
+                .await?;
+        }
+        self.send(&FrontierResp::end()).await?;
 
         Ok(())
     }
@@ -1,20 +1,32 @@
 mod blocks;
+mod bootstrap;
+mod cementing;
+mod elections;
+mod epochs;
+mod forks;
 mod genesis;
 mod messages;
+mod weights;
 
-use crate::blocks::Block;
+use crate::blocks::BlockHolder;
 use crate::network::Network;
 use crate::node::header::{Extensions, Header, MessageType};
+use crate::node::messages::bulk_pull_account::{BulkPullAccountFlags, BulkPullAccountResponse};
 use crate::node::messages::frontier_resp::FrontierResp;
 use crate::node::state::ArcState;
 use crate::node::wire::Wire;
 use crate::{to_hex, Public, Rai};
 use anyhow::{anyhow, Context};
+use blocks::VoteStats;
+use bootstrap::{BootstrapProgress, Pull, Push, MAX_PULLED_BLOCKS};
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::net::SocketAddr;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Receiver, Sender};
-use tracing::{debug, instrument, trace};
+use tracing::{debug, info, instrument, trace, warn};
 
 /// A message sent between channels that contains a peer's network data.
 #[derive(Debug)]
@@ -42,19 +54,107 @@ impl Packet {
     }
 }
 
+/// A message that one controller of a node passes on to the peers of all the other controllers,
+/// which is how published blocks and votes make it across the network.
+#[derive(Debug, Clone)]
+pub(crate) struct Flood {
+    /// The peer the message came from, which doesn't need it back.
+    from: SocketAddr,
+
+    /// The header and payload of the message.
+    data: Vec<u8>,
+}
+
+/// How many flooded messages a controller can fall behind on before it starts missing them.
+pub(crate) const FLOOD_CAPACITY: usize = 1024;
+
+/// Bootstrap responses don't have a header, so we need to remember what kind of data the peer is
+/// going to send us next.
+#[derive(Debug, PartialEq)]
+enum Incoming {
+    /// Regular messages which start with a header.
+    Messages,
+
+    /// Are we doing a frontier req stream? (Bootstrap?)
+    Frontiers,
+
+    /// Blocks of an account, in response to our `BulkPull`.
+    BulkPull(Public),
+
+    /// Blocks following a `BulkPush` from the peer.
+    BulkPush,
+
+    /// The account frontier and balance, in response to our `BulkPullAccount`.
+    BulkPullAccountFrontier(BulkPullAccountFlags),
+
+    /// Pending entries following the account frontier of a `BulkPullAccount` response.
+    BulkPullAccountPending(BulkPullAccountFlags),
+}
+
+/// What a connection is used for, which decides the messages that belong on it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Connection {
+    /// A peer connected to us and hasn't sent anything yet.
+    Undecided,
+
+    /// Handshakes, keepalives, blocks and votes, which are flooded between realtime peers.
+    Realtime,
+
+    /// Bootstrap requests and their responses.
+    Bootstrap,
+}
+
 /// The controller handles the logic of one peer. It handles and emits messages, as well as time
 /// based actions, peer management, etc.
 pub struct Controller {
     /// Disable when used for pcap dump, where might have our own different cookie.
     pub validate_handshakes: bool,
 
+    /// Disable to accept blocks without checking their proof of work, e.g. in tests.
+    pub validate_work: bool,
+
+    /// Enable when used for pcap dump, where both sides of the connection are incoming data.
+    /// Requests are not responded to, and their responses are expected to follow.
+    pub passive: bool,
+
+    /// Use this connection for a legacy bootstrap instead of realtime messages. The controller
+    /// will quit once the bootstrap has finished.
+    pub bootstrap: bool,
+
+    /// Enable when the peer connected to us. Nothing is sent until the peer's first message, which
+    /// decides whether the connection is realtime or a bootstrap, since a bootstrapping peer only
+    /// expects responses to its own requests.
+    pub inbound: bool,
+
     network: Network,
     state: ArcState,
 
     peer_addr: SocketAddr,
 
-    /// Are we doing a frontier req stream? (Bootstrap?)
-    frontier_stream: bool,
+    /// Decided once `run` starts, or by the first message of an inbound peer.
+    connection: Connection,
+
+    /// What kind of data we're expecting from the peer.
+    incoming_kind: Incoming,
+
+    /// Blocks received from a `BulkPull` stream that haven't been processed yet.
+    streamed_blocks: Vec<BlockHolder>,
+    max_pulled_blocks: usize,
+
+    /// Accounts that are behind the peer, waiting to be pulled.
+    pulls: VecDeque<Pull>,
+
+    /// Accounts that the peer is behind on, to be pushed once pulling is done.
+    pushes: Vec<Push>,
+
+    /// The response of our last `BulkPullAccount`, which is filled in as it's received.
+    bulk_pull_account_response: Option<BulkPullAccountResponse>,
+
+    /// Votes received from the peer, and why any of them were rejected.
+    vote_stats: VoteStats,
+
+    /// Only set when we have requested frontiers from the peer.
+    bootstrap_progress: Option<BootstrapProgress>,
 
     /// Internal buffer for incoming data.
     incoming_buffer: Vec<u8>,
@@ -65,6 +165,12 @@ pub struct Controller {
     /// Data to be sent to the other peer.
     outgoing: Sender<Packet>,
 
+    /// Where new blocks and votes from this peer are flooded to the other peers of the node.
+    flood: Option<broadcast::Sender<Flood>>,
+
+    /// Blocks and votes flooded from the other peers of the node, to be forwarded to this peer.
+    flooded: Option<broadcast::Receiver<Flood>>,
+
     /// A reusable header to reduce allocations.
     pub(crate) header: Header,
 
@@ -84,13 +190,27 @@ impl Controller {
 
         let s = Self {
             validate_handshakes: true,
+            validate_work: true,
+            passive: false,
+            bootstrap: false,
+            inbound: false,
             network,
             state,
             peer_addr,
-            frontier_stream: false,
+            connection: Connection::Undecided,
+            incoming_kind: Incoming::Messages,
+            streamed_blocks: vec![],
+            max_pulled_blocks: MAX_PULLED_BLOCKS,
+            pulls: VecDeque::new(),
+            pushes: vec![],
+            bulk_pull_account_response: None,
+            vote_stats: VoteStats::default(),
+            bootstrap_progress: None,
             incoming_buffer: Vec::with_capacity(10_000),
             incoming: incoming_rx,
             outgoing: outgoing_tx,
+            flood: None,
+            flooded: None,
             header: Header::new(network, MessageType::Handshake, Extensions::new()),
             last_annotation: None,
         };
@@ -98,6 +218,14 @@ impl Controller {
         (s, incoming_tx, outgoing_rx)
     }
 
+    /// Take part in flooding with the other controllers of the node that share `flood`.
+    ///
+    /// Only realtime connections flood, since bootstrap peers aren't expecting messages.
+    pub(crate) fn set_flood(&mut self, flood: &broadcast::Sender<Flood>) {
+        self.flooded = Some(flood.subscribe());
+        self.flood = Some(flood.to_owned());
+    }
+
     /// Run will loop forever and is expected to be spawned and will quit when the incoming channel
     /// is closed.
     pub async fn run(mut self) -> anyhow::Result<()> {
@@ -118,38 +246,93 @@ impl Controller {
                     .$fun(&$header, payload)
                     .await
                     .with_context(|| format!("Handling payload for {:?}", $header))?;
-            };};
+            }};
         }
 
-        trace!("Initial handshake");
-        self.send_handshake().await?;
-        // trace!("Initial telemetry request");
-        // self.send_telemetry_req().await?;
+        if self.bootstrap {
+            self.set_connection(Connection::Bootstrap);
+            trace!("Initial frontier request");
+            self.send_frontier_req().await?;
+        } else if !self.inbound {
+            self.set_connection(Connection::Realtime);
+            trace!("Initial handshake");
+            self.send_handshake().await?;
+            // trace!("Initial telemetry request");
+            // self.send_telemetry_req().await?;
+        }
 
         loop {
-            if self.frontier_stream {
-                let payload = self.recv::<FrontierResp>(None).await?;
-                self.handle_frontier_resp(payload).await?;
-            } else {
-                let header = self.recv::<Header>(None).await?;
-                header.validate(&self.network)?;
-
-                match header.message_type() {
-                    MessageType::Keepalive => handle!(self, handle_keepalive, header),
-                    MessageType::Publish => handle!(self, handle_publish, header),
-                    MessageType::ConfirmReq => handle!(self, handle_confirm_req, header),
-                    MessageType::ConfirmAck => handle!(self, handle_confirm_ack, header),
-                    MessageType::FrontierReq => handle!(self, handle_frontier_req, header),
-                    MessageType::Handshake => handle!(self, handle_handshake, header),
-                    MessageType::TelemetryReq => handle!(self, handle_telemetry_req, header),
-                    MessageType::TelemetryAck => handle!(self, handle_telemetry_ack, header),
-                    // MessageType::BulkPull => {}
-                    // MessageType::BulkPush => {}
-                    // MessageType::BulkPullAccount => {}
-                    _ => panic!("{:?}", header),
-                };
+            if self.bootstrap && self.incoming_kind == Incoming::Messages {
+                info!("Bootstrap with {:?} finished", self.peer_addr);
+                return Ok(());
+            }
+
+            match self.incoming_kind {
+                Incoming::Frontiers => {
+                    let payload = self.recv::<FrontierResp>(None).await?;
+                    self.handle_frontier_resp(payload).await?;
+                }
+                Incoming::BulkPull(_) | Incoming::BulkPush => {
+                    self.recv_streamed_block().await?;
+                }
+                Incoming::BulkPullAccountFrontier(_) | Incoming::BulkPullAccountPending(_) => {
+                    self.recv_bulk_pull_account_response().await?;
+                }
+                Incoming::Messages => {
+                    let header = self.recv::<Header>(None).await?;
+                    header.validate(&self.network)?;
+                    self.check_connection(header.message_type())?;
+
+                    match header.message_type() {
+                        MessageType::Keepalive => handle!(self, handle_keepalive, header),
+                        MessageType::Publish => handle!(self, handle_publish, header),
+                        MessageType::ConfirmReq => handle!(self, handle_confirm_req, header),
+                        MessageType::ConfirmAck => handle!(self, handle_confirm_ack, header),
+                        MessageType::BulkPull => handle!(self, handle_bulk_pull, header),
+                        MessageType::BulkPush => handle!(self, handle_bulk_push, header),
+                        MessageType::FrontierReq => handle!(self, handle_frontier_req, header),
+                        MessageType::Handshake => handle!(self, handle_handshake, header),
+                        MessageType::BulkPullAccount => {
+                            handle!(self, handle_bulk_pull_account, header)
+                        }
+                        MessageType::TelemetryReq => handle!(self, handle_telemetry_req, header),
+                        MessageType::TelemetryAck => handle!(self, handle_telemetry_ack, header),
+                    };
+                }
+            }
+        }
+    }
+
+    fn set_connection(&mut self, connection: Connection) {
+        debug!("{:?} connection with {:?}", connection, self.peer_addr);
+        self.connection = connection;
+        if connection == Connection::Bootstrap {
+            self.flooded = None;
+        }
+    }
+
+    /// Decide what an inbound connection is used for from the peer's first message, and refuse
+    /// messages that don't belong on the connection.
+    fn check_connection(&mut self, message_type: MessageType) -> anyhow::Result<()> {
+        let bootstrap_request = matches!(
+            message_type,
+            MessageType::FrontierReq
+                | MessageType::BulkPull
+                | MessageType::BulkPullAccount
+                | MessageType::BulkPush
+        );
+        match self.connection {
+            Connection::Undecided if bootstrap_request => {
+                self.set_connection(Connection::Bootstrap)
+            }
+            Connection::Undecided => self.set_connection(Connection::Realtime),
+            // Passive controllers see both sides of any kind of connection.
+            Connection::Realtime if message_type == MessageType::BulkPush && !self.passive => {
+                return Err(anyhow!("Refusing a bulk push on a realtime connection"));
             }
+            _ => {}
         }
+        Ok(())
     }
 
     #[instrument(skip(self, header))]
@@ -169,7 +352,17 @@ impl Controller {
                 return self.recv_immediate(size);
             }
 
-            let packet = match self.incoming.recv().await {
+            let packet = tokio::select! {
+                packet = self.incoming.recv() => packet,
+                flood = Self::next_flood(&mut self.flooded) => {
+                    // An inbound peer doesn't get anything before it has said what it's here for.
+                    if flood.from != self.peer_addr && self.connection == Connection::Realtime {
+                        self.send_raw(flood.data).await?;
+                    }
+                    continue;
+                }
+            };
+            let packet = match packet {
                 Some(data) => data,
                 None => {
                     return Err(anyhow!(
@@ -187,6 +380,23 @@ impl Controller {
         }
     }
 
+    /// Waits for a message flooded by another controller, or forever when not flooding.
+    async fn next_flood(flooded: &mut Option<broadcast::Receiver<Flood>>) -> Flood {
+        if let Some(flooded) = flooded {
+            loop {
+                match flooded.recv().await {
+                    Ok(flood) => return flood,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Missed {} flooded messages", missed)
+                    }
+                    // Not possible while we hold a sender ourselves.
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+        std::future::pending().await
+    }
+
     fn recv_immediate(&mut self, size: usize) -> anyhow::Result<Vec<u8>> {
         debug_assert!(self.incoming_buffer.len() >= size);
 
@@ -201,13 +411,34 @@ impl Controller {
 
     #[instrument(level = "debug", skip(self, message))]
     async fn send<T: Wire + Debug>(&mut self, message: &T) -> anyhow::Result<()> {
-        let data = message.serialize();
-        trace!("HEX {}", to_hex(&data));
         debug!("OBJ {:?}", &message);
-        self.outgoing.send(Packet::new(Vec::from(data))).await?;
+        self.send_raw(message.serialize()).await
+    }
+
+    /// Send data that can't be serialized on its own, e.g. when its layout depends on a request.
+    async fn send_raw(&mut self, data: Vec<u8>) -> anyhow::Result<()> {
+        trace!("HEX {}", to_hex(&data));
+        self.outgoing.send(Packet::new(data)).await?;
         Ok(())
     }
 
+    /// Pass a message from this peer on to the other peers of the node, if flooding.
+    fn flood<T: Wire>(&self, message_type: MessageType, ext: Extensions, message: &T) {
+        let flood = match &self.flood {
+            Some(flood) if self.connection == Connection::Realtime => flood,
+            _ => return,
+        };
+        let mut header = self.header;
+        header.reset(message_type, ext);
+        let mut data = header.serialize();
+        data.extend(message.serialize());
+        // Only fails when there is nobody to flood to.
+        let _ = flood.send(Flood {
+            from: self.peer_addr,
+            data,
+        });
+    }
+
     async fn send_header(
         &mut self,
         message_type: MessageType,
@@ -224,11 +455,6 @@ impl Controller {
         Ok(())
     }
 
-    /// Update the representative weights based on this block being added to the network.
-    pub async fn balance_rep_weights(&mut self, _full_block: &Block) -> anyhow::Result<()> {
-        todo!()
-    }
-
     pub async fn account_balance(&self, account: &Public) -> anyhow::Result<Rai> {
         let context = || anyhow!("Account balance for {:?}", account);
         let block = self.get_latest_block(account).await.with_context(context)?;
@@ -250,24 +476,61 @@ impl Controller {
 
 #[cfg(test)]
 mod tests {
+    use super::blocks::VoteRejection;
     use super::*;
-    use crate::blocks::{Block, BlockHash, OpenBlock, Previous, SendBlock};
-    use crate::node::state::MemoryState;
-    use crate::{Address, DEFAULT_PORT};
+    use crate::blocks::{
+        Block, BlockHash, BlockType, ChangeBlock, Epoch, Link, OpenBlock, Previous, ReceiveBlock,
+        SendBlock, Subtype, ValidationState,
+    };
+    use crate::network::DevGenesis;
+    use crate::node::election::{Election, ELECTION_EXPIRY, MAX_CANDIDATES};
+    use crate::node::messages::bulk_pull::BulkPull;
+    use crate::node::messages::bulk_pull_account::{BulkPullAccount, PendingEntry};
+    use crate::node::messages::bulk_push::BulkPush;
+    use crate::node::messages::confirm_ack::{Confirm, ConfirmAck};
+    use crate::node::messages::frontier_req::FrontierReq;
+    use crate::node::messages::frontier_resp::FrontierResp;
+    use crate::node::state::{ConfirmationHeight, MemoryState};
+    use crate::node::timestamp::Timestamp;
+    use crate::{Address, Private, Seed, Signature, Subject, Work, DEFAULT_PORT};
+    use std::convert::TryFrom;
     use std::net::{Ipv4Addr, SocketAddrV4};
     use std::str::FromStr;
     use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
     use tokio::sync::Mutex;
 
     async fn empty_lattice(network: Network) -> Controller {
+        let (controller, _tx, _rx) = lattice_with_channels(network).await;
+        controller
+    }
+
+    async fn lattice_with_channels(
+        network: Network,
+    ) -> (Controller, Sender<Packet>, Receiver<Packet>) {
         let state = Arc::new(Mutex::new(MemoryState::new(network)));
-        let (mut controller, _rx, _tx) = Controller::new_with_channels(
+        let (mut controller, tx, rx) = Controller::new_with_channels(
             network,
             state,
             SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, DEFAULT_PORT)),
         );
         controller.init().await.unwrap();
-        controller
+        (controller, tx, rx)
+    }
+
+    /// The first send from the genesis account on the live network.
+    fn genesis_send() -> SendBlock {
+        serde_json::from_str(
+            r#"{
+                "type": "send",
+                "previous": "991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948",
+                "destination": "nano_13ezf4od79h1tgj9aiu4djzcmmguendtjfuhwfukhuucboua8cpoihmh8byo",
+                "balance": "FD89D89D89D89D89D89D89D89D89D89D",
+                "work": "3c82cc724905ee95",
+                "signature": "5B11B17DB9C8FE0CC58CAC6A6EECEF9CB122DA8A81C6D3DB1B5EE3AB065AA8F8CB1D6765C8EB91B58530C5FF5987AD95E6D34BB57F44257E20795EE412E61600"
+            }"#,
+        )
+        .unwrap()
     }
 
     #[tokio::test]
@@ -301,17 +564,7 @@ mod tests {
 
         let mut controller = empty_lattice(network).await;
 
-        let gen_send: SendBlock = serde_json::from_str(
-            r#"{
-                "type": "send",
-                "previous": "991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948",
-                "destination": "nano_13ezf4od79h1tgj9aiu4djzcmmguendtjfuhwfukhuucboua8cpoihmh8byo",
-                "balance": "FD89D89D89D89D89D89D89D89D89D89D",
-                "work": "3c82cc724905ee95",
-                "signature": "5B11B17DB9C8FE0CC58CAC6A6EECEF9CB122DA8A81C6D3DB1B5EE3AB065AA8F8CB1D6765C8EB91B58530C5FF5987AD95E6D34BB57F44257E20795EE412E61600"
-            }"#,
-        )
-        .unwrap();
+        let gen_send = genesis_send();
 
         // TODO: This should be done somewhere (the controller?
         // e.g. controller.validate_send_block() or controller.fill_send_block()
@@ -392,4 +645,1568 @@ mod tests {
                 .unwrap()
         );
     }
+
+    /// Serve a bulk pull from a lattice with the genesis send, to a lattice that only has genesis.
+    #[tokio::test]
+    async fn bulk_pull() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+
+        let (mut server, _server_tx, mut server_rx) = lattice_with_channels(network).await;
+        let gen_send =
+            Block::from_send_block(&genesis_send(), genesis.account(), genesis.representative());
+        server.add_elected_block(&gen_send).await.unwrap();
+
+        let (mut client, client_tx, _client_rx) = lattice_with_channels(network).await;
+        client
+            .send_bulk_pull(genesis.account(), genesis.hash().unwrap())
+            .await
+            .unwrap();
+
+        let header = Header::new(network, MessageType::BulkPull, Extensions::new());
+        let bulk_pull = BulkPull::account(genesis.account(), genesis.hash().unwrap().to_owned());
+        server.handle_bulk_pull(&header, bulk_pull).await.unwrap();
+
+        // Send block type, send block, end of stream.
+        for _ in 0..3 {
+            client_tx
+                .send(server_rx.recv().await.unwrap())
+                .await
+                .unwrap();
+        }
+        while client.incoming_kind != Incoming::Messages {
+            client.recv_streamed_block().await.unwrap();
+        }
+
+        assert_eq!(
+            client.account_balance(genesis.account()).await.unwrap(),
+            gen_send.balance().to_owned()
+        );
+    }
+
+    /// Pushed blocks are handled like published blocks. The newest block is parked until the one
+    /// before it arrives, and neither is added before it's confirmed.
+    #[tokio::test]
+    async fn bulk_push() {
+        let network = Network::Live;
+        let a_private = Seed::random().derive(0);
+        let a = a_private.to_public().unwrap();
+        let b = Seed::random().derive(1).to_public().unwrap();
+
+        let (mut server, server_tx, _server_rx) = lattice_with_channels(network).await;
+        server.validate_work = false;
+        let a_open = OpenBlock::new(BlockHash::zero(), a.clone(), a.clone());
+        let a_open = Block::from_open_block(&a_open, &Previous::Open, &Rai::from(100));
+        server.state.lock().await.add_block(&a_open).await.unwrap();
+        server.balance_rep_weights(&a_open).await.unwrap();
+
+        let first = signed_send(&a_private, a_open.hash().unwrap(), &b, 60);
+        let first = Block::from_send_block(&first, &a, &a);
+        let second = signed_send(&a_private, first.hash().unwrap(), &b, 50);
+        let second = Block::from_send_block(&second, &a, &a);
+
+        let (mut client, _client_tx, mut client_rx) = lattice_with_channels(network).await;
+        client
+            .send_bulk_push(&[second.clone(), first.clone()])
+            .await
+            .unwrap();
+
+        let header = Header::deserialize(None, &client_rx.recv().await.unwrap().data).unwrap();
+        assert_eq!(header.message_type(), MessageType::BulkPush);
+        server.handle_bulk_push(&header, BulkPush).await.unwrap();
+        assert_eq!(server.incoming_kind, Incoming::BulkPush);
+
+        // Empty payload, the block type and block of both blocks, end of stream.
+        for _ in 0..6 {
+            server_tx
+                .send(client_rx.recv().await.unwrap())
+                .await
+                .unwrap();
+        }
+        while server.incoming_kind != Incoming::Messages {
+            server.recv_streamed_block().await.unwrap();
+        }
+
+        assert_eq!(
+            server.state.lock().await.unchecked_count().await.unwrap(),
+            1
+        );
+        assert!(server
+            .state
+            .lock()
+            .await
+            .election(&first.root())
+            .await
+            .unwrap()
+            .is_some());
+        let latest = server.get_latest_block(&a).await.unwrap().unwrap();
+        assert_eq!(latest.hash().unwrap(), a_open.hash().unwrap());
+
+        let rep = Seed::random().derive(2);
+        set_rep_weight(&server, &rep, &Rai::from(50_000_000 * 10u128.pow(30))).await;
+        let hashes = vec![
+            first.hash().unwrap().to_owned(),
+            second.hash().unwrap().to_owned(),
+        ];
+        server
+            .add_vote(&signed_vote(&rep, 100, hashes))
+            .await
+            .unwrap();
+        let latest = server.get_latest_block(&a).await.unwrap().unwrap();
+        assert_eq!(latest.hash().unwrap(), second.hash().unwrap());
+        assert_eq!(
+            server.state.lock().await.unchecked_count().await.unwrap(),
+            0
+        );
+    }
+
+    /// A peer that keeps streaming blocks in response to a bulk pull is dropped.
+    #[tokio::test]
+    async fn bulk_pull_cap() {
+        let network = Network::Live;
+        let a_private = Seed::random().derive(0);
+        let a = a_private.to_public().unwrap();
+        let b = Seed::random().derive(1).to_public().unwrap();
+        let first = signed_send(&a_private, &BlockHash::zero(), &b, 60);
+        let first = Block::from_send_block(&first, &a, &a);
+        let second = signed_send(&a_private, first.hash().unwrap(), &b, 50);
+        let second = Block::from_send_block(&second, &a, &a);
+
+        let (mut client, client_tx, _client_rx) = lattice_with_channels(network).await;
+        client.incoming_kind = Incoming::BulkPull(a.clone());
+        client.max_pulled_blocks = 1;
+
+        // The peer's response is the same stream as a bulk push, without the header.
+        let (mut server, _server_tx, mut server_rx) = lattice_with_channels(network).await;
+        server
+            .send_bulk_push(&[second.clone(), first.clone()])
+            .await
+            .unwrap();
+        server_rx.recv().await.unwrap();
+        for _ in 0..6 {
+            client_tx
+                .send(server_rx.recv().await.unwrap())
+                .await
+                .unwrap();
+        }
+
+        client.recv_streamed_block().await.unwrap();
+        assert!(client.recv_streamed_block().await.is_err());
+    }
+
+    /// Only a connection that isn't used for realtime messages can push blocks to us.
+    #[tokio::test]
+    async fn bulk_push_connection() {
+        let mut realtime = empty_lattice(Network::Live).await;
+        realtime.check_connection(MessageType::Handshake).unwrap();
+        assert!(realtime.check_connection(MessageType::BulkPush).is_err());
+
+        let mut bootstrap = empty_lattice(Network::Live).await;
+        bootstrap
+            .check_connection(MessageType::FrontierReq)
+            .unwrap();
+        bootstrap.check_connection(MessageType::BulkPush).unwrap();
+    }
+
+    #[tokio::test]
+    async fn bulk_pull_account() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let gen_send =
+            Block::from_send_block(&genesis_send(), genesis.account(), genesis.representative());
+
+        let (mut server, _server_tx, mut server_rx) = lattice_with_channels(network).await;
+        server.add_elected_block(&gen_send).await.unwrap();
+
+        let (mut client, client_tx, mut client_rx) = lattice_with_channels(network).await;
+        let flags = BulkPullAccountFlags::HashAmountAndAddress;
+        let landing_account = gen_send.destination().unwrap().to_owned();
+        client
+            .send_bulk_pull_account(&landing_account, Rai::zero(), flags)
+            .await
+            .unwrap();
+        assert!(client.bulk_pull_account_response().is_none());
+
+        let header = Header::deserialize(None, &client_rx.recv().await.unwrap().data).unwrap();
+        assert_eq!(header.message_type(), MessageType::BulkPullAccount);
+        let data = client_rx.recv().await.unwrap().data;
+        let bulk_pull_account = BulkPullAccount::deserialize(Some(&header), &data).unwrap();
+        server
+            .handle_bulk_pull_account(&header, bulk_pull_account)
+            .await
+            .unwrap();
+
+        // Frontier, the genesis send and the end of the pending entries.
+        for _ in 0..3 {
+            client_tx
+                .send(server_rx.recv().await.unwrap())
+                .await
+                .unwrap();
+        }
+        while client.incoming_kind != Incoming::Messages {
+            client.recv_bulk_pull_account_response().await.unwrap();
+        }
+
+        // The landing account hasn't been opened yet.
+        let response = client.bulk_pull_account_response().unwrap();
+        assert_eq!(response.account, landing_account);
+        assert_eq!(response.frontier.frontier_hash, BlockHash::zero());
+        assert_eq!(response.frontier.balance, Rai::zero());
+        let given = Rai::max().checked_sub(gen_send.balance()).unwrap();
+        assert_eq!(
+            response.pending,
+            vec![PendingEntry {
+                hash_and_amount: Some((gen_send.hash().unwrap().to_owned(), given)),
+                source: Some(genesis.account().to_owned()),
+            }]
+        );
+    }
+
+    /// The peer has a newer frontier for the genesis account, so it should be pulled.
+    #[tokio::test]
+    async fn frontier_bootstrap() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let gen_send =
+            Block::from_send_block(&genesis_send(), genesis.account(), genesis.representative());
+
+        let (mut client, _client_tx, mut client_rx) = lattice_with_channels(network).await;
+        client.send_frontier_req().await.unwrap();
+        assert_eq!(client.incoming_kind, Incoming::Frontiers);
+        // Header and frontier request.
+        client_rx.recv().await.unwrap();
+        client_rx.recv().await.unwrap();
+
+        let frontiers = vec![
+            // An account that we're up to date with.
+            FrontierResp::new(
+                genesis.account().to_owned(),
+                genesis.hash().unwrap().to_owned(),
+            ),
+            // The same account, but the peer has a newer block.
+            FrontierResp::new(
+                genesis.account().to_owned(),
+                gen_send.hash().unwrap().to_owned(),
+            ),
+            FrontierResp::end(),
+        ];
+        for frontier in frontiers {
+            client.handle_frontier_resp(frontier).await.unwrap();
+        }
+
+        let progress = client.bootstrap_progress().unwrap();
+        assert_eq!(progress.frontiers, 2);
+        assert_eq!(progress.up_to_date, 1);
+        assert_eq!(progress.behind, 1);
+        assert_eq!(
+            client.incoming_kind,
+            Incoming::BulkPull(genesis.account().to_owned())
+        );
+
+        let header = Header::deserialize(None, &client_rx.recv().await.unwrap().data).unwrap();
+        assert_eq!(header.message_type(), MessageType::BulkPull);
+        let data = client_rx.recv().await.unwrap().data;
+        let bulk_pull = BulkPull::deserialize(Some(&header), &data).unwrap();
+        assert_eq!(&bulk_pull.start_account(), genesis.account());
+        assert_eq!(&bulk_pull.end, genesis.hash().unwrap());
+    }
+
+    /// Send a frontier request to the controller and collect the frontiers it responds with.
+    async fn serve_frontier_req(
+        controller: &mut Controller,
+        rx: &mut Receiver<Packet>,
+        frontier_req: FrontierReq,
+    ) -> Vec<FrontierResp> {
+        let header = Header::new(
+            controller.network,
+            MessageType::FrontierReq,
+            Extensions::new(),
+        );
+        controller
+            .handle_frontier_req(&header, frontier_req)
+            .await
+            .unwrap();
+
+        let mut frontiers = vec![];
+        loop {
+            let data = rx.recv().await.unwrap().data;
+            let frontier = FrontierResp::deserialize(None, &data).unwrap();
+            if frontier.is_end() {
+                return frontiers;
+            }
+            frontiers.push(frontier);
+        }
+    }
+
+    #[tokio::test]
+    async fn frontier_req() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let gen_send =
+            Block::from_send_block(&genesis_send(), genesis.account(), genesis.representative());
+
+        let (mut server, _tx, mut rx) = lattice_with_channels(network).await;
+        server.add_elected_block(&gen_send).await.unwrap();
+
+        let frontiers = serve_frontier_req(&mut server, &mut rx, FrontierReq::all()).await;
+        assert_eq!(frontiers.len(), 1);
+        assert_eq!(&frontiers[0].account, genesis.account());
+        assert_eq!(&frontiers[0].frontier_hash, gen_send.hash().unwrap());
+
+        // Recently modified.
+        let req = FrontierReq::new(Public::zero(), 60, u32::MAX);
+        let frontiers = serve_frontier_req(&mut server, &mut rx, req).await;
+        assert_eq!(frontiers.len(), 1);
+
+        // Starting after the genesis account.
+        let mut start = genesis.account().as_bytes().to_vec();
+        *start.last_mut().unwrap() += 1;
+        let start = Public::try_from(start.as_slice()).unwrap();
+        let req = FrontierReq::new(start, u32::MAX, u32::MAX);
+        let frontiers = serve_frontier_req(&mut server, &mut rx, req).await;
+        assert!(frontiers.is_empty());
+
+        let req = FrontierReq::new(Public::zero(), u32::MAX, 0);
+        let frontiers = serve_frontier_req(&mut server, &mut rx, req).await;
+        assert!(frontiers.is_empty());
+    }
+
+    fn signed_vote(private: &Private, timestamp: u64, hashes: Vec<BlockHash>) -> ConfirmAck {
+        let mut confirm_ack = ConfirmAck::new(
+            private.to_public().unwrap(),
+            Signature::zero(),
+            Timestamp::from_u64(timestamp),
+            Confirm::VoteByHash(hashes),
+        );
+        confirm_ack.signature = private.sign(&confirm_ack.inner_hash()).unwrap();
+        confirm_ack
+    }
+
+    #[tokio::test]
+    async fn vote_validation() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        let private = Seed::random().derive(0);
+        let representative = private.to_public().unwrap();
+        let hash =
+            BlockHash::from_str("0000000000000000000000000000000000000000000000000000000000000001")
+                .unwrap();
+        let other =
+            BlockHash::from_str("0000000000000000000000000000000000000000000000000000000000000002")
+                .unwrap();
+
+        // Votes from representatives without weight aren't stored.
+        let vote = signed_vote(&private, 100, vec![hash.clone()]);
+        controller.add_vote(&vote).await.unwrap();
+        assert_eq!(controller.vote_stats().no_weight, 1);
+        assert_eq!(
+            controller.vote_stats().last_rejection,
+            Some(VoteRejection::NoWeight)
+        );
+        let stored = controller
+            .state
+            .lock()
+            .await
+            .latest_vote_timestamp(&hash, &representative)
+            .await
+            .unwrap();
+        assert_eq!(stored, None);
+
+        set_rep_weight(&controller, &private, &Rai::from(1)).await;
+        controller.add_vote(&vote).await.unwrap();
+        assert_eq!(controller.vote_stats().accepted, 1);
+        let stored = controller
+            .state
+            .lock()
+            .await
+            .latest_vote_timestamp(&hash, &representative)
+            .await
+            .unwrap();
+        assert_eq!(stored, Some(Timestamp::from_u64(100)));
+
+        // Tampering with the timestamp invalidates the signature.
+        let mut tampered = signed_vote(&private, 200, vec![hash.clone()]);
+        tampered.timestamp = Timestamp::from_u64(300);
+        controller.add_vote(&tampered).await.unwrap();
+        assert_eq!(controller.vote_stats().invalid_signature, 1);
+        assert_eq!(
+            controller.vote_stats().last_rejection,
+            Some(VoteRejection::InvalidSignature)
+        );
+
+        // The same vote again, and an older vote, are both replays.
+        controller.add_vote(&vote).await.unwrap();
+        let older = signed_vote(&private, 50, vec![hash.clone()]);
+        controller.add_vote(&older).await.unwrap();
+        assert_eq!(controller.vote_stats().replayed, 2);
+        assert_eq!(
+            controller.vote_stats().last_rejection,
+            Some(VoteRejection::Replay {
+                hash: hash.clone(),
+                previous: Timestamp::from_u64(100)
+            })
+        );
+
+        // A newer vote replaces the old one.
+        let newer = signed_vote(&private, 150, vec![hash.clone()]);
+        controller.add_vote(&newer).await.unwrap();
+        assert_eq!(controller.vote_stats().accepted, 2);
+        let stored = controller
+            .state
+            .lock()
+            .await
+            .latest_vote_timestamp(&hash, &representative)
+            .await
+            .unwrap();
+        assert_eq!(stored, Some(Timestamp::from_u64(150)));
+
+        // Votes for different blocks are checked separately, so an older vote for another block
+        // still counts.
+        let switch = signed_vote(&private, 120, vec![other.clone()]);
+        controller.add_vote(&switch).await.unwrap();
+        assert_eq!(controller.vote_stats().accepted, 3);
+
+        // A final vote can't be replaced, but doesn't stop votes for other blocks.
+        let last = Timestamp::from_u64(u64::MAX);
+        let final_vote = signed_vote(&private, u64::MAX, vec![hash.clone()]);
+        controller.add_vote(&final_vote).await.unwrap();
+        assert_eq!(controller.vote_stats().accepted, 4);
+        controller.add_vote(&final_vote).await.unwrap();
+        assert_eq!(controller.vote_stats().replayed, 3);
+        assert_eq!(
+            controller.vote_stats().last_rejection,
+            Some(VoteRejection::Replay {
+                hash: hash.clone(),
+                previous: last
+            })
+        );
+        let switch = signed_vote(&private, 200, vec![other.clone()]);
+        controller.add_vote(&switch).await.unwrap();
+        assert_eq!(controller.vote_stats().accepted, 5);
+    }
+
+    #[tokio::test]
+    async fn rep_weights() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let mut controller = empty_lattice(network).await;
+        assert_eq!(
+            controller
+                .rep_weight(genesis.representative())
+                .await
+                .unwrap(),
+            Rai::max()
+        );
+
+        let gen_send = genesis_send();
+        let block = Block::from_send_block(&gen_send, genesis.account(), genesis.representative());
+        controller.add_elected_block(&block).await.unwrap();
+        let given = Rai::from(3271945835778254456378601994536232802u128);
+        assert_eq!(
+            controller
+                .rep_weight(genesis.representative())
+                .await
+                .unwrap(),
+            Rai::max().checked_sub(&given).unwrap()
+        );
+
+        // The sent amount is delegated again once it's received.
+        let land_open = OpenBlock::new(
+            block.hash().unwrap().to_owned(),
+            Public::zero(),
+            gen_send.destination.to_owned(),
+        );
+        let land_open = Block::from_open_block(&land_open, &Previous::Open, &given);
+        controller.balance_rep_weights(&land_open).await.unwrap();
+        assert_eq!(controller.rep_weight(&Public::zero()).await.unwrap(), given);
+    }
+
+    #[tokio::test]
+    async fn online_weight() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        let hash = network.genesis_hash();
+        let online = Seed::random().derive(0);
+        let offline = Seed::random().derive(1);
+        let weight = Rai::from(100 * 10u128.pow(36));
+        for private in &[&online, &offline] {
+            controller
+                .state
+                .lock()
+                .await
+                .set_rep_weight(&private.to_public().unwrap(), &weight)
+                .await
+                .unwrap();
+        }
+
+        // Nobody has voted yet, so the quorum is based on the minimum online weight.
+        assert_eq!(controller.online_weight().await.unwrap(), Rai::zero());
+        let minimum_quorum = network.online_weight_minimum().to_u128() / 100 * 67;
+        assert_eq!(
+            controller.quorum_delta().await.unwrap(),
+            Rai::from(minimum_quorum)
+        );
+
+        let vote = signed_vote(&online, 100, vec![hash]);
+        controller.add_vote(&vote).await.unwrap();
+        assert_eq!(controller.online_weight().await.unwrap(), weight);
+        assert_eq!(
+            controller.quorum_delta().await.unwrap(),
+            Rai::from(67 * 10u128.pow(36))
+        );
+    }
+
+    async fn set_rep_weight(controller: &Controller, private: &Private, weight: &Rai) {
+        controller
+            .state
+            .lock()
+            .await
+            .set_rep_weight(&private.to_public().unwrap(), weight)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn election() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let mut controller = empty_lattice(network).await;
+        let gen_send = BlockHolder::Send(genesis_send());
+        let hash = gen_send.hash();
+
+        // Together these are over the quorum of the minimum online weight.
+        let first = Seed::random().derive(0);
+        let second = Seed::random().derive(1);
+        let weight = Rai::from(30_000_000 * 10u128.pow(30));
+        set_rep_weight(&controller, &first, &weight).await;
+        set_rep_weight(&controller, &second, &weight).await;
+
+        controller.handle_published_block(&gen_send).await.unwrap();
+        let root = network.genesis_hash();
+        let election = controller
+            .state
+            .lock()
+            .await
+            .election(&root)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(election.candidate_hashes().unwrap(), vec![hash.clone()]);
+
+        controller
+            .add_vote(&signed_vote(&first, 100, vec![hash.clone()]))
+            .await
+            .unwrap();
+        let tally = controller.tally(&election).await.unwrap();
+        assert_eq!(tally.get(&hash), Some(&weight));
+        assert_eq!(
+            controller.account_balance(genesis.account()).await.unwrap(),
+            Rai::max()
+        );
+
+        controller
+            .add_vote(&signed_vote(&second, 100, vec![hash.clone()]))
+            .await
+            .unwrap();
+        assert!(controller
+            .state
+            .lock()
+            .await
+            .election(&root)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            controller
+                .get_latest_block(genesis.account())
+                .await
+                .unwrap()
+                .unwrap()
+                .hash()
+                .unwrap(),
+            &hash
+        );
+
+        // The election is over, so its votes are dropped, and aren't kept again when they're
+        // rebroadcast.
+        let votes = controller
+            .state
+            .lock()
+            .await
+            .votes_for_block(&hash)
+            .await
+            .unwrap();
+        assert!(votes.is_empty());
+        controller
+            .add_vote(&signed_vote(&second, 100, vec![hash.clone()]))
+            .await
+            .unwrap();
+        let votes = controller
+            .state
+            .lock()
+            .await
+            .votes_for_block(&hash)
+            .await
+            .unwrap();
+        assert!(votes.is_empty());
+    }
+
+    /// Votes that arrive before the block are counted once it's published.
+    #[tokio::test]
+    async fn election_votes_before_block() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let mut controller = empty_lattice(network).await;
+        let gen_send = BlockHolder::Send(genesis_send());
+        let hash = gen_send.hash();
+
+        let rep = Seed::random().derive(0);
+        set_rep_weight(&controller, &rep, &Rai::from(50_000_000 * 10u128.pow(30))).await;
+        controller
+            .add_vote(&signed_vote(&rep, 100, vec![hash.clone()]))
+            .await
+            .unwrap();
+        assert_eq!(
+            controller.account_balance(genesis.account()).await.unwrap(),
+            Rai::max()
+        );
+
+        controller.handle_published_block(&gen_send).await.unwrap();
+        let latest = controller
+            .get_latest_block(genesis.account())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(latest.hash().unwrap(), &hash);
+    }
+
+    /// An election that doesn't reach quorum is dropped once it has expired.
+    #[tokio::test]
+    async fn election_expiry() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        let root = network.genesis_hash();
+        controller
+            .handle_published_block(&BlockHolder::Send(genesis_send()))
+            .await
+            .unwrap();
+
+        controller.remove_expired_elections().await.unwrap();
+        let mut election = controller
+            .state
+            .lock()
+            .await
+            .election(&root)
+            .await
+            .unwrap()
+            .unwrap();
+
+        election.started = SystemTime::now() - ELECTION_EXPIRY - Duration::from_secs(1);
+        controller
+            .state
+            .lock()
+            .await
+            .set_election(&election)
+            .await
+            .unwrap();
+        controller.remove_expired_elections().await.unwrap();
+        assert!(controller
+            .state
+            .lock()
+            .await
+            .election(&root)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    /// Forks past [MAX_CANDIDATES] aren't added to the election for their root.
+    #[tokio::test]
+    async fn election_candidate_cap() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        controller.validate_work = false;
+        let a_private = Seed::random().derive(0);
+        let a = a_private.to_public().unwrap();
+        let b = Seed::random().derive(1).to_public().unwrap();
+
+        let a_open = OpenBlock::new(BlockHash::zero(), a.clone(), a.clone());
+        let a_open = Block::from_open_block(&a_open, &Previous::Open, &Rai::from(100));
+        controller
+            .state
+            .lock()
+            .await
+            .add_block(&a_open)
+            .await
+            .unwrap();
+        controller.balance_rep_weights(&a_open).await.unwrap();
+
+        for balance in 0..=MAX_CANDIDATES as u128 {
+            let send = signed_send(&a_private, a_open.hash().unwrap(), &b, balance);
+            controller
+                .handle_published_block(&BlockHolder::Send(send))
+                .await
+                .unwrap();
+        }
+        let election = controller
+            .state
+            .lock()
+            .await
+            .election(a_open.hash().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(election.candidates.len(), MAX_CANDIDATES);
+    }
+
+    fn signed_send(
+        private: &Private,
+        previous: &BlockHash,
+        to: &Public,
+        balance: u128,
+    ) -> SendBlock {
+        let mut send = SendBlock::new(previous.to_owned(), to.to_owned(), Rai::from(balance));
+        let hash = BlockHolder::Send(send.clone()).hash();
+        send.signature = Some(private.sign(hash.as_bytes()).unwrap());
+        send.work = Some(Work::zero());
+        send
+    }
+
+    /// Account A sends to B, which B receives. A fork of the send to C then wins its election,
+    /// so both the send to B and B's open block are rolled back.
+    #[tokio::test]
+    async fn fork_rollback() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        controller.validate_work = false;
+        let a_private = Seed::random().derive(0);
+        let b_private = Seed::random().derive(1);
+        let a = a_private.to_public().unwrap();
+        let b = b_private.to_public().unwrap();
+        let c = Seed::random().derive(2).to_public().unwrap();
+
+        // Give A some funds without a real source block.
+        let a_open = OpenBlock::new(BlockHash::zero(), a.clone(), a.clone());
+        let a_open = Block::from_open_block(&a_open, &Previous::Open, &Rai::from(100));
+        let a_open_hash = a_open.hash().unwrap().to_owned();
+        controller
+            .state
+            .lock()
+            .await
+            .add_block(&a_open)
+            .await
+            .unwrap();
+        controller.balance_rep_weights(&a_open).await.unwrap();
+
+        let send_b = signed_send(&a_private, &a_open_hash, &b, 60);
+        let send_b = Block::from_send_block(&send_b, &a, &a);
+        let send_b_hash = send_b.hash().unwrap().to_owned();
+        controller.add_elected_block(&send_b).await.unwrap();
+
+        let mut b_open = OpenBlock::new(send_b_hash.clone(), b.clone(), b.clone());
+        let b_open_hash = BlockHolder::Open(b_open.clone()).hash();
+        b_open.signature = Some(b_private.sign(b_open_hash.as_bytes()).unwrap());
+        b_open.work = Some(Work::zero());
+        let b_open = Block::from_open_block(&b_open, &Previous::Open, &Rai::from(40));
+        controller.add_elected_block(&b_open).await.unwrap();
+        assert_eq!(controller.rep_weight(&a).await.unwrap(), Rai::from(60));
+        assert_eq!(controller.rep_weight(&b).await.unwrap(), Rai::from(40));
+
+        // The fork is detected, both when adding it directly and when it's published.
+        let send_c = signed_send(&a_private, &a_open_hash, &c, 70);
+        let send_c_hash = BlockHolder::Send(send_c.clone()).hash();
+        let full_send_c = Block::from_send_block(&send_c, &a, &a);
+        assert!(controller.add_elected_block(&full_send_c).await.is_err());
+        controller
+            .handle_published_block(&BlockHolder::Send(send_c))
+            .await
+            .unwrap();
+        let election = controller
+            .state
+            .lock()
+            .await
+            .election(&a_open_hash)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            election.candidate_hashes().unwrap(),
+            vec![send_b_hash.clone(), send_c_hash.clone()]
+        );
+
+        let rep = Seed::random().derive(3);
+        set_rep_weight(&controller, &rep, &Rai::from(50_000_000 * 10u128.pow(30))).await;
+        controller
+            .add_vote(&signed_vote(&rep, 100, vec![send_c_hash.clone()]))
+            .await
+            .unwrap();
+
+        let a_head = controller.get_latest_block(&a).await.unwrap().unwrap();
+        assert_eq!(a_head.hash().unwrap(), &send_c_hash);
+        assert!(controller.get_latest_block(&b).await.unwrap().is_none());
+        let state = controller.state.lock().await;
+        assert!(state
+            .get_block_by_hash(&send_b_hash)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(state
+            .get_block_by_hash(&b_open_hash)
+            .await
+            .unwrap()
+            .is_none());
+        drop(state);
+        assert_eq!(controller.rep_weight(&a).await.unwrap(), Rai::from(70));
+        assert_eq!(controller.rep_weight(&b).await.unwrap(), Rai::zero());
+        let state = controller.state.lock().await;
+        assert!(state.pending(&b, &Rai::zero()).await.unwrap().is_empty());
+        let pending = state.pending(&c, &Rai::zero()).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].send_hash, send_c_hash);
+        assert_eq!(pending[0].amount, Rai::from(30));
+    }
+
+    /// A fork that wins its election but can't be added doesn't roll back the block it forks.
+    #[tokio::test]
+    async fn invalid_fork_winner() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        controller.validate_work = false;
+        let a_private = Seed::random().derive(0);
+        let a = a_private.to_public().unwrap();
+        let b = Seed::random().derive(1).to_public().unwrap();
+        let a_open = fund_account(&mut controller, &a, 100).await;
+
+        let send_b = signed_send(&a_private, a_open.hash().unwrap(), &b, 60);
+        let send_b = Block::from_send_block(&send_b, &a, &a);
+        controller.add_elected_block(&send_b).await.unwrap();
+
+        // Signed by the wrong account.
+        let bad_private = Seed::random().derive(2);
+        let bad_send = signed_send(&bad_private, a_open.hash().unwrap(), &b, 50);
+        let bad_send = Block::from_send_block(&bad_send, &a, &a);
+        let bad_send_hash = bad_send.hash().unwrap().to_owned();
+        let mut election = Election::new(send_b.clone());
+        election.add_candidate(bad_send).unwrap();
+        controller
+            .state
+            .lock()
+            .await
+            .set_election(&election)
+            .await
+            .unwrap();
+
+        let rep = Seed::random().derive(3);
+        set_rep_weight(&controller, &rep, &Rai::from(50_000_000 * 10u128.pow(30))).await;
+        controller
+            .add_vote(&signed_vote(&rep, 100, vec![bad_send_hash]))
+            .await
+            .unwrap();
+
+        let a_head = controller.get_latest_block(&a).await.unwrap().unwrap();
+        assert_eq!(a_head.hash().unwrap(), send_b.hash().unwrap());
+        assert_eq!(controller.account_balance(&a).await.unwrap(), Rai::from(60));
+        assert!(controller
+            .state
+            .lock()
+            .await
+            .election(&election.root)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    /// Both sides of a fork receive the same send, which is pending again once the loser is
+    /// rolled back.
+    #[tokio::test]
+    async fn fork_receiving_same_send() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        controller.validate_work = false;
+        let a_private = Seed::random().derive(0);
+        let b_private = Seed::random().derive(1);
+        let a = a_private.to_public().unwrap();
+        let b = b_private.to_public().unwrap();
+        let a_open = fund_account(&mut controller, &a, 100).await;
+
+        let send = Block::from_send_block(
+            &signed_send(&a_private, a_open.hash().unwrap(), &b, 60),
+            &a,
+            &a,
+        );
+        controller.add_elected_block(&send).await.unwrap();
+        let b_open = |representative: &Public| {
+            let open = Block::new(
+                BlockType::State,
+                b.clone(),
+                Previous::Open,
+                representative.clone(),
+                Rai::from(40),
+                Link::Source(send.hash().unwrap().to_owned()),
+                ValidationState::Published,
+            );
+            signed(open, &b_private)
+        };
+        let loser = b_open(&b);
+        let winner = b_open(&a);
+        let winner_hash = winner.hash().unwrap().to_owned();
+        controller.add_elected_block(&loser).await.unwrap();
+
+        let mut election = Election::new(loser);
+        election.add_candidate(winner).unwrap();
+        controller
+            .state
+            .lock()
+            .await
+            .set_election(&election)
+            .await
+            .unwrap();
+        let rep = Seed::random().derive(2);
+        set_rep_weight(&controller, &rep, &Rai::from(50_000_000 * 10u128.pow(30))).await;
+        controller
+            .add_vote(&signed_vote(&rep, 100, vec![winner_hash.clone()]))
+            .await
+            .unwrap();
+
+        let b_head = controller.get_latest_block(&b).await.unwrap().unwrap();
+        assert_eq!(b_head.hash().unwrap(), &winner_hash);
+        assert_eq!(controller.rep_weight(&a).await.unwrap(), Rai::from(100));
+        assert_eq!(controller.rep_weight(&b).await.unwrap(), Rai::zero());
+    }
+
+    /// B's open block reaches quorum before the send it receives is in the ledger, so it waits as
+    /// unchecked with its election kept, and is added once the send arrives.
+    #[tokio::test]
+    async fn confirmed_block_waits_for_dependency() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        controller.validate_work = false;
+        let a_private = Seed::random().derive(0);
+        let b_private = Seed::random().derive(1);
+        let a = a_private.to_public().unwrap();
+        let b = b_private.to_public().unwrap();
+        let a_open = fund_account(&mut controller, &a, 100).await;
+
+        let send = Block::from_send_block(
+            &signed_send(&a_private, a_open.hash().unwrap(), &b, 60),
+            &a,
+            &a,
+        );
+        let send_hash = send.hash().unwrap().to_owned();
+        let b_open = Block::new(
+            BlockType::State,
+            b.clone(),
+            Previous::Open,
+            b.clone(),
+            Rai::from(40),
+            Link::Source(send_hash.clone()),
+            ValidationState::Published,
+        );
+        let b_open = signed(b_open, &b_private);
+        let b_open_hash = b_open.hash().unwrap().to_owned();
+        controller
+            .state
+            .lock()
+            .await
+            .set_election(&Election::new(b_open.clone()))
+            .await
+            .unwrap();
+
+        let rep = Seed::random().derive(2);
+        set_rep_weight(&controller, &rep, &Rai::from(50_000_000 * 10u128.pow(30))).await;
+        controller
+            .add_vote(&signed_vote(&rep, 100, vec![b_open_hash.clone()]))
+            .await
+            .unwrap();
+        assert!(controller.get_latest_block(&b).await.unwrap().is_none());
+        assert_eq!(
+            controller
+                .state
+                .lock()
+                .await
+                .unchecked_count()
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(controller
+            .state
+            .lock()
+            .await
+            .election(&b_open.root())
+            .await
+            .unwrap()
+            .is_some());
+
+        controller.add_elected_block(&send).await.unwrap();
+        controller.release_unchecked(&send_hash).await.unwrap();
+        let b_head = controller.get_latest_block(&b).await.unwrap().unwrap();
+        assert_eq!(b_head.hash().unwrap(), &b_open_hash);
+        assert!(controller.is_cemented(&b_open_hash).await.unwrap());
+        assert!(controller
+            .state
+            .lock()
+            .await
+            .election(&b_open.root())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    /// B's open block is published before the send it receives, so it waits as unchecked until
+    /// the send is confirmed.
+    #[tokio::test]
+    async fn publish_out_of_order() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        controller.validate_work = false;
+        let a_private = Seed::random().derive(0);
+        let b_private = Seed::random().derive(1);
+        let a = a_private.to_public().unwrap();
+        let b = b_private.to_public().unwrap();
+
+        let a_open = OpenBlock::new(BlockHash::zero(), a.clone(), a.clone());
+        let a_open = Block::from_open_block(&a_open, &Previous::Open, &Rai::from(100));
+        controller
+            .state
+            .lock()
+            .await
+            .add_block(&a_open)
+            .await
+            .unwrap();
+        controller.balance_rep_weights(&a_open).await.unwrap();
+
+        let send_b = signed_send(&a_private, a_open.hash().unwrap(), &b, 60);
+        let send_b = BlockHolder::Send(send_b);
+        let mut b_open = OpenBlock::new(send_b.hash(), b.clone(), b.clone());
+        let b_open_hash = BlockHolder::Open(b_open.clone()).hash();
+        b_open.signature = Some(b_private.sign(b_open_hash.as_bytes()).unwrap());
+        b_open.work = Some(Work::zero());
+        let b_open = BlockHolder::Open(b_open);
+
+        controller.handle_published_block(&b_open).await.unwrap();
+        assert_eq!(
+            controller.missing_dependency(&b_open).await.unwrap(),
+            Some(send_b.hash())
+        );
+        let unchecked = controller
+            .state
+            .lock()
+            .await
+            .unchecked_count()
+            .await
+            .unwrap();
+        assert_eq!(unchecked, 1);
+
+        // The votes for both blocks arrive before the send is published.
+        let rep = Seed::random().derive(2);
+        set_rep_weight(&controller, &rep, &Rai::from(50_000_000 * 10u128.pow(30))).await;
+        let vote = signed_vote(&rep, 100, vec![send_b.hash(), b_open_hash.clone()]);
+        controller.add_vote(&vote).await.unwrap();
+        assert!(controller.get_latest_block(&b).await.unwrap().is_none());
+
+        controller.handle_published_block(&send_b).await.unwrap();
+        let a_head = controller.get_latest_block(&a).await.unwrap().unwrap();
+        assert_eq!(a_head.hash().unwrap(), &send_b.hash());
+        let b_head = controller.get_latest_block(&b).await.unwrap().unwrap();
+        assert_eq!(b_head.hash().unwrap(), &b_open_hash);
+        assert_eq!(b_head.balance(), &Rai::from(40));
+        let unchecked = controller
+            .state
+            .lock()
+            .await
+            .unchecked_count()
+            .await
+            .unwrap();
+        assert_eq!(unchecked, 0);
+
+        // Confirmed blocks are cemented.
+        assert!(controller.is_cemented(&b_open_hash).await.unwrap());
+        assert!(controller.is_cemented(&send_b.hash()).await.unwrap());
+    }
+
+    /// Cementing B's open block cements the send it received first, and neither can be rolled
+    /// back after that.
+    #[tokio::test]
+    async fn cementing() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        controller.validate_work = false;
+        let a_private = Seed::random().derive(0);
+        let b_private = Seed::random().derive(1);
+        let a = a_private.to_public().unwrap();
+        let b = b_private.to_public().unwrap();
+        let c = Seed::random().derive(2).to_public().unwrap();
+        let a_open = fund_account(&mut controller, &a, 100).await;
+        let a_open_hash = a_open.hash().unwrap().to_owned();
+
+        let send_b = signed_send(&a_private, &a_open_hash, &b, 60);
+        let send_b = Block::from_send_block(&send_b, &a, &a);
+        let send_b_hash = send_b.hash().unwrap().to_owned();
+        controller.add_elected_block(&send_b).await.unwrap();
+        let b_open = OpenBlock::new(send_b_hash.clone(), b.clone(), b.clone());
+        let b_open = signed(
+            Block::from_open_block(&b_open, &Previous::Open, &Rai::from(40)),
+            &b_private,
+        );
+        let b_open_hash = b_open.hash().unwrap().to_owned();
+        controller.add_elected_block(&b_open).await.unwrap();
+        let send_c = signed_send(&a_private, &send_b_hash, &c, 50);
+        let send_c = Block::from_send_block(&send_c, &a, &a);
+        let send_c_hash = send_c.hash().unwrap().to_owned();
+        controller.add_elected_block(&send_c).await.unwrap();
+        assert_eq!(controller.block_height(&send_c_hash).await.unwrap(), 3);
+
+        let cemented = controller.cement(&b_open_hash).await.unwrap();
+        assert_eq!(
+            cemented,
+            vec![a_open_hash, send_b_hash.clone(), b_open_hash.clone()]
+        );
+        let state = controller.state.lock().await;
+        assert_eq!(
+            state.confirmation_height(&a).await.unwrap(),
+            Some(ConfirmationHeight {
+                height: 2,
+                frontier: send_b_hash.clone(),
+            })
+        );
+        assert_eq!(
+            state.confirmation_height(&b).await.unwrap(),
+            Some(ConfirmationHeight {
+                height: 1,
+                frontier: b_open_hash.clone(),
+            })
+        );
+        drop(state);
+        assert!(controller.is_cemented(&send_b_hash).await.unwrap());
+        assert!(!controller.is_cemented(&send_c_hash).await.unwrap());
+
+        // Cementing again doesn't do anything.
+        assert!(controller.cement(&send_b_hash).await.unwrap().is_empty());
+
+        // Nothing is removed when a cemented block would be rolled back.
+        assert!(controller.rollback(&send_b_hash).await.is_err());
+        let a_head = controller.get_latest_block(&a).await.unwrap().unwrap();
+        assert_eq!(a_head.hash().unwrap(), &send_c_hash);
+        let b_head = controller.get_latest_block(&b).await.unwrap().unwrap();
+        assert_eq!(b_head.hash().unwrap(), &b_open_hash);
+
+        // The block after the cemented ones can still be rolled back.
+        assert_eq!(
+            controller.rollback(&send_c_hash).await.unwrap(),
+            vec![send_c_hash]
+        );
+    }
+
+    #[tokio::test]
+    async fn publish_insufficient_work() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let mut controller = empty_lattice(network).await;
+        let mut gen_send = genesis_send();
+        gen_send.work = Some(Work::zero());
+
+        controller
+            .handle_published_block(&BlockHolder::Send(gen_send))
+            .await
+            .unwrap();
+        let election = controller
+            .state
+            .lock()
+            .await
+            .election(genesis.hash().unwrap())
+            .await
+            .unwrap();
+        assert!(election.is_none());
+    }
+
+    /// Open an account with some funds, without a real source block.
+    async fn fund_account(controller: &mut Controller, account: &Public, balance: u128) -> Block {
+        let open = OpenBlock::new(BlockHash::zero(), account.clone(), account.clone());
+        let open = Block::from_open_block(&open, &Previous::Open, &Rai::from(balance));
+        controller
+            .state
+            .lock()
+            .await
+            .add_block(&open)
+            .await
+            .unwrap();
+        controller.balance_rep_weights(&open).await.unwrap();
+        open
+    }
+
+    fn signed(mut block: Block, private: &Private) -> Block {
+        block.sign(private.clone()).unwrap();
+        block.set_work(Work::zero());
+        block
+    }
+
+    #[tokio::test]
+    async fn ledger_rules() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        controller.validate_work = false;
+        let a_private = Seed::random().derive(0);
+        let b_private = Seed::random().derive(1);
+        let c_private = Seed::random().derive(2);
+        let a = a_private.to_public().unwrap();
+        let b = b_private.to_public().unwrap();
+        let c = c_private.to_public().unwrap();
+        let a_open = fund_account(&mut controller, &a, 100).await;
+
+        let send = signed_send(&a_private, a_open.hash().unwrap(), &b, 60);
+        let send = Block::from_send_block(&send, &a, &a);
+        let send_hash = send.hash().unwrap().to_owned();
+        assert_eq!(
+            controller.validate_block(&send).await.unwrap(),
+            Subtype::Send
+        );
+        controller.add_elected_block(&send).await.unwrap();
+
+        // Sends can't increase the balance.
+        let bad_send = signed_send(&a_private, &send_hash, &b, 70);
+        let bad_send = Block::from_send_block(&bad_send, &a, &a);
+        assert!(controller.add_elected_block(&bad_send).await.is_err());
+
+        // The open block has to receive exactly the amount sent.
+        let open = OpenBlock::new(send_hash.clone(), b.clone(), b.clone());
+        for amount in &[30, 50] {
+            let wrong = Block::from_open_block(&open, &Previous::Open, &Rai::from(*amount));
+            let wrong = signed(wrong, &b_private);
+            assert!(controller.add_elected_block(&wrong).await.is_err());
+        }
+        let open = signed(
+            Block::from_open_block(&open, &Previous::Open, &Rai::from(40)),
+            &b_private,
+        );
+        let open_hash = open.hash().unwrap().to_owned();
+        controller.add_elected_block(&open).await.unwrap();
+
+        // A send can't be received twice.
+        let receive = ReceiveBlock::new(open_hash.clone(), send_hash.clone());
+        let receive = Block::from_receive_block(&receive, &b, &b, &Rai::from(80));
+        let receive = signed(receive, &b_private);
+        assert!(controller.add_elected_block(&receive).await.is_err());
+
+        // Only the account that was sent to can receive.
+        let c_open = OpenBlock::new(send_hash.clone(), c.clone(), c.clone());
+        let c_open = Block::from_open_block(&c_open, &Previous::Open, &Rai::from(40));
+        let c_open = signed(c_open, &c_private);
+        assert!(controller.add_elected_block(&c_open).await.is_err());
+
+        // Change blocks keep the balance.
+        let change = ChangeBlock::new(open_hash.clone(), c.clone());
+        let bad_change = signed(
+            Block::from_change_block(&change, &b, &Rai::from(41)),
+            &b_private,
+        );
+        assert!(controller.add_elected_block(&bad_change).await.is_err());
+        let change = signed(
+            Block::from_change_block(&change, &b, &Rai::from(40)),
+            &b_private,
+        );
+        let change_hash = change.hash().unwrap().to_owned();
+        controller.add_elected_block(&change).await.unwrap();
+        assert_eq!(controller.rep_weight(&c).await.unwrap(), Rai::from(40));
+
+        // A state send from B to C, which C opens with a state block.
+        let state_send = Block::new(
+            BlockType::State,
+            b.clone(),
+            Previous::Block(change_hash),
+            c.clone(),
+            Rai::from(10),
+            Link::DestinationAccount(c.clone()),
+            ValidationState::Published,
+        );
+        let state_send = signed(state_send, &b_private);
+        let state_send_hash = state_send.hash().unwrap().to_owned();
+        assert_eq!(
+            controller.validate_block(&state_send).await.unwrap(),
+            Subtype::Send
+        );
+        controller.add_elected_block(&state_send).await.unwrap();
+
+        let state_open = Block::new(
+            BlockType::State,
+            c.clone(),
+            Previous::Open,
+            c.clone(),
+            Rai::from(30),
+            Link::Source(state_send_hash),
+            ValidationState::Published,
+        );
+        let state_open = signed(state_open, &c_private);
+        assert_eq!(
+            controller.validate_block(&state_open).await.unwrap(),
+            Subtype::Open
+        );
+        controller.add_elected_block(&state_open).await.unwrap();
+        assert_eq!(controller.account_balance(&c).await.unwrap(), Rai::from(30));
+
+        // A state change can't have a link.
+        let state_change = Block::new(
+            BlockType::State,
+            c.clone(),
+            Previous::Block(state_open.hash().unwrap().to_owned()),
+            a.clone(),
+            Rai::from(30),
+            Link::DestinationAccount(a.clone()),
+            ValidationState::Published,
+        );
+        let state_change = signed(state_change, &c_private);
+        assert!(controller.validate_block(&state_change).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn epoch_blocks() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        controller.validate_work = false;
+        let a_private = Seed::random().derive(0);
+        let b_private = Seed::random().derive(1);
+        let a = a_private.to_public().unwrap();
+        let b = b_private.to_public().unwrap();
+        let c = Seed::random().derive(2).to_public().unwrap();
+        let a_open = fund_account(&mut controller, &a, 100).await;
+        let a_open_hash = a_open.hash().unwrap().to_owned();
+        assert_eq!(controller.account_epoch(&a).await.unwrap(), Epoch::V0);
+
+        let epoch_block = |epoch: Epoch, representative: &Public, balance: u128| {
+            Block::new(
+                BlockType::State,
+                a.clone(),
+                Previous::Block(a_open_hash.clone()),
+                representative.clone(),
+                Rai::from(balance),
+                epoch.link().unwrap(),
+                ValidationState::Published,
+            )
+        };
+        let upgrade = epoch_block(Epoch::V1, &a, 100);
+        assert_eq!(
+            controller.validate_block(&upgrade).await.unwrap(),
+            Subtype::Epoch
+        );
+        // Epoch blocks can't change the balance or representative, or skip an epoch.
+        assert!(controller
+            .validate_block(&epoch_block(Epoch::V1, &a, 99))
+            .await
+            .is_err());
+        assert!(controller
+            .validate_block(&epoch_block(Epoch::V1, &b, 100))
+            .await
+            .is_err());
+        assert!(controller
+            .validate_block(&epoch_block(Epoch::V2, &a, 100))
+            .await
+            .is_err());
+
+        // Only the epoch signer can sign them, not the account.
+        let err = controller
+            .add_elected_block(&signed(upgrade.clone(), &a_private))
+            .await
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains("Incorrect signature"));
+
+        // Pretend the epoch signer signed it.
+        {
+            let mut state = controller.state.lock().await;
+            state.add_block(&upgrade).await.unwrap();
+            state
+                .set_block_epoch(upgrade.hash().unwrap(), Epoch::V1)
+                .await
+                .unwrap();
+        }
+        let upgrade_hash = upgrade.hash().unwrap().to_owned();
+        assert_eq!(controller.account_epoch(&a).await.unwrap(), Epoch::V1);
+
+        // Legacy blocks aren't allowed anymore.
+        let legacy_send = signed_send(&a_private, &upgrade_hash, &b, 60);
+        let legacy_send = Block::from_send_block(&legacy_send, &a, &a);
+        let err = controller
+            .add_elected_block(&legacy_send)
+            .await
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains("Legacy block"));
+
+        // An account opened from a send in epoch 1 starts in epoch 1.
+        let send = Block::new(
+            BlockType::State,
+            a.clone(),
+            Previous::Block(upgrade_hash),
+            a.clone(),
+            Rai::from(60),
+            Link::DestinationAccount(b.clone()),
+            ValidationState::Published,
+        );
+        let send = signed(send, &a_private);
+        controller.add_elected_block(&send).await.unwrap();
+        assert_eq!(controller.account_epoch(&a).await.unwrap(), Epoch::V1);
+        let open = Block::new(
+            BlockType::State,
+            b.clone(),
+            Previous::Open,
+            b.clone(),
+            Rai::from(40),
+            Link::Source(send.hash().unwrap().to_owned()),
+            ValidationState::Published,
+        );
+        controller
+            .add_elected_block(&signed(open, &b_private))
+            .await
+            .unwrap();
+        assert_eq!(controller.account_epoch(&b).await.unwrap(), Epoch::V1);
+
+        // An account can only be opened by an epoch block if something is pending for it.
+        let epoch_open = Block::new(
+            BlockType::State,
+            c.clone(),
+            Previous::Open,
+            Public::zero(),
+            Rai::zero(),
+            Epoch::V2.link().unwrap(),
+            ValidationState::Published,
+        );
+        assert!(controller.validate_block(&epoch_open).await.is_err());
+    }
+
+    /// A send to the account whose key is the same as an epoch link is a regular send.
+    #[tokio::test]
+    async fn send_to_epoch_link() {
+        let network = Network::Live;
+        let mut controller = empty_lattice(network).await;
+        controller.validate_work = false;
+        let a_private = Seed::random().derive(0);
+        let a = a_private.to_public().unwrap();
+        let a_open = fund_account(&mut controller, &a, 100).await;
+
+        let destination = Public::try_from(Epoch::V1.link().unwrap().as_bytes()).unwrap();
+        let send = Block::new(
+            BlockType::State,
+            a.clone(),
+            Previous::Block(a_open.hash().unwrap().to_owned()),
+            a.clone(),
+            Rai::from(60),
+            Link::DestinationAccount(destination.clone()),
+            ValidationState::Published,
+        );
+        let send = signed(send, &a_private);
+        assert_eq!(
+            controller.validate_block(&send).await.unwrap(),
+            Subtype::Send
+        );
+        controller.add_elected_block(&send).await.unwrap();
+        assert_eq!(controller.account_epoch(&a).await.unwrap(), Epoch::V0);
+        assert_eq!(controller.account_balance(&a).await.unwrap(), Rai::from(60));
+        let pending = controller
+            .state
+            .lock()
+            .await
+            .pending(&destination, &Rai::zero())
+            .await
+            .unwrap();
+        assert_eq!(pending.len(), 1);
+    }
+
+    /// A dev network needs so little work that blocks can be made on the spot.
+    #[tokio::test]
+    async fn dev_network() {
+        let genesis_private = Seed::random().derive(0);
+        let network = Network::Dev(DevGenesis::new(&genesis_private).unwrap());
+        let mut controller = empty_lattice(network).await;
+        let genesis = network.genesis_block();
+        assert_eq!(
+            controller.account_balance(genesis.account()).await.unwrap(),
+            Rai::max()
+        );
+
+        let b_private = Seed::random().derive(1);
+        let b = b_private.to_public().unwrap();
+        let mut send = Block::new(
+            BlockType::State,
+            genesis.account().to_owned(),
+            Previous::Block(genesis.hash().unwrap().to_owned()),
+            genesis.account().to_owned(),
+            Rai::from(u128::MAX - 10),
+            Link::DestinationAccount(b.clone()),
+            ValidationState::Published,
+        );
+        send.sign(genesis_private).unwrap();
+        let threshold = network.work_threshold(Epoch::V0, &Subtype::Send);
+        send.set_work(Work::generate(&Subject::Hash(send.root()), &threshold).unwrap());
+        controller.add_elected_block(&send).await.unwrap();
+
+        let mut open = Block::new(
+            BlockType::State,
+            b.clone(),
+            Previous::Open,
+            b.clone(),
+            Rai::from(10),
+            Link::Source(send.hash().unwrap().to_owned()),
+            ValidationState::Published,
+        );
+        open.sign(b_private).unwrap();
+        let threshold = network.work_threshold(Epoch::V0, &Subtype::Open);
+        open.set_work(Work::generate(&Subject::Hash(open.root()), &threshold).unwrap());
+        controller.add_elected_block(&open).await.unwrap();
+        assert_eq!(controller.account_balance(&b).await.unwrap(), Rai::from(10));
+    }
+
+    /// An epoch block is signed by the epoch signer rather than the account, which on a dev
+    /// network is the genesis account.
+    #[tokio::test]
+    async fn published_epoch_block() {
+        let genesis_private = Seed::random().derive(0);
+        let network = Network::Dev(DevGenesis::new(&genesis_private).unwrap());
+        let mut controller = empty_lattice(network).await;
+        controller.validate_work = false;
+        let a = Seed::random().derive(1).to_public().unwrap();
+        let a_open = fund_account(&mut controller, &a, 100).await;
+
+        let upgrade = Block::new(
+            BlockType::State,
+            a.clone(),
+            Previous::Block(a_open.hash().unwrap().to_owned()),
+            a.clone(),
+            Rai::from(100),
+            Epoch::V1.link().unwrap(),
+            ValidationState::Published,
+        );
+        let upgrade = signed(upgrade, &genesis_private);
+        let hash = upgrade.hash().unwrap().to_owned();
+        controller
+            .handle_published_block(&upgrade.to_holder().unwrap())
+            .await
+            .unwrap();
+        assert!(controller
+            .state
+            .lock()
+            .await
+            .election(&upgrade.root())
+            .await
+            .unwrap()
+            .is_some());
+
+        controller
+            .add_vote(&signed_vote(&genesis_private, 1, vec![hash.clone()]))
+            .await
+            .unwrap();
+        let latest = controller.get_latest_block(&a).await.unwrap().unwrap();
+        assert_eq!(latest.hash().unwrap(), &hash);
+        assert_eq!(controller.account_epoch(&a).await.unwrap(), Epoch::V1);
+    }
+
+    #[tokio::test]
+    async fn insufficient_work() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let mut controller = empty_lattice(network).await;
+        let mut gen_send = genesis_send();
+        gen_send.work = Some(Work::zero());
+        let block = Block::from_send_block(&gen_send, genesis.account(), genesis.representative());
+
+        let err = controller.add_elected_block(&block).await.unwrap_err();
+        assert!(format!("{:?}", err).contains("Not enough work"));
+    }
 }
@@ -0,0 +1,165 @@
+use crate::blocks::{Block, Previous};
+use crate::node::controller::Controller;
+use crate::{Public, Rai};
+use anyhow::{anyhow, Context};
+use std::time::{Duration, SystemTime};
+
+/// Representatives that have voted within this period are counted towards the online weight.
+pub const ONLINE_WEIGHT_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+/// Percentage of the online weight needed to confirm a block.
+pub const QUORUM_PERCENT: u128 = 67;
+
+impl Controller {
+    /// Update the representative weights based on this block being added to the network.
+    pub async fn balance_rep_weights(&mut self, full_block: &Block) -> anyhow::Result<()> {
+        let weights = self.rep_weights_after_add(full_block).await?;
+        let mut state = self.state.lock().await;
+        for (representative, weight) in weights {
+            state.set_rep_weight(&representative, &weight).await?;
+        }
+        Ok(())
+    }
+
+    /// The new weights of the representatives affected by adding this block to the network.
+    ///
+    /// The whole balance of the previous block is taken away from its representative and the
+    /// balance of the new block is given to the new representative. This works the same for every
+    /// block type, e.g. a change block moves the balance, and a send reduces it.
+    pub(crate) async fn rep_weights_after_add(
+        &self,
+        full_block: &Block,
+    ) -> anyhow::Result<Vec<(Public, Rai)>> {
+        let context = || format!("Balancing rep weights for {:?}", full_block);
+        let mut weights = vec![];
+
+        if let Previous::Block(previous_hash) = full_block.previous() {
+            let previous = self.get_block(previous_hash).await.with_context(context)?;
+            self.sub_rep_weight(&mut weights, previous.representative(), previous.balance())
+                .await
+                .with_context(context)?;
+        }
+
+        self.add_rep_weight(
+            &mut weights,
+            full_block.representative(),
+            full_block.balance(),
+        )
+        .await
+        .with_context(context)?;
+        Ok(weights)
+    }
+
+    /// The new weights of the representatives affected by rolling back this block, undoing
+    /// [Controller::rep_weights_after_add].
+    pub(crate) async fn rep_weights_after_remove(
+        &self,
+        full_block: &Block,
+    ) -> anyhow::Result<Vec<(Public, Rai)>> {
+        let context = || format!("Unbalancing rep weights for {:?}", full_block);
+        let mut weights = vec![];
+
+        self.sub_rep_weight(
+            &mut weights,
+            full_block.representative(),
+            full_block.balance(),
+        )
+        .await
+        .with_context(context)?;
+
+        if let Previous::Block(previous_hash) = full_block.previous() {
+            let previous = self.get_block(previous_hash).await.with_context(context)?;
+            self.add_rep_weight(&mut weights, previous.representative(), previous.balance())
+                .await
+                .with_context(context)?;
+        }
+        Ok(weights)
+    }
+
+    /// The weight of a representative, taking the changes in `weights` into account.
+    async fn changed_rep_weight(
+        &self,
+        weights: &[(Public, Rai)],
+        representative: &Public,
+    ) -> anyhow::Result<Rai> {
+        match weights.iter().find(|(rep, _)| rep == representative) {
+            Some((_, weight)) => Ok(weight.to_owned()),
+            None => self.rep_weight(representative).await,
+        }
+    }
+
+    fn set_changed_rep_weight(
+        weights: &mut Vec<(Public, Rai)>,
+        representative: &Public,
+        weight: Rai,
+    ) {
+        match weights.iter_mut().find(|(rep, _)| rep == representative) {
+            Some((_, changed)) => *changed = weight,
+            None => weights.push((representative.to_owned(), weight)),
+        }
+    }
+
+    async fn add_rep_weight(
+        &self,
+        weights: &mut Vec<(Public, Rai)>,
+        representative: &Public,
+        amount: &Rai,
+    ) -> anyhow::Result<()> {
+        let weight = self
+            .changed_rep_weight(weights, representative)
+            .await?
+            .checked_add(amount)
+            .ok_or_else(|| anyhow!("Rep weight overflow for {:?}", representative))?;
+        Self::set_changed_rep_weight(weights, representative, weight);
+        Ok(())
+    }
+
+    async fn sub_rep_weight(
+        &self,
+        weights: &mut Vec<(Public, Rai)>,
+        representative: &Public,
+        amount: &Rai,
+    ) -> anyhow::Result<()> {
+        let weight = self
+            .changed_rep_weight(weights, representative)
+            .await?
+            .checked_sub(amount)
+            .ok_or_else(|| anyhow!("Rep weight underflow for {:?}", representative))?;
+        Self::set_changed_rep_weight(weights, representative, weight);
+        Ok(())
+    }
+
+    pub async fn rep_weight(&self, representative: &Public) -> anyhow::Result<Rai> {
+        self.state.lock().await.rep_weight(representative).await
+    }
+
+    /// The total weight of the representatives that have voted within [ONLINE_WEIGHT_PERIOD].
+    pub async fn online_weight(&self) -> anyhow::Result<Rai> {
+        let since = SystemTime::now() - ONLINE_WEIGHT_PERIOD;
+        let state = self.state.lock().await;
+        let mut total = Rai::zero();
+        for representative in state.reps_voted_since(since).await? {
+            let weight = state.rep_weight(&representative).await?;
+            total = total
+                .checked_add(&weight)
+                .ok_or_else(|| anyhow!("Online weight overflow"))?;
+        }
+        Ok(total)
+    }
+
+    /// The vote weight a block needs to be confirmed.
+    ///
+    /// This is a [QUORUM_PERCENT] of the online weight, which is never lower than the network's
+    /// [Network::online_weight_minimum](crate::network::Network::online_weight_minimum).
+    pub async fn quorum_delta(&self) -> anyhow::Result<Rai> {
+        let online_weight = self.online_weight().await?;
+        let minimum = self.network.online_weight_minimum();
+        let weight = if online_weight > minimum {
+            online_weight
+        } else {
+            minimum
+        };
+        // Divide first so this can't overflow.
+        Ok(Rai::from(weight.to_u128() / 100 * QUORUM_PERCENT))
+    }
+}
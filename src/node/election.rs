@@ -0,0 +1,61 @@
+use crate::blocks::{Block, BlockHash};
+use std::time::{Duration, SystemTime};
+
+/// The most blocks that can compete in one election, so a peer can't keep a root busy with
+/// endless forks. The same limit as nano-node.
+pub const MAX_CANDIDATES: usize = 10;
+
+/// How long an election can go without reaching quorum before it's dropped.
+pub const ELECTION_EXPIRY: Duration = Duration::from_secs(5 * 60);
+
+/// An election decides which block is confirmed for a root, using the votes of representatives.
+///
+/// Usually there is a single candidate. More than one candidate means the account has forked.
+#[derive(Debug, Clone)]
+pub struct Election {
+    pub root: BlockHash,
+    pub candidates: Vec<Block>,
+    pub started: SystemTime,
+}
+
+impl Election {
+    pub fn new(block: Block) -> Self {
+        Self {
+            root: block.root(),
+            candidates: vec![block],
+            started: SystemTime::now(),
+        }
+    }
+
+    pub fn candidate(&self, hash: &BlockHash) -> Option<&Block> {
+        self.candidates
+            .iter()
+            .find(|block| block.hash().map(|h| h == hash).unwrap_or(false))
+    }
+
+    /// Returns false if the block is already a candidate, or if there are already
+    /// [MAX_CANDIDATES].
+    pub fn add_candidate(&mut self, block: Block) -> anyhow::Result<bool> {
+        if self.candidate(block.hash()?).is_some() || self.candidates.len() >= MAX_CANDIDATES {
+            return Ok(false);
+        }
+        self.candidates.push(block);
+        Ok(true)
+    }
+
+    /// Whether the election started more than [ELECTION_EXPIRY] before `now`.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        match now.duration_since(self.started) {
+            Ok(age) => age > ELECTION_EXPIRY,
+            // The clock went backwards.
+            Err(_) => false,
+        }
+    }
+
+    pub fn candidate_hashes(&self) -> anyhow::Result<Vec<BlockHash>> {
+        self.candidates
+            .iter()
+            .map(|block| block.hash().map(|h| h.to_owned()))
+            .collect()
+    }
+}
@@ -14,9 +14,11 @@ pub struct Header {
     /// Always "R" 0x82, probably for RaiBlocks!
     magic_number: MagicNumber,
 
-    /// Network: live (C 0x43), beta (B 0x42), test (A 0x41).
+    /// Network: live (C 0x43), beta (B 0x42), test (A 0x41), or feeless' own dev (D 0x44).
     /// https://github.com/nanocurrency/nano-node/blob/8c650ee8f537c3ded9a4a518f5f7df56c6a67904/nano/secure/common.cpp#L89
-    network: Network,
+    ///
+    /// Only the byte is kept, since a dev network can't be rebuilt from it. See [Network::id].
+    network: u8,
 
     /// Protocol version
     /// https://github.com/nanocurrency/nano-node/blob/8c650ee8f537c3ded9a4a518f5f7df56c6a67904/nano/secure/common.hpp#L350
@@ -34,11 +36,12 @@ pub struct Header {
 
 impl Header {
     pub fn validate(&self, network: &Network) -> anyhow::Result<()> {
-        if &self.network != network {
+        if self.network != network.id() {
             return Err(anyhow!(
-                "network mismatch: They're on {:?}. We're on {:?}",
+                "network mismatch: They're on {:X}. We're on {} ({:X})",
                 self.network,
                 network,
+                network.id(),
             ));
         }
 
@@ -67,7 +70,7 @@ impl Header {
     pub fn new(network: Network, message_type: MessageType, ext: Extensions) -> Self {
         Self {
             magic_number: MagicNumber::new(),
-            network,
+            network: network.id(),
             version_max: Version::V18,
             version_using: Version::V18,
             version_min: Version::V18,
@@ -95,7 +98,7 @@ impl Wire for Header {
     fn serialize(&self) -> Vec<u8> {
         vec![
             self.magic_number.0,
-            self.network as u8,
+            self.network,
             self.version_max as u8,
             self.version_using as u8,
             self.version_min as u8,
@@ -113,12 +116,14 @@ impl Wire for Header {
         expect_len(data.len(), Header::LEN, "Header")?;
         MagicNumber::try_from(data[Self::MAGIC_NUMBER]).with_context(context)?;
 
-        let network = Network::try_from(data[Self::NETWORK]).with_context(context)?;
         let message_type = MessageType::try_from(data[Self::MESSAGE_TYPE])?;
         let ext =
             Extensions::try_from(&data[Self::EXTENSIONS..Self::EXTENSIONS + Extensions::LEN])?;
 
-        Ok(Header::new(network, message_type, ext))
+        Ok(Header {
+            network: data[Self::NETWORK],
+            ..Header::new(Network::Live, message_type, ext)
+        })
     }
 
     fn len(_: Option<&Header>) -> anyhow::Result<usize> {
@@ -213,6 +218,7 @@ impl Extensions {
     // Bit offsets and lengths
     const QUERY: usize = 0;
     const RESPONSE: usize = 1;
+    const COUNT_PRESENT: usize = 0;
     const ITEM_COUNT: usize = 12;
     const ITEM_COUNT_BITS: usize = 4;
     const BLOCK_TYPE: usize = 8;
@@ -240,16 +246,42 @@ impl Extensions {
         self.bits()[Self::RESPONSE]
     }
 
+    /// Used by `BulkPull` to signal that the extended parameters (count) are in the payload.
+    ///
+    /// This shares the same bit as `query`.
+    pub fn count_present(&mut self) -> &mut Self {
+        self.mut_bits().set(Self::COUNT_PRESENT, true);
+        self
+    }
+
+    pub fn is_count_present(&self) -> bool {
+        self.bits()[Self::COUNT_PRESENT]
+    }
+
     pub fn item_count(&self) -> usize {
         self.bits()[Self::ITEM_COUNT..Self::ITEM_COUNT + Self::ITEM_COUNT_BITS].load_be()
     }
 
+    /// Only 4 bits are available, so the count has to be less than 16.
+    pub fn set_item_count(&mut self, count: usize) -> &mut Self {
+        debug_assert!(count < 1 << Self::ITEM_COUNT_BITS);
+        self.mut_bits()[Self::ITEM_COUNT..Self::ITEM_COUNT + Self::ITEM_COUNT_BITS]
+            .store_be(count as u8);
+        self
+    }
+
     pub fn block_type(&self) -> anyhow::Result<BlockType> {
         self.bits()[Self::BLOCK_TYPE..Self::BLOCK_TYPE + Self::BLOCK_TYPE_BITS]
             .load_be::<u8>()
             .try_into()
     }
 
+    pub fn set_block_type(&mut self, block_type: &BlockType) -> &mut Self {
+        self.mut_bits()[Self::BLOCK_TYPE..Self::BLOCK_TYPE + Self::BLOCK_TYPE_BITS]
+            .store_be(block_type.as_u8());
+        self
+    }
+
     fn bits(&self) -> &BitSlice<Lsb0, u8> {
         self.0.view_bits()
     }
@@ -366,4 +398,24 @@ mod tests {
             assert_eq!(ext.item_count() as u8, *expected);
         }
     }
+
+    #[test]
+    fn block_type() {
+        let ext = *Extensions::new().query().set_block_type(&BlockType::State);
+        assert_eq!(ext.block_type().unwrap(), BlockType::State);
+        assert!(ext.is_query());
+
+        let ext = Extensions::try_from([0x00, 0x06].as_ref()).unwrap();
+        assert_eq!(ext.block_type().unwrap(), BlockType::State);
+    }
+
+    #[test]
+    fn set_item_count() {
+        let ext = *Extensions::new()
+            .set_block_type(&BlockType::NotABlock)
+            .set_item_count(2);
+        assert_eq!(ext.0, [0x00, 0x21]);
+        assert_eq!(ext.item_count(), 2);
+        assert_eq!(ext.block_type().unwrap(), BlockType::NotABlock);
+    }
 }
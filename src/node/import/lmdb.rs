@@ -0,0 +1,320 @@
+//! A read only reader of LMDB database files, just enough to read a nano-node ledger.
+//!
+//! Only the parts of the format that nano-node uses are supported: named databases with fixed
+//! size keys, and values that might be on overflow pages. Duplicate keys are not supported.
+//!
+//! The layout is for LMDB on a little endian 64 bit machine, which is what nano-node ledgers are
+//! made on in practice.
+use crate::bytes::Bytes;
+use anyhow::{anyhow, Context};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const MAGIC: u32 = 0xBEEF_C0DE;
+const PAGE_HEADER_LEN: usize = 16;
+const NODE_HEADER_LEN: usize = 8;
+const DB_LEN: usize = 48;
+const INVALID_PAGE: u64 = !0;
+
+/// LMDB itself can't open trees deeper than this, so anything deeper is a corrupt file, e.g. a
+/// branch page pointing back at itself.
+const MAX_DEPTH: usize = 32;
+
+const P_BRANCH: u16 = 0x01;
+const P_LEAF: u16 = 0x02;
+const P_LEAF2: u16 = 0x20;
+
+const F_BIGDATA: u16 = 0x01;
+const F_SUBDATA: u16 = 0x02;
+const F_DUPDATA: u16 = 0x04;
+
+/// The root of a database (`MDB_db`).
+#[derive(Debug, Clone)]
+pub struct Db {
+    pub entries: u64,
+    root: u64,
+}
+
+impl Db {
+    fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        let mut data = Bytes::new(data);
+        // Pad, flags and depth, then the page counts.
+        data.slice(8 + 8 * 3)?;
+        let entries = u64_le(data.slice(8)?)?;
+        let root = u64_le(data.slice(8)?)?;
+        Ok(Self { entries, root })
+    }
+}
+
+pub struct Lmdb {
+    file: File,
+    file_len: u64,
+    page_size: usize,
+    main: Db,
+}
+
+impl Lmdb {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut file = File::open(path).with_context(|| format!("Opening {:?}", path))?;
+        let file_len = file.metadata()?.len();
+
+        // The page size is in the first meta page, so read that with the smallest page size.
+        let mut first = vec![0u8; 4096];
+        file.read_exact(&mut first)
+            .context("Reading the first meta page")?;
+        let (page_size, first_meta) = Self::meta(&first)?;
+
+        let mut second = vec![0u8; page_size];
+        file.seek(SeekFrom::Start(page_size as u64))?;
+        file.read_exact(&mut second)
+            .context("Reading the second meta page")?;
+        let (_, second_meta) = Self::meta(&second)?;
+
+        // The meta page with the latest transaction is the current one.
+        let main = if second_meta.0 > first_meta.0 {
+            second_meta.1
+        } else {
+            first_meta.1
+        };
+        Ok(Self {
+            file,
+            file_len,
+            page_size,
+            main,
+        })
+    }
+
+    /// Returns the page size, and the transaction id with the main database.
+    fn meta(page: &[u8]) -> anyhow::Result<(usize, (u64, Db))> {
+        let mut data = Bytes::new(page);
+        data.slice(PAGE_HEADER_LEN)?;
+        let magic = u32_le(data.slice(4)?)?;
+        if magic != MAGIC {
+            return Err(anyhow!("Not an LMDB file, magic is {:X}", magic));
+        }
+        // Version, address and map size.
+        data.slice(4 + 8 + 8)?;
+        // The page size is kept in the padding of the free pages database.
+        let free = data.slice(DB_LEN)?;
+        let page_size = u32_le(&free[0..4])? as usize;
+        if !page_size.is_power_of_two() || !(4096..=65536).contains(&page_size) {
+            return Err(anyhow!("Invalid page size {}", page_size));
+        }
+        let main = Db::decode(data.slice(DB_LEN)?)?;
+        // Last page.
+        data.slice(8)?;
+        let transaction = u64_le(data.slice(8)?)?;
+        Ok((page_size, (transaction, main)))
+    }
+
+    /// Find a named database.
+    pub fn db(&mut self, name: &str) -> anyhow::Result<Option<Db>> {
+        let main = self.main.to_owned();
+        Ok(match self.get(&main, name.as_bytes())? {
+            Some(value) => Some(Db::decode(&value)?),
+            None => None,
+        })
+    }
+
+    /// Go through every key and value of `db` in key order.
+    pub fn cursor(&self, db: &Db) -> Cursor {
+        let stack = if db.root == INVALID_PAGE {
+            vec![]
+        } else {
+            vec![(db.root, 1)]
+        };
+        Cursor {
+            stack,
+            page: vec![],
+            nodes: vec![].into_iter(),
+        }
+    }
+
+    /// Look up a single key in `db`.
+    pub fn get(&mut self, db: &Db, key: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        if db.root == INVALID_PAGE {
+            return Ok(None);
+        }
+        let mut page_number = db.root;
+        for _ in 0..MAX_DEPTH {
+            let page = self.page(page_number)?;
+            let flags = page_flags(&page)?;
+            let nodes = node_offsets(&page)?;
+            if flags & P_BRANCH != 0 {
+                // The first key of a branch page is always empty, i.e. lower than any key.
+                let first = nodes
+                    .first()
+                    .ok_or_else(|| anyhow!("Branch page {} has no nodes", page_number))?;
+                let mut child = branch_child(&page, *first)?;
+                for offset in nodes.into_iter().skip(1) {
+                    if node_key(&page, offset)? > key {
+                        break;
+                    }
+                    child = branch_child(&page, offset)?;
+                }
+                page_number = child;
+            } else if flags & P_LEAF != 0 && flags & P_LEAF2 == 0 {
+                for offset in nodes {
+                    if node_key(&page, offset)? == key {
+                        return Ok(Some(self.leaf(&page, offset)?.1));
+                    }
+                }
+                return Ok(None);
+            } else {
+                return Err(anyhow!(
+                    "Unsupported page {} with flags {:X}",
+                    page_number,
+                    flags
+                ));
+            }
+        }
+        Err(anyhow!("Tree is deeper than {} pages", MAX_DEPTH))
+    }
+
+    fn page(&mut self, page_number: u64) -> anyhow::Result<Vec<u8>> {
+        self.read(page_number, 0, self.page_size)
+    }
+
+    fn read(&mut self, page_number: u64, offset: usize, len: usize) -> anyhow::Result<Vec<u8>> {
+        let start = page_number
+            .checked_mul(self.page_size as u64)
+            .and_then(|start| start.checked_add(offset as u64));
+        let end = start.and_then(|start| start.checked_add(len as u64));
+        let start = start
+            .zip(end)
+            .filter(|(_, end)| end <= &self.file_len)
+            .map(|(start, _)| start)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Page {} with length {} is outside of the file",
+                    page_number,
+                    len
+                )
+            })?;
+        let mut buffer = vec![0u8; len];
+        self.file.seek(SeekFrom::Start(start))?;
+        self.file
+            .read_exact(&mut buffer)
+            .with_context(|| format!("Reading page {}", page_number))?;
+        Ok(buffer)
+    }
+
+    fn leaf(&mut self, page: &[u8], offset: usize) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+        let header = slice(page, offset, NODE_HEADER_LEN)?;
+        let data_len = u16_le(&header[0..2])? as usize | (u16_le(&header[2..4])? as usize) << 16;
+        let flags = u16_le(&header[4..6])?;
+        let key_len = u16_le(&header[6..8])? as usize;
+        if flags & F_DUPDATA != 0 {
+            return Err(anyhow!("Duplicate keys are not supported"));
+        }
+        let key = slice(page, offset + NODE_HEADER_LEN, key_len)?.to_vec();
+        let data_offset = offset + NODE_HEADER_LEN + key_len;
+        let value = if flags & F_BIGDATA != 0 {
+            let overflow_page = u64_le(slice(page, data_offset, 8)?)?;
+            self.read(overflow_page, PAGE_HEADER_LEN, data_len)?
+        } else {
+            let len = if flags & F_SUBDATA != 0 {
+                DB_LEN
+            } else {
+                data_len
+            };
+            slice(page, data_offset, len)?.to_vec()
+        };
+        Ok((key, value))
+    }
+}
+
+/// Walks the tree of a database depth first, so leaves are visited in key order.
+pub struct Cursor {
+    /// Pages still to visit with their depth in the tree, with the next one at the end.
+    stack: Vec<(u64, usize)>,
+
+    /// The current leaf page, and the nodes in it that haven't been visited yet.
+    page: Vec<u8>,
+    nodes: std::vec::IntoIter<usize>,
+}
+
+impl Cursor {
+    pub fn next(&mut self, lmdb: &mut Lmdb) -> anyhow::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        loop {
+            if let Some(offset) = self.nodes.next() {
+                return Ok(Some(lmdb.leaf(&self.page, offset)?));
+            }
+            let (page_number, depth) = match self.stack.pop() {
+                Some(next) => next,
+                None => return Ok(None),
+            };
+            if depth > MAX_DEPTH {
+                return Err(anyhow!("Tree is deeper than {} pages", MAX_DEPTH));
+            }
+            let page = lmdb.page(page_number)?;
+            let flags = page_flags(&page)?;
+            let nodes = node_offsets(&page)?;
+            if flags & P_BRANCH != 0 {
+                // Children are pushed backwards so the first one is visited first.
+                for offset in nodes.into_iter().rev() {
+                    self.stack.push((branch_child(&page, offset)?, depth + 1));
+                }
+            } else if flags & P_LEAF != 0 && flags & P_LEAF2 == 0 {
+                self.page = page;
+                self.nodes = nodes.into_iter();
+            } else {
+                return Err(anyhow!(
+                    "Unsupported page {} with flags {:X}",
+                    page_number,
+                    flags
+                ));
+            }
+        }
+    }
+}
+
+fn page_flags(page: &[u8]) -> anyhow::Result<u16> {
+    u16_le(slice(page, 10, 2)?)
+}
+
+/// Where each node starts in the page.
+fn node_offsets(page: &[u8]) -> anyhow::Result<Vec<usize>> {
+    let lower = u16_le(slice(page, 12, 2)?)? as usize;
+    let count = lower.saturating_sub(PAGE_HEADER_LEN) / 2;
+    (0..count)
+        .map(|i| Ok(u16_le(slice(page, PAGE_HEADER_LEN + i * 2, 2)?)? as usize))
+        .collect()
+}
+
+fn node_key(page: &[u8], offset: usize) -> anyhow::Result<&[u8]> {
+    let key_len = u16_le(slice(page, offset + 6, 2)?)? as usize;
+    slice(page, offset + NODE_HEADER_LEN, key_len)
+}
+
+/// A branch node keeps the child page number where a leaf keeps the data size and flags.
+fn branch_child(page: &[u8], offset: usize) -> anyhow::Result<u64> {
+    let header = slice(page, offset, NODE_HEADER_LEN)?;
+    Ok(u16_le(&header[0..2])? as u64
+        | (u16_le(&header[2..4])? as u64) << 16
+        | (u16_le(&header[4..6])? as u64) << 32)
+}
+
+fn slice(page: &[u8], offset: usize, len: usize) -> anyhow::Result<&[u8]> {
+    page.get(offset..offset + len).ok_or_else(|| {
+        anyhow!(
+            "Node at {} with length {} is outside of the page",
+            offset,
+            len
+        )
+    })
+}
+
+fn u16_le(data: &[u8]) -> anyhow::Result<u16> {
+    Ok(u16::from_le_bytes(<[u8; 2]>::try_from(data)?))
+}
+
+fn u32_le(data: &[u8]) -> anyhow::Result<u32> {
+    Ok(u32::from_le_bytes(<[u8; 4]>::try_from(data)?))
+}
+
+fn u64_le(data: &[u8]) -> anyhow::Result<u64> {
+    Ok(u64::from_le_bytes(<[u8; 8]>::try_from(data)?))
+}
@@ -0,0 +1,735 @@
+//! Import the ledger of a nano-node (`data.ldb`) into a [State](crate::node::state::State).
+//!
+//! Each account chain is walked from its open block to its head, following the successor kept
+//! in the sideband of each block, so that legacy blocks can be filled in with their account,
+//! representative and balance. The hash of every block is checked against its key as it's read.
+//!
+//! Only ledgers with a single `blocks` table are supported, i.e. not the older layouts with a
+//! table per block type.
+mod lmdb;
+
+use crate::blocks::{Block, BlockHash, BlockHolder, BlockType, Epoch, Previous, ValidationState};
+use crate::bytes::Bytes;
+use crate::network::Network;
+use crate::node::header::{Extensions, Header, MessageType};
+use crate::node::state::{ArcState, ConfirmationHeight, Pending};
+use crate::node::wire::Wire;
+use crate::{Public, Rai};
+use anyhow::{anyhow, Context};
+use lmdb::{Db, Lmdb};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::Path;
+use tracing::info;
+
+/// How many of each thing were imported.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ImportSummary {
+    pub accounts: usize,
+    pub blocks: usize,
+    pub pending: usize,
+    pub confirmation_heights: usize,
+}
+
+/// Load the accounts, blocks, pending sends and confirmation heights of a nano-node ledger into
+/// `state`, which needs to have an empty ledger.
+///
+/// Representative weights are worked out from the head block of each account.
+pub async fn import_ldb(
+    path: &Path,
+    network: Network,
+    state: &ArcState,
+) -> anyhow::Result<ImportSummary> {
+    let context = || format!("Importing {:?}", path);
+    let is_empty = state
+        .lock()
+        .await
+        .frontiers(&Public::zero(), None, 1)
+        .await?
+        .is_empty();
+    if !is_empty {
+        return Err(anyhow!("The ledger to import into is not empty")).with_context(context);
+    }
+
+    // Accounts are read with one reader while blocks are looked up with another.
+    let mut accounts_lmdb = Lmdb::open(path).with_context(context)?;
+    let mut lmdb = Lmdb::open(path).with_context(context)?;
+    let accounts = table(&mut lmdb, "accounts").with_context(context)?;
+    let blocks = table(&mut lmdb, "blocks").with_context(context)?;
+    let pending = table(&mut lmdb, "pending").with_context(context)?;
+    let confirmation_height = table(&mut lmdb, "confirmation_height").with_context(context)?;
+    info!(
+        "Importing {} accounts and {} blocks",
+        accounts.entries, blocks.entries
+    );
+
+    if lmdb
+        .get(&blocks, network.genesis_hash().as_bytes())?
+        .is_none()
+    {
+        return Err(anyhow!(
+            "Ledger doesn't have the {:?} genesis block",
+            network
+        ))
+        .with_context(context);
+    }
+
+    let mut summary = ImportSummary::default();
+    let mut rep_weights: HashMap<Public, Rai> = HashMap::new();
+    let mut cursor = accounts_lmdb.cursor(&accounts);
+    while let Some((key, value)) = cursor.next(&mut accounts_lmdb).with_context(context)? {
+        let account = Public::try_from(key.as_slice())?;
+        let mut info = Bytes::new(&value);
+        let head = BlockHash::try_from(info.slice(BlockHash::LEN)?)?;
+        // Representative.
+        info.slice(Public::LEN)?;
+        let open = BlockHash::try_from(info.slice(BlockHash::LEN)?)?;
+
+        let (head_block, count) = import_account(&mut lmdb, &blocks, state, &account, &open, &head)
+            .await
+            .with_context(|| format!("Importing account {:?}", account))?;
+        summary.blocks += count;
+
+        let weight = rep_weights
+            .entry(head_block.representative().to_owned())
+            .or_insert_with(Rai::zero);
+        *weight = weight
+            .checked_add(head_block.balance())
+            .ok_or_else(|| anyhow!("Rep weight overflow"))?;
+        summary.accounts += 1;
+    }
+
+    let mut state = state.lock().await;
+    for (representative, weight) in rep_weights {
+        state.set_rep_weight(&representative, &weight).await?;
+    }
+
+    let mut cursor = lmdb.cursor(&pending);
+    while let Some((key, value)) = cursor.next(&mut lmdb).with_context(context)? {
+        let mut key = Bytes::new(&key);
+        let destination = Public::try_from(key.slice(Public::LEN)?)?;
+        let send_hash = BlockHash::try_from(key.slice(BlockHash::LEN)?)?;
+        let mut value = Bytes::new(&value);
+        let source = Public::try_from(value.slice(Public::LEN)?)?;
+        let amount = Rai::try_from(value.slice(Rai::LEN)?)?;
+        state
+            .add_pending(
+                &destination,
+                &Pending {
+                    send_hash,
+                    source,
+                    amount,
+                },
+            )
+            .await?;
+        summary.pending += 1;
+    }
+
+    let mut cursor = lmdb.cursor(&confirmation_height);
+    while let Some((key, value)) = cursor.next(&mut lmdb).with_context(context)? {
+        let account = Public::try_from(key.as_slice())?;
+        let mut value = Bytes::new(&value);
+        let height = u64::from_le_bytes(<[u8; 8]>::try_from(value.slice(8)?)?);
+        let frontier = BlockHash::try_from(value.slice(BlockHash::LEN)?)?;
+        state
+            .set_confirmation_height(&account, &ConfirmationHeight { height, frontier })
+            .await?;
+        summary.confirmation_heights += 1;
+    }
+
+    info!("Imported {:?}", summary);
+    Ok(summary)
+}
+
+fn table(lmdb: &mut Lmdb, name: &str) -> anyhow::Result<Db> {
+    lmdb.db(name)?
+        .ok_or_else(|| anyhow!("Ledger doesn't have a `{}` table", name))
+}
+
+/// Add every block of an account to `state`, returning the head block and how many blocks there
+/// were.
+async fn import_account(
+    lmdb: &mut Lmdb,
+    blocks: &Db,
+    state: &ArcState,
+    account: &Public,
+    open: &BlockHash,
+    head: &BlockHash,
+) -> anyhow::Result<(Block, usize)> {
+    let mut hash = open.to_owned();
+    let mut previous: Option<Block> = None;
+    let mut count = 0;
+    loop {
+        let data = lmdb
+            .get(blocks, hash.as_bytes())?
+            .ok_or_else(|| anyhow!("Missing block {:?}", hash))?;
+        let (block, successor, epoch) = decode_block(&data, account, previous.as_ref())
+            .with_context(|| format!("Decoding block {:?}", hash))?;
+
+        let calculated = block.hash()?;
+        if calculated != &hash {
+            return Err(anyhow!("Block {:?} hashes to {:?}", hash, calculated));
+        }
+        if block.account() != account {
+            return Err(anyhow!("Block {:?} belongs to {:?}", hash, block.account()));
+        }
+        // A corrupt successor could splice in a block from somewhere else, or loop back.
+        let expected_previous = match &previous {
+            Some(previous) => Previous::Block(previous.hash()?.to_owned()),
+            None => Previous::Open,
+        };
+        if block.previous() != &expected_previous {
+            return Err(anyhow!(
+                "Block {:?} follows {:?} instead of {:?}",
+                hash,
+                block.previous(),
+                expected_previous
+            ));
+        }
+
+        {
+            let mut state = state.lock().await;
+            state.add_block(&block).await?;
+            state.set_block_epoch(&hash, epoch).await?;
+        }
+        count += 1;
+
+        if &hash == head {
+            return Ok((block, count));
+        }
+        if successor == BlockHash::zero() {
+            return Err(anyhow!("Chain ended at {:?} before the head", hash));
+        }
+        hash = successor;
+        previous = Some(block);
+    }
+}
+
+/// Decode a block from the `blocks` table, which is the block type, the block as it is sent on the
+/// network, then the sideband. Returns the block, the hash of its successor and its epoch.
+///
+/// The sideband has what legacy blocks are missing, apart from the representative which comes
+/// from the `previous` block. Legacy blocks are always in epoch 0.
+fn decode_block(
+    data: &[u8],
+    account: &Public,
+    previous: Option<&Block>,
+) -> anyhow::Result<(Block, BlockHash, Epoch)> {
+    let mut data = Bytes::new(data);
+    let block_type = BlockType::try_from(data.u8()?)?;
+    let header = Header::new(
+        Network::Live,
+        MessageType::Publish,
+        *Extensions::new().set_block_type(&block_type),
+    );
+    let len = BlockHolder::len(Some(&header))?;
+    let holder = BlockHolder::deserialize(Some(&header), data.slice(len)?)?;
+
+    let successor = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
+    if block_type != BlockType::State && block_type != BlockType::Open {
+        // Account.
+        data.slice(Public::LEN)?;
+    }
+    if block_type != BlockType::Open {
+        // Height.
+        data.slice(8)?;
+    }
+    let sideband_balance = match block_type {
+        BlockType::Receive | BlockType::Change | BlockType::Open => {
+            Some(Rai::try_from(data.slice(Rai::LEN)?)?)
+        }
+        _ => None,
+    };
+    let epoch = if block_type == BlockType::State {
+        // Timestamp.
+        data.slice(8)?;
+        // The low bits of the block details are the epoch, which nano-node counts from 2.
+        let details = data.u8()?;
+        let epoch = (details & 0x1f)
+            .checked_sub(2)
+            .ok_or_else(|| anyhow!("Invalid epoch in block details {:X}", details))?;
+        Epoch::try_from(epoch)?
+    } else {
+        Epoch::V0
+    };
+    let balance = || {
+        sideband_balance
+            .to_owned()
+            .ok_or_else(|| anyhow!("No balance"))
+    };
+    let previous = || previous.ok_or_else(|| anyhow!("{:?} block without a previous", block_type));
+
+    let block = match &holder {
+        BlockHolder::Send(send) => {
+            Block::from_send_block(send, account, previous()?.representative())
+        }
+        BlockHolder::Receive(receive) => {
+            Block::from_receive_block(receive, account, previous()?.representative(), &balance()?)
+        }
+        BlockHolder::Open(open) => Block::from_open_block(open, &Previous::Open, &balance()?),
+        BlockHolder::Change(change) => Block::from_change_block(change, account, &balance()?),
+        BlockHolder::State(state) => {
+            let previous_balance = match previous() {
+                Ok(previous) => previous.balance().to_owned(),
+                Err(_) => Rai::zero(),
+            };
+            Block::from_state_block_with_previous_balance(
+                state,
+                &previous_balance,
+                ValidationState::Valid,
+            )?
+        }
+    };
+    Ok((block, successor, epoch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{Link, SendBlock, StateBlock};
+    use crate::node::state::MemoryState;
+    use crate::{Seed, Work};
+    use std::io::Write;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    type Table = (&'static str, Vec<(Vec<u8>, Vec<u8>)>);
+
+    const PAGE_SIZE: usize = 4096;
+
+    /// How the tables of a test LMDB file are spread over pages.
+    struct Layout {
+        /// The most nodes in a page, so that bigger tables need branch pages.
+        nodes_per_page: usize,
+
+        /// Values longer than this are kept on overflow pages.
+        max_inline_value: usize,
+    }
+
+    /// A single leaf page for each table, which is enough for a tiny ledger.
+    const SINGLE_PAGE: Layout = Layout {
+        nodes_per_page: usize::MAX,
+        max_inline_value: usize::MAX,
+    };
+
+    const PAGE_HEADER: usize = 16;
+
+    /// The pages after the two meta pages.
+    #[derive(Default)]
+    struct Pages(Vec<Vec<u8>>);
+
+    impl Pages {
+        fn next_number(&self) -> u64 {
+            2 + self.0.len() as u64
+        }
+
+        /// Add a page with nodes, returning its page number.
+        fn push_nodes(&mut self, flags: u16, nodes: &[Vec<u8>]) -> u64 {
+            let page_number = self.next_number();
+            let mut page = vec![0u8; PAGE_SIZE];
+            page[0..8].copy_from_slice(&page_number.to_le_bytes());
+            page[10..12].copy_from_slice(&flags.to_le_bytes());
+            let mut upper = PAGE_SIZE;
+            for (i, node) in nodes.iter().enumerate() {
+                upper -= node.len() + node.len() % 2;
+                page[upper..upper + node.len()].copy_from_slice(node);
+                let pointer = PAGE_HEADER + i * 2;
+                page[pointer..pointer + 2].copy_from_slice(&(upper as u16).to_le_bytes());
+            }
+            let lower = PAGE_HEADER + nodes.len() * 2;
+            page[12..14].copy_from_slice(&(lower as u16).to_le_bytes());
+            page[14..16].copy_from_slice(&(upper as u16).to_le_bytes());
+            self.0.push(page);
+            page_number
+        }
+
+        /// Add a value on as many overflow pages as it needs, returning the first page number.
+        fn push_overflow(&mut self, value: &[u8]) -> u64 {
+            let page_number = self.next_number();
+            let count = (PAGE_HEADER + value.len()).div_ceil(PAGE_SIZE);
+            let mut data = vec![0u8; count * PAGE_SIZE];
+            data[0..8].copy_from_slice(&page_number.to_le_bytes());
+            data[10..12].copy_from_slice(&0x04u16.to_le_bytes());
+            data[12..16].copy_from_slice(&(count as u32).to_le_bytes());
+            data[PAGE_HEADER..PAGE_HEADER + value.len()].copy_from_slice(value);
+            self.0
+                .extend(data.chunks(PAGE_SIZE).map(|page| page.to_vec()));
+            page_number
+        }
+
+        /// Add the entries of a table, returning its root page.
+        fn push_tree(&mut self, mut entries: Vec<(Vec<u8>, Vec<u8>, u16)>, layout: &Layout) -> u64 {
+            entries.sort();
+            if entries.is_empty() {
+                return self.push_nodes(0x02, &[]);
+            }
+
+            // The first key and page number of each page on the current level of the tree.
+            let mut level = vec![];
+            for chunk in entries.chunks(layout.nodes_per_page) {
+                let mut nodes = vec![];
+                for (key, value, flags) in chunk {
+                    nodes.push(if value.len() > layout.max_inline_value {
+                        let overflow = self.push_overflow(value);
+                        node(
+                            value.len() as u64,
+                            flags | 0x01,
+                            key,
+                            &overflow.to_le_bytes(),
+                        )
+                    } else {
+                        node(value.len() as u64, *flags, key, value)
+                    });
+                }
+                level.push((chunk[0].0.to_owned(), self.push_nodes(0x02, &nodes)));
+            }
+
+            while level.len() > 1 {
+                let mut parents = vec![];
+                for chunk in level.chunks(layout.nodes_per_page.max(2)) {
+                    let nodes: Vec<_> = chunk
+                        .iter()
+                        .enumerate()
+                        .map(|(i, (key, child))| {
+                            // The first key of a branch page is left out.
+                            let key: &[u8] = if i == 0 { &[] } else { key };
+                            node(*child, (*child >> 32) as u16, key, &[])
+                        })
+                        .collect();
+                    parents.push((chunk[0].0.to_owned(), self.push_nodes(0x01, &nodes)));
+                }
+                level = parents;
+            }
+            level[0].1
+        }
+    }
+
+    /// A node starts with the data size of a leaf or the child page of a branch, the flags of a
+    /// leaf, or the top bits of the child page of a branch, and the key size.
+    fn node(size: u64, flags: u16, key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut node = vec![];
+        node.extend_from_slice(&(size as u16).to_le_bytes());
+        node.extend_from_slice(&((size >> 16) as u16).to_le_bytes());
+        node.extend_from_slice(&flags.to_le_bytes());
+        node.extend_from_slice(&(key.len() as u16).to_le_bytes());
+        node.extend_from_slice(key);
+        node.extend_from_slice(data);
+        node
+    }
+
+    fn db(entries: usize, root: u64) -> Vec<u8> {
+        let mut db = vec![0u8; 8 + 8 * 3];
+        db.extend_from_slice(&(entries as u64).to_le_bytes());
+        db.extend_from_slice(&root.to_le_bytes());
+        db
+    }
+
+    fn write_lmdb(path: &Path, tables: Vec<Table>, layout: &Layout) {
+        let mut pages = Pages::default();
+        let mut named = vec![];
+        for (name, entries) in tables {
+            let len = entries.len();
+            let entries = entries.into_iter().map(|(k, v)| (k, v, 0)).collect();
+            let root = pages.push_tree(entries, layout);
+            named.push((name.as_bytes().to_vec(), db(len, root), 0x02));
+        }
+        let main_entries = named.len();
+        let main_root = pages.push_tree(named, layout);
+        write_file(path, pages, main_root, main_entries);
+    }
+
+    fn write_file(path: &Path, pages: Pages, main_root: u64, main_entries: usize) {
+        let mut file = std::fs::File::create(path).unwrap();
+        for transaction in 0..2u64 {
+            let mut meta = vec![0u8; PAGE_SIZE];
+            meta[0..8].copy_from_slice(&transaction.to_le_bytes());
+            meta[10..12].copy_from_slice(&0x08u16.to_le_bytes());
+            meta[16..20].copy_from_slice(&0xBEEF_C0DEu32.to_le_bytes());
+            meta[20..24].copy_from_slice(&1u32.to_le_bytes());
+            let mut free = db(0, !0);
+            free[0..4].copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+            meta[40..88].copy_from_slice(&free);
+            meta[88..136].copy_from_slice(&db(main_entries, main_root));
+            meta[136..144].copy_from_slice(&(pages.next_number() - 1).to_le_bytes());
+            meta[144..152].copy_from_slice(&transaction.to_le_bytes());
+            file.write_all(&meta).unwrap();
+        }
+        for page in pages.0 {
+            file.write_all(&page).unwrap();
+        }
+    }
+
+    fn block_entry(holder: &BlockHolder, sideband: &[&[u8]]) -> (Vec<u8>, Vec<u8>) {
+        let mut value = vec![holder.block_type().as_u8()];
+        value.extend_from_slice(&Wire::serialize(holder));
+        for part in sideband {
+            value.extend_from_slice(part);
+        }
+        (holder.hash().as_bytes().to_vec(), value)
+    }
+
+    fn signed(mut holder: BlockHolder) -> BlockHolder {
+        let signature = Seed::random()
+            .derive(0)
+            .sign(holder.hash().as_bytes())
+            .unwrap();
+        match &mut holder {
+            BlockHolder::Send(b) => {
+                b.signature = Some(signature);
+                b.work = Some(Work::zero());
+            }
+            BlockHolder::State(b) => {
+                b.signature = Some(signature);
+                b.work = Some(Work::zero());
+            }
+            _ => unreachable!(),
+        }
+        holder
+    }
+
+    /// The genesis account sends to B, which B receives with a state block, then sends to C which
+    /// is still pending.
+    #[tokio::test]
+    async fn import() {
+        import_with_layout(&SINGLE_PAGE).await;
+    }
+
+    /// The same ledger, with the tables spread over branch pages and every block on overflow
+    /// pages, like in a real ledger.
+    #[tokio::test]
+    async fn import_multi_page() {
+        import_with_layout(&Layout {
+            nodes_per_page: 1,
+            max_inline_value: 100,
+        })
+        .await;
+    }
+
+    async fn import_with_layout(layout: &Layout) {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let genesis_hash = genesis.hash().unwrap().to_owned();
+        let genesis_account = genesis.account().to_owned();
+        let b = Seed::random().derive(1).to_public().unwrap();
+        let c = Seed::random().derive(2).to_public().unwrap();
+        let timestamp = 0u64.to_be_bytes();
+
+        let send_b = signed(BlockHolder::Send(SendBlock::new(
+            genesis_hash.to_owned(),
+            b.to_owned(),
+            Rai::from(u128::MAX - 100),
+        )));
+        let send_c = signed(BlockHolder::Send(SendBlock::new(
+            send_b.hash(),
+            c.to_owned(),
+            Rai::from(u128::MAX - 150),
+        )));
+        let open_b = signed(BlockHolder::State(StateBlock::new(
+            b.to_owned(),
+            BlockHash::zero(),
+            b.to_owned(),
+            Rai::from(100),
+            Link::Source(send_b.hash()),
+        )));
+
+        let blocks = vec![
+            block_entry(
+                &genesis.to_holder().unwrap(),
+                &[send_b.hash().as_bytes(), &Rai::max().to_vec(), &timestamp],
+            ),
+            block_entry(
+                &send_b,
+                &[
+                    send_c.hash().as_bytes(),
+                    genesis_account.as_bytes(),
+                    &2u64.to_be_bytes(),
+                    &timestamp,
+                ],
+            ),
+            block_entry(
+                &send_c,
+                &[
+                    BlockHash::zero().as_bytes(),
+                    genesis_account.as_bytes(),
+                    &3u64.to_be_bytes(),
+                    &timestamp,
+                ],
+            ),
+            block_entry(
+                &open_b,
+                &[
+                    BlockHash::zero().as_bytes(),
+                    &1u64.to_be_bytes(),
+                    &timestamp,
+                    // A receive in epoch 1.
+                    &[0b0100_0011, 2],
+                ],
+            ),
+        ];
+
+        let account_info = |head: &BlockHash, representative: &Public, open: &BlockHash| {
+            let mut info = vec![];
+            info.extend_from_slice(head.as_bytes());
+            info.extend_from_slice(representative.as_bytes());
+            info.extend_from_slice(open.as_bytes());
+            // Balance, modified, block count and epoch aren't used.
+            info.extend_from_slice(&[0u8; 16 + 8 + 8 + 1]);
+            info
+        };
+        let accounts = vec![
+            (
+                genesis_account.as_bytes().to_vec(),
+                account_info(&send_c.hash(), &genesis_account, &genesis_hash),
+            ),
+            (
+                b.as_bytes().to_vec(),
+                account_info(&open_b.hash(), &b, &open_b.hash()),
+            ),
+        ];
+
+        let mut pending_key = c.as_bytes().to_vec();
+        pending_key.extend_from_slice(send_c.hash().as_bytes());
+        let mut pending_value = genesis_account.as_bytes().to_vec();
+        pending_value.extend_from_slice(&Rai::from(50).to_vec());
+        pending_value.push(0);
+
+        let mut height = 1u64.to_le_bytes().to_vec();
+        height.extend_from_slice(genesis_hash.as_bytes());
+
+        let path = std::env::temp_dir().join(format!("feeless-test-{}.ldb", rand::random::<u64>()));
+        write_lmdb(
+            &path,
+            vec![
+                ("accounts", accounts),
+                ("blocks", blocks),
+                ("pending", vec![(pending_key, pending_value)]),
+                (
+                    "confirmation_height",
+                    vec![(genesis_account.as_bytes().to_vec(), height)],
+                ),
+            ],
+            layout,
+        );
+
+        let state: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        let summary = import_ldb(&path, network, &state).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                accounts: 2,
+                blocks: 4,
+                pending: 1,
+                confirmation_heights: 1,
+            }
+        );
+
+        let state = state.lock().await;
+        assert_eq!(
+            state
+                .get_latest_block_hash_for_account(&genesis_account)
+                .await
+                .unwrap(),
+            Some(send_c.hash())
+        );
+        let send = state
+            .get_block_by_hash(&send_b.hash())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(send.account(), &genesis_account);
+        assert_eq!(send.representative(), genesis.representative());
+        let open = state
+            .get_block_by_hash(&open_b.hash())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(open.link(), &Link::Source(send_b.hash()));
+        assert_eq!(
+            state.block_epoch(&open_b.hash()).await.unwrap(),
+            Some(Epoch::V1)
+        );
+        assert_eq!(
+            state.block_epoch(&send_b.hash()).await.unwrap(),
+            Some(Epoch::V0)
+        );
+        assert_eq!(
+            state.block_hash_receiving(&send_b.hash()).await.unwrap(),
+            Some(open_b.hash())
+        );
+        assert_eq!(
+            state.rep_weight(&genesis_account).await.unwrap(),
+            Rai::from(u128::MAX - 150)
+        );
+        assert_eq!(state.rep_weight(&b).await.unwrap(), Rai::from(100));
+        assert_eq!(
+            state.pending(&c, &Rai::zero()).await.unwrap()[0].amount,
+            Rai::from(50)
+        );
+        assert_eq!(
+            state
+                .confirmation_height(&genesis_account)
+                .await
+                .unwrap()
+                .unwrap()
+                .height,
+            1
+        );
+    }
+
+    /// The sideband of the genesis block says it's followed by itself, which would never reach
+    /// the head of the account.
+    #[tokio::test]
+    async fn successor_loop() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let genesis_hash = genesis.hash().unwrap().to_owned();
+        let genesis_account = genesis.account().to_owned();
+        let blocks = vec![block_entry(
+            &genesis.to_holder().unwrap(),
+            &[
+                genesis_hash.as_bytes(),
+                &Rai::max().to_vec(),
+                &0u64.to_be_bytes(),
+            ],
+        )];
+        let mut info = vec![1u8; BlockHash::LEN];
+        info.extend_from_slice(genesis_account.as_bytes());
+        info.extend_from_slice(genesis_hash.as_bytes());
+        info.extend_from_slice(&[0u8; 16 + 8 + 8 + 1]);
+
+        let path = std::env::temp_dir().join(format!("feeless-test-{}.ldb", rand::random::<u64>()));
+        write_lmdb(
+            &path,
+            vec![
+                (
+                    "accounts",
+                    vec![(genesis_account.as_bytes().to_vec(), info)],
+                ),
+                ("blocks", blocks),
+                ("pending", vec![]),
+                ("confirmation_height", vec![]),
+            ],
+            &SINGLE_PAGE,
+        );
+        let state: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        let err = import_ldb(&path, network, &state).await.unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(format!("{:?}", err).contains("instead of"));
+    }
+
+    /// A branch page that points back at itself.
+    #[test]
+    fn branch_loop() {
+        let mut pages = Pages::default();
+        let page_number = pages.next_number();
+        pages.push_nodes(0x01, &[node(page_number, 0, &[], &[])]);
+        let path = std::env::temp_dir().join(format!("feeless-test-{}.ldb", rand::random::<u64>()));
+        write_file(&path, pages, page_number, 1);
+
+        let mut lmdb = Lmdb::open(&path).unwrap();
+        let err = lmdb.db("blocks").unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(format!("{:?}", err).contains("deeper"));
+    }
+}
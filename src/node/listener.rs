@@ -0,0 +1,134 @@
+use crate::network::Network;
+use crate::node::channel::network_channel;
+use crate::node::controller::Flood;
+use crate::node::state::ArcState;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, Semaphore};
+use tracing::{debug, info, warn};
+
+/// Accept peers connecting to us, each getting their own `network_channel`.
+///
+/// The peer speaks first, so it can use the connection for either realtime messages or a
+/// bootstrap. Connections over `max_inbound` are dropped straight away.
+pub async fn listen(
+    network: Network,
+    state: ArcState,
+    listener: TcpListener,
+    max_inbound: usize,
+    flood: broadcast::Sender<Flood>,
+) -> anyhow::Result<()> {
+    info!("Listening for peers on {:?}", listener.local_addr()?);
+    let slots = Arc::new(Semaphore::new(max_inbound));
+
+    loop {
+        let (stream, socket_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                warn!("Could not accept a peer: {:?}", err);
+                continue;
+            }
+        };
+
+        let slot = match slots.clone().try_acquire_owned() {
+            Ok(slot) => slot,
+            Err(_) => {
+                debug!(
+                    "Dropping {:?}, already at {} inbound connections",
+                    socket_addr, max_inbound
+                );
+                continue;
+            }
+        };
+
+        info!("Accepted a channel from {:?}", socket_addr);
+        let state = state.clone();
+        let flood = flood.clone();
+        tokio::spawn(async move {
+            if let Err(err) = network_channel(network, state, stream, false, true, flood).await {
+                warn!("Inbound channel from {:?} closed: {:?}", socket_addr, err);
+            }
+            drop(slot);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::controller::{Controller, FLOOD_CAPACITY};
+    use crate::node::cookie::Cookie;
+    use crate::node::header::{Extensions, Header, MessageType};
+    use crate::node::messages::frontier_req::FrontierReq;
+    use crate::node::messages::frontier_resp::FrontierResp;
+    use crate::node::messages::handshake::HandshakeQuery;
+    use crate::node::state::MemoryState;
+    use crate::node::wire::Wire;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::Mutex;
+
+    async fn start(network: Network, max_inbound: usize) -> SocketAddr {
+        let state: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (mut controller, _, _) = Controller::new_with_channels(network, state.clone(), addr);
+        controller.init().await.unwrap();
+        let (flood, _) = broadcast::channel(FLOOD_CAPACITY);
+        tokio::spawn(listen(network, state, listener, max_inbound, flood));
+        addr
+    }
+
+    #[tokio::test]
+    async fn inbound_cap() {
+        let network = Network::Live;
+        let addr = start(network, 1).await;
+
+        // The first peer gets a response to its handshake.
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        let header = Header::new(network, MessageType::Handshake, *Extensions::new().query());
+        first.write_all(&header.serialize()).await.unwrap();
+        first
+            .write_all(&HandshakeQuery::new(Cookie::random()).serialize())
+            .await
+            .unwrap();
+        let mut buffer = [0u8; Header::LEN];
+        first.read_exact(&mut buffer).await.unwrap();
+        let header = Header::deserialize(None, &buffer).unwrap();
+        assert_eq!(header.message_type(), MessageType::Handshake);
+        assert!(header.ext().is_response());
+
+        // The second peer goes over the cap and is disconnected.
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        let read = tokio::time::timeout(Duration::from_secs(5), second.read(&mut buffer))
+            .await
+            .unwrap();
+        assert!(matches!(read, Ok(0) | Err(_)));
+    }
+
+    /// A bootstrapping peer only gets the responses to its own requests, without a handshake.
+    #[tokio::test]
+    async fn frontier_req_first() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let addr = start(network, 1).await;
+
+        let mut peer = TcpStream::connect(addr).await.unwrap();
+        let header = Header::new(network, MessageType::FrontierReq, Extensions::new());
+        peer.write_all(&header.serialize()).await.unwrap();
+        peer.write_all(&FrontierReq::all().serialize())
+            .await
+            .unwrap();
+
+        let mut buffer = [0u8; FrontierResp::LEN];
+        peer.read_exact(&mut buffer).await.unwrap();
+        let frontier = FrontierResp::deserialize(None, &buffer).unwrap();
+        assert_eq!(&frontier.account, genesis.account());
+        assert_eq!(&frontier.frontier_hash, genesis.hash().unwrap());
+
+        peer.read_exact(&mut buffer).await.unwrap();
+        assert!(FrontierResp::deserialize(None, &buffer).unwrap().is_end());
+    }
+}
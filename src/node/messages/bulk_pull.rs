@@ -0,0 +1,137 @@
+use crate::blocks::BlockHash;
+use crate::bytes::Bytes;
+use crate::node::header::Header;
+use crate::node::wire::Wire;
+use crate::Public;
+use anyhow::{anyhow, Context};
+use std::convert::TryFrom;
+
+/// Requests a chain of blocks, starting at `start` and going backwards until `end`.
+///
+/// The response is a stream of blocks without headers. Each block is prefixed with its block type,
+/// and the stream ends with a `NotABlock` block type.
+#[derive(Debug)]
+pub struct BulkPull {
+    /// Either an account or a block hash. When it's an account, the account's frontier is used.
+    pub start: BlockHash,
+
+    /// The last block to stop at, which is not sent. Zero means to go back to the open block.
+    pub end: BlockHash,
+
+    /// Maximum number of blocks to send. Only present when the header extension is set.
+    pub count: Option<u32>,
+}
+
+impl BulkPull {
+    pub const LEN: usize = BlockHash::LEN * 2;
+    pub const EXTENDED_LEN: usize = 8;
+
+    pub fn new(start: BlockHash, end: BlockHash, count: Option<u32>) -> Self {
+        Self { start, end, count }
+    }
+
+    /// Pull an account's chain from its frontier back to `end`.
+    pub fn account(account: &Public, end: BlockHash) -> Self {
+        // This unwrap is safe since an account is the same length as a block hash.
+        let start = BlockHash::try_from(account.as_bytes()).unwrap();
+        Self::new(start, end, None)
+    }
+
+    /// The `start` field interpreted as an account.
+    pub fn start_account(&self) -> Public {
+        // This unwrap is safe since an account is the same length as a block hash.
+        Public::try_from(self.start.as_bytes()).unwrap()
+    }
+}
+
+impl Wire for BulkPull {
+    fn serialize(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(Self::LEN + Self::EXTENDED_LEN);
+        v.extend_from_slice(self.start.as_bytes());
+        v.extend_from_slice(self.end.as_bytes());
+        if let Some(count) = self.count {
+            // The first byte is always zero, followed by the count, then three reserved bytes.
+            v.push(0);
+            v.extend_from_slice(&count.to_le_bytes());
+            v.extend_from_slice(&[0u8; 3]);
+        }
+        v
+    }
+
+    fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        debug_assert!(header.is_some());
+        let header = header.unwrap();
+        let context = || "Deserialize bulk pull";
+
+        let mut bytes = Bytes::new(data);
+        let start = BlockHash::try_from(bytes.slice(BlockHash::LEN)?).with_context(context)?;
+        let end = BlockHash::try_from(bytes.slice(BlockHash::LEN)?).with_context(context)?;
+
+        let count = if header.ext().is_count_present() {
+            if bytes.u8()? != 0 {
+                return Err(anyhow!("Extended parameters have a non-zero first byte"))
+                    .with_context(context);
+            }
+            let mut s32 = [0u8; 4];
+            s32.copy_from_slice(bytes.slice(4)?);
+            Some(u32::from_le_bytes(s32))
+        } else {
+            None
+        };
+
+        Ok(Self { start, end, count })
+    }
+
+    fn len(header: Option<&Header>) -> anyhow::Result<usize>
+    where
+        Self: Sized,
+    {
+        debug_assert!(header.is_some());
+        let header = header.unwrap();
+
+        if header.ext().is_count_present() {
+            Ok(Self::LEN + Self::EXTENDED_LEN)
+        } else {
+            Ok(Self::LEN)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Network;
+    use crate::node::header::{Extensions, MessageType};
+    use std::str::FromStr;
+
+    #[test]
+    fn serialize() {
+        let start =
+            BlockHash::from_str("991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948")
+                .unwrap();
+
+        let header = Header::new(Network::Live, MessageType::BulkPull, Extensions::new());
+        let bulk_pull = BulkPull::new(start.clone(), BlockHash::zero(), None);
+        let data = bulk_pull.serialize();
+        assert_eq!(data.len(), BulkPull::len(Some(&header)).unwrap());
+        let decoded = BulkPull::deserialize(Some(&header), &data).unwrap();
+        assert_eq!(decoded.start, start);
+        assert_eq!(decoded.end, BlockHash::zero());
+        assert_eq!(decoded.count, None);
+
+        let header = Header::new(
+            Network::Live,
+            MessageType::BulkPull,
+            *Extensions::new().count_present(),
+        );
+        let bulk_pull = BulkPull::new(start, BlockHash::zero(), Some(1000));
+        let data = bulk_pull.serialize();
+        assert_eq!(data.len(), BulkPull::len(Some(&header)).unwrap());
+        assert_eq!(&data[64..], &[0, 0xe8, 0x03, 0, 0, 0, 0, 0]);
+        let decoded = BulkPull::deserialize(Some(&header), &data).unwrap();
+        assert_eq!(decoded.count, Some(1000));
+    }
+}
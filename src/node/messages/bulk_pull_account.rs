@@ -0,0 +1,312 @@
+use crate::blocks::BlockHash;
+use crate::bytes::Bytes;
+use crate::node::header::Header;
+use crate::node::wire::Wire;
+use crate::{Public, Rai};
+use anyhow::anyhow;
+use std::convert::TryFrom;
+
+/// Requests the frontier and balance of an account, followed by its pending receivable sends.
+///
+/// The response has no header. It starts with a [BulkPullAccountFrontier], followed by a
+/// [PendingEntry] for each receivable send, ending with an all zero entry. The layout of the
+/// entries depends on the [BulkPullAccountFlags] of the request.
+#[derive(Debug)]
+pub struct BulkPullAccount {
+    pub account: Public,
+
+    /// Pending sends with a smaller amount than this are not included.
+    pub minimum_amount: Rai,
+
+    pub flags: BulkPullAccountFlags,
+}
+
+impl BulkPullAccount {
+    pub const LEN: usize = Public::LEN + Rai::LEN + 1;
+
+    pub fn new(account: Public, minimum_amount: Rai, flags: BulkPullAccountFlags) -> Self {
+        Self {
+            account,
+            minimum_amount,
+            flags,
+        }
+    }
+}
+
+impl Wire for BulkPullAccount {
+    fn serialize(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.account.as_bytes());
+        v.extend_from_slice(&self.minimum_amount.to_vec());
+        v.push(self.flags as u8);
+        v
+    }
+
+    fn deserialize(_: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut bytes = Bytes::new(data);
+        let account = Public::try_from(bytes.slice(Public::LEN)?)?;
+        let minimum_amount = Rai::try_from(bytes.slice(Rai::LEN)?)?;
+        let flags = BulkPullAccountFlags::try_from(bytes.u8()?)?;
+        Ok(Self {
+            account,
+            minimum_amount,
+            flags,
+        })
+    }
+
+    fn len(_: Option<&Header>) -> anyhow::Result<usize>
+    where
+        Self: Sized,
+    {
+        Ok(Self::LEN)
+    }
+}
+
+/// Which fields each [PendingEntry] of the response contains.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(u8)]
+pub enum BulkPullAccountFlags {
+    HashAndAmount = 0,
+    AddressOnly = 1,
+    HashAmountAndAddress = 2,
+}
+
+impl BulkPullAccountFlags {
+    pub fn has_hash_and_amount(&self) -> bool {
+        *self != Self::AddressOnly
+    }
+
+    pub fn has_address(&self) -> bool {
+        *self != Self::HashAndAmount
+    }
+
+    /// The length of each [PendingEntry] in the response.
+    pub fn entry_len(&self) -> usize {
+        let mut len = 0;
+        if self.has_hash_and_amount() {
+            len += BlockHash::LEN + Rai::LEN;
+        }
+        if self.has_address() {
+            len += Public::LEN;
+        }
+        len
+    }
+}
+
+impl TryFrom<u8> for BulkPullAccountFlags {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        use BulkPullAccountFlags::*;
+        Ok(match value {
+            0 => HashAndAmount,
+            1 => AddressOnly,
+            2 => HashAmountAndAddress,
+            _ => return Err(anyhow!("Unknown bulk pull account flags: {}", value)),
+        })
+    }
+}
+
+/// The first part of a [BulkPullAccount] response. Both fields are zero if the account is unknown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkPullAccountFrontier {
+    pub frontier_hash: BlockHash,
+    pub balance: Rai,
+}
+
+impl BulkPullAccountFrontier {
+    pub const LEN: usize = BlockHash::LEN + Rai::LEN;
+
+    pub fn new(frontier_hash: BlockHash, balance: Rai) -> Self {
+        Self {
+            frontier_hash,
+            balance,
+        }
+    }
+}
+
+impl Wire for BulkPullAccountFrontier {
+    fn serialize(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.frontier_hash.as_bytes());
+        v.extend_from_slice(&self.balance.to_vec());
+        v
+    }
+
+    fn deserialize(_: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut bytes = Bytes::new(data);
+        let frontier_hash = BlockHash::try_from(bytes.slice(BlockHash::LEN)?)?;
+        let balance = Rai::try_from(bytes.slice(Rai::LEN)?)?;
+        Ok(Self {
+            frontier_hash,
+            balance,
+        })
+    }
+
+    fn len(_: Option<&Header>) -> anyhow::Result<usize>
+    where
+        Self: Sized,
+    {
+        Ok(Self::LEN)
+    }
+}
+
+/// A complete [BulkPullAccount] response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkPullAccountResponse {
+    pub account: Public,
+    pub frontier: BulkPullAccountFrontier,
+    pub pending: Vec<PendingEntry>,
+}
+
+/// A pending receivable send of a [BulkPullAccount] response.
+///
+/// Which fields are present depends on the [BulkPullAccountFlags] of the request, so this doesn't
+/// implement [Wire].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    /// The hash and amount of the send block.
+    pub hash_and_amount: Option<(BlockHash, Rai)>,
+
+    /// The account that sent the amount.
+    pub source: Option<Public>,
+}
+
+impl PendingEntry {
+    /// Marks the end of the response.
+    pub fn end(flags: BulkPullAccountFlags) -> Self {
+        Self {
+            hash_and_amount: if flags.has_hash_and_amount() {
+                Some((BlockHash::zero(), Rai::zero()))
+            } else {
+                None
+            },
+            source: if flags.has_address() {
+                Some(Public::zero())
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn is_end(&self) -> bool {
+        let hash_is_zero = match &self.hash_and_amount {
+            Some((hash, _)) => hash == &BlockHash::zero(),
+            None => true,
+        };
+        let source_is_zero = match &self.source {
+            Some(source) => source == &Public::zero(),
+            None => true,
+        };
+        hash_is_zero && source_is_zero
+    }
+
+    pub fn serialize(&self, flags: BulkPullAccountFlags) -> Vec<u8> {
+        let mut v = Vec::with_capacity(flags.entry_len());
+        if flags.has_hash_and_amount() {
+            let (hash, amount) = self
+                .hash_and_amount
+                .to_owned()
+                .unwrap_or_else(|| (BlockHash::zero(), Rai::zero()));
+            v.extend_from_slice(hash.as_bytes());
+            v.extend_from_slice(&amount.to_vec());
+        }
+        if flags.has_address() {
+            let source = self.source.to_owned().unwrap_or_else(Public::zero);
+            v.extend_from_slice(source.as_bytes());
+        }
+        v
+    }
+
+    pub fn deserialize(flags: BulkPullAccountFlags, data: &[u8]) -> anyhow::Result<Self> {
+        let mut bytes = Bytes::new(data);
+        let hash_and_amount = if flags.has_hash_and_amount() {
+            let hash = BlockHash::try_from(bytes.slice(BlockHash::LEN)?)?;
+            let amount = Rai::try_from(bytes.slice(Rai::LEN)?)?;
+            Some((hash, amount))
+        } else {
+            None
+        };
+        let source = if flags.has_address() {
+            Some(Public::try_from(bytes.slice(Public::LEN)?)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            hash_and_amount,
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn serialize() {
+        let account =
+            Public::from_str("E89208DD038FBB269987689621D52292AE9C35941A7484756ECCED92A65093BA")
+                .unwrap();
+        let req = BulkPullAccount::new(
+            account.clone(),
+            Rai::from(1000),
+            BulkPullAccountFlags::HashAmountAndAddress,
+        );
+        let data = req.serialize();
+        assert_eq!(data.len(), BulkPullAccount::LEN);
+        assert_eq!(data[48], 2);
+        let decoded = BulkPullAccount::deserialize(None, &data).unwrap();
+        assert_eq!(decoded.account, account);
+        assert_eq!(decoded.minimum_amount, Rai::from(1000));
+        assert_eq!(decoded.flags, BulkPullAccountFlags::HashAmountAndAddress);
+    }
+
+    #[test]
+    fn pending_entries() {
+        let hash =
+            BlockHash::from_str("991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948")
+                .unwrap();
+        let source =
+            Public::from_str("E89208DD038FBB269987689621D52292AE9C35941A7484756ECCED92A65093BA")
+                .unwrap();
+
+        let flags = [
+            (BulkPullAccountFlags::HashAndAmount, 48),
+            (BulkPullAccountFlags::AddressOnly, 32),
+            (BulkPullAccountFlags::HashAmountAndAddress, 80),
+        ];
+        for (flags, len) in flags.iter() {
+            assert_eq!(flags.entry_len(), *len);
+
+            let entry = PendingEntry {
+                hash_and_amount: if flags.has_hash_and_amount() {
+                    Some((hash.clone(), Rai::from(5)))
+                } else {
+                    None
+                },
+                source: if flags.has_address() {
+                    Some(source.clone())
+                } else {
+                    None
+                },
+            };
+            let data = entry.serialize(*flags);
+            assert_eq!(data.len(), *len);
+            let decoded = PendingEntry::deserialize(*flags, &data).unwrap();
+            assert_eq!(decoded, entry);
+            assert!(!decoded.is_end());
+
+            let end = PendingEntry::end(*flags).serialize(*flags);
+            assert_eq!(end, vec![0u8; *len]);
+            assert!(PendingEntry::deserialize(*flags, &end).unwrap().is_end());
+        }
+    }
+}
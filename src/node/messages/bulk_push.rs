@@ -0,0 +1,27 @@
+use crate::node::header::Header;
+use crate::node::wire::Wire;
+
+/// Pushes blocks to a peer that is behind.
+///
+/// There is no payload. The header is followed by a stream of blocks without headers, the same as
+/// a `BulkPull` response: each block is prefixed with its block type, and the stream ends with a
+/// `NotABlock` block type.
+#[derive(Debug)]
+pub struct BulkPush;
+
+impl Wire for BulkPush {
+    fn serialize(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn deserialize(_: Option<&Header>, _data: &[u8]) -> Result<Self, anyhow::Error>
+    where
+        Self: Sized,
+    {
+        Ok(Self {})
+    }
+
+    fn len(_: Option<&Header>) -> Result<usize, anyhow::Error> {
+        Ok(0)
+    }
+}
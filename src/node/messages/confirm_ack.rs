@@ -1,7 +1,7 @@
-use crate::blocks::{Block, BlockHash, BlockType};
+use crate::blocks::{BlockHash, BlockHolder, BlockType};
 use crate::bytes::Bytes;
 use crate::encoding::blake2b;
-use crate::node::header::Header;
+use crate::node::header::{Extensions, Header};
 use crate::node::timestamp::Timestamp;
 use crate::node::wire::Wire;
 use crate::{Public, Signature};
@@ -22,8 +22,18 @@ pub struct ConfirmAck {
 pub enum Confirm {
     VoteByHash(Vec<BlockHash>),
 
-    // TODO: This looks like it isn't used on the live network.
-    Block(Block),
+    /// A vote for a single block, sent in full. Older nodes still send these.
+    Block(BlockHolder),
+}
+
+impl Confirm {
+    /// The hashes of the blocks being voted for.
+    pub fn hashes(&self) -> Vec<BlockHash> {
+        match self {
+            Confirm::VoteByHash(hashes) => hashes.to_owned(),
+            Confirm::Block(block) => vec![block.hash()],
+        }
+    }
 }
 
 impl ConfirmAck {
@@ -53,25 +63,49 @@ impl ConfirmAck {
     pub fn inner_hash(&self) -> Vec<u8> {
         let mut v = Vec::new();
 
-        // TODO: Only add this prefix if there's data. See nano::vote::hash()
-        v.extend_from_slice("vote ".as_bytes());
+        // The prefix is only used for votes by hash, or votes with more than one block.
+        let has_prefix = match &self.confirm {
+            Confirm::VoteByHash(hashes) => !hashes.is_empty(),
+            Confirm::Block(_) => false,
+        };
+        if has_prefix {
+            v.extend_from_slice("vote ".as_bytes());
+        }
 
-        if let Confirm::VoteByHash(hashes) = &self.confirm {
-            for hash in hashes {
-                v.extend_from_slice(hash.as_bytes())
-            }
-            v.extend_from_slice(&self.timestamp.to_bytes())
-        } else {
-            todo!("handle block hash");
+        for hash in self.confirm.hashes() {
+            v.extend_from_slice(hash.as_bytes())
         }
+        v.extend_from_slice(&self.timestamp.to_bytes());
 
         blake2b(BlockHash::LEN, &v).to_vec()
     }
+
+    /// The header extensions needed to send this message.
+    pub fn extensions(&self) -> Extensions {
+        match &self.confirm {
+            Confirm::VoteByHash(hashes) => *Extensions::new()
+                .set_block_type(&BlockType::NotABlock)
+                .set_item_count(hashes.len()),
+            Confirm::Block(block) => *Extensions::new().set_block_type(&block.block_type()),
+        }
+    }
 }
 
 impl Wire for ConfirmAck {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        let mut v = Vec::with_capacity(Self::VOTE_COMMON_LEN);
+        v.extend_from_slice(self.account.as_bytes());
+        v.extend_from_slice(self.signature.as_bytes());
+        v.extend_from_slice(&self.timestamp.to_bytes());
+        match &self.confirm {
+            Confirm::VoteByHash(hashes) => {
+                for hash in hashes {
+                    v.extend_from_slice(hash.as_bytes());
+                }
+            }
+            Confirm::Block(block) => v.extend_from_slice(&block.serialize()),
+        }
+        v
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -94,9 +128,12 @@ impl Wire for ConfirmAck {
             }
             Confirm::VoteByHash(block_hashes)
         } else {
-            // let block = Block;
-            dbg!("block!!!!!!!", header.ext().block_type().unwrap());
-            todo!()
+            let block = BlockHolder::deserialize(
+                Some(header),
+                data.slice(BlockHolder::len(Some(header))?)?,
+            )
+            .context("Decoding vote by block")?;
+            Confirm::Block(block)
         };
 
         Ok(Self::new(account, signature, timestamp, confirm))
@@ -109,8 +146,7 @@ impl Wire for ConfirmAck {
         if header.ext().block_type()? == BlockType::NotABlock {
             Ok(Self::VOTE_COMMON_LEN + header.ext().item_count() * BlockHash::LEN)
         } else {
-            todo!("got a block in confirm ack {:#?}", header);
-            // Ok(Self::VOTE_COMMON_LEN + FullBlock::LEN)
+            Ok(Self::VOTE_COMMON_LEN + BlockHolder::len(Some(header))?)
         }
     }
 }
@@ -118,8 +154,37 @@ impl Wire for ConfirmAck {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::encoding::to_hex;
+    use crate::network::Network;
+    use crate::node::header::MessageType;
     use std::str::FromStr;
 
+    /// A vote by hash from the live network, for two blocks.
+    const VOTE_BY_HASH: &str = concat!(
+        "2994D330022A052DF83E10FCE1B3E140496CDCD7E0C0F2FF6DE2670291B88011",
+        "721C6CAFD61C2D7ED27643C556F77AE900308BD5AAF458E74310E42773BB4549",
+        "4A138EE0291B6868C360EB983AB5CE8FF2EFF6A66044CBA2B128047ACDBD4402",
+        "6B0E617800000000",
+        "C3A3FE56D584CB997199E3B09EC454F62DED3B7EF875D9D7E8E5011AC34C77A5",
+        "139E1064D7CCC26495EFB4030015C02CE78556EBE3547192843B0E71C91599FC",
+    );
+
+    #[test]
+    fn vote_by_hash_fixture() {
+        let ext = *Extensions::new()
+            .set_block_type(&BlockType::NotABlock)
+            .set_item_count(2);
+        let header = Header::new(Network::Live, MessageType::ConfirmAck, ext);
+        let data = hex::decode(VOTE_BY_HASH).unwrap();
+        assert_eq!(ConfirmAck::len(Some(&header)).unwrap(), data.len());
+
+        let confirm_ack = ConfirmAck::deserialize(Some(&header), &data).unwrap();
+        assert!(confirm_ack.verify_signature().is_ok());
+        assert_eq!(confirm_ack.timestamp, Timestamp::from_u64(2019626603));
+        assert_eq!(confirm_ack.extensions(), ext);
+        assert_eq!(to_hex(&confirm_ack.serialize()), VOTE_BY_HASH);
+    }
+
     #[test]
     fn verify_sig() {
         let account =
@@ -141,4 +206,46 @@ mod tests {
         );
         assert!(confirm_ack.verify_signature().is_ok());
     }
+
+    #[test]
+    fn vote_by_block() {
+        use crate::blocks::SendBlock;
+        use crate::{Rai, Seed};
+
+        let private = Seed::random().derive(0);
+        let account = private.to_public().unwrap();
+        let mut send = SendBlock::new(BlockHash::zero(), account.clone(), Rai::from(1));
+        send.signature = Some(Signature::zero());
+        send.work = Some(crate::Work::zero());
+        let block = BlockHolder::Send(send);
+        let timestamp = Timestamp::from_u64(1614200740266);
+
+        // A single block isn't prefixed when hashed.
+        let mut expected = block.hash().as_bytes().to_vec();
+        expected.extend_from_slice(&timestamp.to_bytes());
+        let expected = blake2b(BlockHash::LEN, &expected).to_vec();
+
+        let mut confirm_ack = ConfirmAck::new(
+            account,
+            Signature::zero(),
+            timestamp,
+            Confirm::Block(block.clone()),
+        );
+        assert_eq!(confirm_ack.inner_hash(), expected);
+        confirm_ack.signature = private.sign(&confirm_ack.inner_hash()).unwrap();
+
+        let ext = confirm_ack.extensions();
+        assert_eq!(ext.block_type().unwrap(), BlockType::Send);
+        let header = Header::new(Network::Live, MessageType::ConfirmAck, ext);
+        let data = confirm_ack.serialize();
+        assert_eq!(ConfirmAck::len(Some(&header)).unwrap(), data.len());
+
+        let decoded = ConfirmAck::deserialize(Some(&header), &data).unwrap();
+        assert!(decoded.verify_signature().is_ok());
+        assert_eq!(decoded.confirm.hashes(), vec![block.hash()]);
+        match decoded.confirm {
+            Confirm::Block(decoded_block) => assert_eq!(decoded_block, block),
+            _ => panic!("Expected a vote by block"),
+        }
+    }
 }
@@ -1,7 +1,7 @@
 use crate::blocks::{BlockHash, BlockHolder, BlockType};
 use crate::bytes::Bytes;
 use crate::expect_len;
-use crate::node::header::Header;
+use crate::node::header::{Extensions, Header};
 use crate::node::wire::Wire;
 use anyhow::Context;
 use std::convert::TryFrom;
@@ -24,11 +24,31 @@ pub enum ConfirmReq {
 
 impl ConfirmReq {
     pub const CONFIRM_REQ_BY_HASH_LEN: usize = BlockHash::LEN * 2;
+
+    /// The header extensions needed to send this message.
+    pub fn extensions(&self) -> Extensions {
+        match self {
+            Self::ConfirmReqByHash(pairs) => *Extensions::new()
+                .set_block_type(&BlockType::NotABlock)
+                .set_item_count(pairs.len()),
+            Self::BlockSelector(holder) => *Extensions::new().set_block_type(&holder.block_type()),
+        }
+    }
 }
 
 impl Wire for ConfirmReq {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        match self {
+            Self::ConfirmReqByHash(pairs) => {
+                let mut v = Vec::with_capacity(RootHashPair::LEN * pairs.len());
+                for pair in pairs {
+                    v.extend_from_slice(pair.hash.as_bytes());
+                    v.extend_from_slice(pair.root.as_bytes());
+                }
+                v
+            }
+            Self::BlockSelector(holder) => holder.serialize(),
+        }
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -111,3 +131,41 @@ impl TryFrom<&[u8]> for RootHashPair {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_hex;
+    use crate::network::Network;
+    use crate::node::header::MessageType;
+
+    /// The first send block from the genesis account, followed by its open block, as root hash pairs.
+    const BY_HASH: &str = concat!(
+        "A170D51B94E00371ACE76E35AC81DC9405D5D04D4CEBC399AEACE07AE05DD293",
+        "991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948",
+        "90D0C16AC92DD35814E84BFBCC739A039615D0A42A76EF44ADAEF1D99E9F8A35",
+        "059F68AAB29DE0D3A27443625C7EA9CDDB6517A8B76FE37727EF6A4D76832AD5",
+    );
+
+    #[test]
+    fn by_hash_fixture() {
+        let ext = *Extensions::new()
+            .set_block_type(&BlockType::NotABlock)
+            .set_item_count(2);
+        let header = Header::new(Network::Live, MessageType::ConfirmReq, ext);
+        let data = hex::decode(BY_HASH).unwrap();
+        assert_eq!(ConfirmReq::len(Some(&header)).unwrap(), data.len());
+
+        let confirm_req = ConfirmReq::deserialize(Some(&header), &data).unwrap();
+        match &confirm_req {
+            ConfirmReq::ConfirmReqByHash(pairs) => {
+                assert_eq!(pairs.len(), 2);
+                assert_eq!(pairs[0].hash.as_hex(), &BY_HASH[0..64]);
+                assert_eq!(pairs[0].root.as_hex(), &BY_HASH[64..128]);
+            }
+            _ => panic!("Expected root hash pairs"),
+        }
+        assert_eq!(confirm_req.extensions(), ext);
+        assert_eq!(to_hex(&confirm_req.serialize()), BY_HASH);
+    }
+}
@@ -4,20 +4,40 @@ use crate::node::wire::Wire;
 use crate::Public;
 use std::convert::TryFrom;
 
+/// Requests a list of accounts and their frontiers, which is responded with a stream of
+/// [FrontierResp](super::frontier_resp::FrontierResp) without headers.
 #[derive(Debug)]
 pub struct FrontierReq {
-    start: Public,
-    age: u32,
-    count: u32,
+    /// The account to start from, in ascending order.
+    pub start: Public,
+
+    /// Only accounts modified within this many seconds. `u32::MAX` for every account.
+    pub age: u32,
+
+    /// Maximum number of frontiers to respond with. `u32::MAX` for every account.
+    pub count: u32,
 }
 
 impl FrontierReq {
     pub const LEN: usize = 40;
+
+    pub fn new(start: Public, age: u32, count: u32) -> Self {
+        Self { start, age, count }
+    }
+
+    /// Request every frontier.
+    pub fn all() -> Self {
+        Self::new(Public::zero(), u32::MAX, u32::MAX)
+    }
 }
 
 impl Wire for FrontierReq {
     fn serialize(&self) -> Vec<u8> {
-        vec![]
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.start.as_bytes());
+        v.extend_from_slice(&self.age.to_le_bytes());
+        v.extend_from_slice(&self.count.to_le_bytes());
+        v
     }
 
     fn deserialize(_: Option<&Header>, data: &[u8]) -> Result<Self, anyhow::Error>
@@ -41,3 +61,25 @@ impl Wire for FrontierReq {
         Ok(Self::LEN)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn serialize() {
+        let start =
+            Public::from_str("E89208DD038FBB269987689621D52292AE9C35941A7484756ECCED92A65093BA")
+                .unwrap();
+        let frontier_req = FrontierReq::new(start.clone(), 3600, 1000);
+        let data = frontier_req.serialize();
+        assert_eq!(data.len(), FrontierReq::LEN);
+        assert_eq!(&data[32..], &[0x10, 0x0e, 0, 0, 0xe8, 0x03, 0, 0]);
+
+        let decoded = FrontierReq::deserialize(None, &data).unwrap();
+        assert_eq!(decoded.start, start);
+        assert_eq!(decoded.age, 3600);
+        assert_eq!(decoded.count, 1000);
+    }
+}
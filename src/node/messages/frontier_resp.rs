@@ -6,19 +6,41 @@ use crate::Public;
 use anyhow::Context;
 use std::convert::TryFrom;
 
+/// One account and its frontier, in response to a `FrontierReq`.
+///
+/// The stream of frontiers ends with a zero account and zero hash.
 #[derive(Debug)]
 pub struct FrontierResp {
-    account: Public,
-    frontier_hash: BlockHash,
+    pub account: Public,
+    pub frontier_hash: BlockHash,
 }
 
 impl FrontierResp {
     pub const LEN: usize = Public::LEN + BlockHash::LEN;
+
+    pub fn new(account: Public, frontier_hash: BlockHash) -> Self {
+        Self {
+            account,
+            frontier_hash,
+        }
+    }
+
+    /// The entry that signals the end of the frontier stream.
+    pub fn end() -> Self {
+        Self::new(Public::zero(), BlockHash::zero())
+    }
+
+    pub fn is_end(&self) -> bool {
+        self.account == Public::zero() && self.frontier_hash == BlockHash::zero()
+    }
 }
 
 impl Wire for FrontierResp {
     fn serialize(&self) -> Vec<u8> {
-        todo!()
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.account.as_bytes());
+        v.extend_from_slice(self.frontier_hash.as_bytes());
+        v
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> Result<Self, anyhow::Error>
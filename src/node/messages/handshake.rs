@@ -1,6 +1,6 @@
 use crate::bytes::Bytes;
 use crate::node::cookie::Cookie;
-use crate::node::header::Header;
+use crate::node::header::{Extensions, Header};
 use crate::node::wire::Wire;
 use crate::{Public, Signature};
 use std::convert::TryFrom;
@@ -11,9 +11,30 @@ pub struct Handshake {
     pub response: Option<HandshakeResponse>,
 }
 
+impl Handshake {
+    /// The header extensions needed to send this message.
+    pub fn extensions(&self) -> Extensions {
+        let mut ext = Extensions::new();
+        if self.query.is_some() {
+            ext.query();
+        }
+        if self.response.is_some() {
+            ext.response();
+        }
+        ext
+    }
+}
+
 impl Wire for Handshake {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        let mut v = Vec::with_capacity(HandshakeQuery::LEN + HandshakeResponse::LEN);
+        if let Some(query) = &self.query {
+            v.extend_from_slice(&query.serialize());
+        }
+        if let Some(response) = &self.response {
+            v.extend_from_slice(&response.serialize());
+        }
+        v
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> Result<Self, anyhow::Error>
@@ -102,6 +123,11 @@ impl HandshakeResponse {
     pub fn new(public: Public, signature: Signature) -> Self {
         Self { public, signature }
     }
+
+    /// Check that the node id signed `cookie`, which is the cookie we sent the peer in our query.
+    pub fn verify(&self, cookie: &Cookie) -> anyhow::Result<()> {
+        Ok(self.public.verify(cookie.as_bytes(), &self.signature)?)
+    }
 }
 
 impl Wire for HandshakeResponse {
@@ -126,3 +152,69 @@ impl Wire for HandshakeResponse {
         Ok(Self::LEN)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_hex;
+    use crate::network::Network;
+    use crate::node::header::MessageType;
+    use crate::Private;
+    use std::str::FromStr;
+
+    /// A cookie, followed by the node id of the private key `1111…11` and its signature of
+    /// [OUR_COOKIE].
+    ///
+    /// We don't have a capture of a handshake from a nano-node peer, so this was made with our own
+    /// encoder. It only checks that decoding, encoding and signing agree with each other, not that
+    /// they agree with nano-node. It should be replaced with a capture, e.g. from `feeless pcap`,
+    /// once there is one.
+    const QUERY_AND_RESPONSE: &str = concat!(
+        "C3A3FE56D584CB997199E3B09EC454F62DED3B7EF875D9D7E8E5011AC34C77A5",
+        "ACA68A2D52FE17BAB36D48456569FE7F91F23CB57B971B13FAF236EBBCC7FA94",
+        "5BF88AA38A23734BDB64FA62A359FFEEF01DD05145316D31FE13F4E32FB462DE",
+        "31930472B487AD4E980E1585381AFDBA6DDEF6F6DBC3AA4FDDA337021C66630B",
+    );
+
+    /// The cookie the peer was sent in our own query.
+    const OUR_COOKIE: &str = "2994D330022A052DF83E10FCE1B3E140496CDCD7E0C0F2FF6DE2670291B88011";
+
+    #[test]
+    fn fixture() {
+        let ext = *Extensions::new().query().response();
+        let header = Header::new(Network::Live, MessageType::Handshake, ext);
+        let data = hex::decode(QUERY_AND_RESPONSE).unwrap();
+        assert_eq!(Handshake::len(Some(&header)).unwrap(), data.len());
+
+        let handshake = Handshake::deserialize(Some(&header), &data).unwrap();
+        assert_eq!(
+            handshake.query.as_ref().unwrap().cookie().as_hex(),
+            &QUERY_AND_RESPONSE[0..64]
+        );
+        assert_eq!(
+            handshake.response.as_ref().unwrap().public.as_hex(),
+            &QUERY_AND_RESPONSE[64..128]
+        );
+        assert_eq!(handshake.extensions(), ext);
+        assert_eq!(to_hex(&handshake.serialize()), QUERY_AND_RESPONSE);
+
+        let response = handshake.response.as_ref().unwrap();
+        let private = Private::from_str(&"11".repeat(32)).unwrap();
+        assert_eq!(response.public, private.to_public().unwrap());
+        assert!(response
+            .verify(&Cookie::from_str(OUR_COOKIE).unwrap())
+            .is_ok());
+        // The response doesn't sign the cookie of the query it came with.
+        assert!(response
+            .verify(handshake.query.as_ref().unwrap().cookie())
+            .is_err());
+
+        let ext = *Extensions::new().response();
+        let header = Header::new(Network::Live, MessageType::Handshake, ext);
+        let data = &data[Cookie::LEN..];
+        let handshake = Handshake::deserialize(Some(&header), data).unwrap();
+        assert!(handshake.query.is_none());
+        assert_eq!(handshake.extensions(), ext);
+        assert_eq!(handshake.serialize(), data);
+    }
+}
@@ -8,11 +8,26 @@ pub struct Keepalive(Vec<Peer>);
 
 impl Keepalive {
     pub const PEERS: usize = 8;
+
+    /// Only up to `Keepalive::PEERS` peers are sent.
+    pub fn new(peers: Vec<Peer>) -> Self {
+        Self(peers)
+    }
+
+    pub fn peers(&self) -> &[Peer] {
+        &self.0
+    }
 }
 
 impl Wire for Keepalive {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        let mut v = Vec::with_capacity(Peer::LEN * Keepalive::PEERS);
+        for peer in self.0.iter().take(Keepalive::PEERS) {
+            v.extend_from_slice(&peer.serialize());
+        }
+        // Empty slots are zeroed.
+        v.resize(Peer::LEN * Keepalive::PEERS, 0);
+        v
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -36,3 +51,43 @@ impl Wire for Keepalive {
         Ok(Peer::LEN * Keepalive::PEERS)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_hex;
+    use std::str::FromStr;
+
+    /// Two peers, followed by six empty slots.
+    const TWO_PEERS: &str = concat!(
+        "00000000000000000000FFFF5DB8CD50A31B",
+        "00000000000000000000FFFF8A44C3B9A31B",
+        "000000000000000000000000000000000000",
+        "000000000000000000000000000000000000",
+        "000000000000000000000000000000000000",
+        "000000000000000000000000000000000000",
+        "000000000000000000000000000000000000",
+        "000000000000000000000000000000000000",
+    );
+
+    #[test]
+    fn fixture() {
+        let data = hex::decode(TWO_PEERS).unwrap();
+        assert_eq!(Keepalive::len(None).unwrap(), data.len());
+
+        let keepalive = Keepalive::deserialize(None, &data).unwrap();
+        assert_eq!(keepalive.peers().len(), 2);
+        assert_eq!(
+            keepalive.peers()[0].socket_addr_v6().to_string(),
+            "[::ffff:93.184.205.80]:7075"
+        );
+        assert_eq!(to_hex(&keepalive.serialize()), TWO_PEERS);
+
+        let peers = vec![Peer::from_str("[::ffff:138.68.195.185]:7075").unwrap()];
+        let data = Keepalive::new(peers).serialize();
+        assert_eq!(
+            to_hex(&data),
+            TWO_PEERS[36..72].to_owned() + &"0".repeat(36 * 7)
+        );
+    }
+}
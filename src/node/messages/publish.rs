@@ -1,13 +1,24 @@
 use crate::blocks::BlockHolder;
-use crate::node::header::Header;
+use crate::node::header::{Extensions, Header};
 use crate::node::wire::Wire;
 
 #[derive(Debug)]
 pub struct Publish(pub(crate) BlockHolder);
 
+impl Publish {
+    pub fn new(block: BlockHolder) -> Self {
+        Self(block)
+    }
+
+    /// The header extensions needed to send this message.
+    pub fn extensions(&self) -> Extensions {
+        *Extensions::new().set_block_type(&self.0.block_type())
+    }
+}
+
 impl Wire for Publish {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        self.0.serialize()
     }
 
     fn deserialize(header: Option<&Header>, data: &[u8]) -> anyhow::Result<Self>
@@ -21,3 +32,46 @@ impl Wire for Publish {
         BlockHolder::len(header)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{Block, BlockType};
+    use crate::encoding::to_hex;
+    use crate::network::Network;
+    use crate::node::header::MessageType;
+
+    /// The first send block from the genesis account on the live network.
+    const GENESIS_SEND: &str = concat!(
+        "991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948",
+        "059F68AAB29DE0D3A27443625C7EA9CDDB6517A8B76FE37727EF6A4D76832AD5",
+        "FD89D89D89D89D89D89D89D89D89D89D",
+        "5B11B17DB9C8FE0CC58CAC6A6EECEF9CB122DA8A81C6D3DB1B5EE3AB065AA8F8",
+        "CB1D6765C8EB91B58530C5FF5987AD95E6D34BB57F44257E20795EE412E61600",
+        "95EE054972CC823C",
+    );
+
+    #[test]
+    fn send_fixture() {
+        let ext = *Extensions::new().set_block_type(&BlockType::Send);
+        let header = Header::new(Network::Live, MessageType::Publish, ext);
+        let data = hex::decode(GENESIS_SEND).unwrap();
+        assert_eq!(Publish::len(Some(&header)).unwrap(), data.len());
+
+        let publish = Publish::deserialize(Some(&header), &data).unwrap();
+        let send = match &publish.0 {
+            BlockHolder::Send(send) => send,
+            _ => panic!("Expected a send block"),
+        };
+        let genesis = Network::Live.genesis_block();
+        let block = Block::from_send_block(send, genesis.account(), genesis.representative());
+        assert_eq!(
+            block.hash().unwrap().as_hex(),
+            "A170D51B94E00371ACE76E35AC81DC9405D5D04D4CEBC399AEACE07AE05DD293"
+        );
+        assert!(block.verify_signature(genesis.account()).is_ok());
+
+        assert_eq!(publish.extensions(), ext);
+        assert_eq!(to_hex(&publish.serialize()), GENESIS_SEND);
+    }
+}
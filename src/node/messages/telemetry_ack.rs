@@ -5,37 +5,64 @@ use crate::node::wire::Wire;
 use crate::{Public, Signature};
 use anyhow::Context;
 use std::convert::TryFrom;
-use tracing::warn;
 
 #[derive(Debug)]
 pub struct TelemetryAck {
-    signature: Signature,
-    node_id: Public,
-    block_count: u64,
-    cemented_count: u64,
-    unchecked_count: u64,
-    account_count: u64,
-    bandwidth_cap: u64,
-    uptime: u64,
-    peer_count: u32,
-    protocol_version: u8,
-    genesis_block: BlockHash,
-    major_version: u8,
-    minor_version: u8,
-    patch_version: u8,
-    prerelease_version: u8,
-    maker: u8,
-    timestamp: [u8; 8],
-    active_difficulty: [u8; 8],
+    pub signature: Signature,
+    pub node_id: Public,
+    pub block_count: u64,
+    pub cemented_count: u64,
+    pub unchecked_count: u64,
+    pub account_count: u64,
+    pub bandwidth_cap: u64,
+    pub peer_count: u32,
+    pub protocol_version: u8,
+    pub uptime: u64,
+    pub genesis_block: BlockHash,
+    pub major_version: u8,
+    pub minor_version: u8,
+    pub patch_version: u8,
+    pub prerelease_version: u8,
+    pub maker: u8,
+
+    /// Milliseconds since the unix epoch.
+    pub timestamp: u64,
+    pub active_difficulty: u64,
 }
 
 impl TelemetryAck {
     pub const LEN: usize = 202;
+
+    /// The node signs everything in the message after the signature with its node id.
+    pub fn verify_signature(&self) -> anyhow::Result<()> {
+        self.node_id
+            .verify(&self.serialize()[Signature::LEN..], &self.signature)
+            .context("Verify signature on TelemetryAck")
+    }
 }
 
 impl Wire for TelemetryAck {
     fn serialize(&self) -> Vec<u8> {
-        unimplemented!()
+        let mut v = Vec::with_capacity(Self::LEN);
+        v.extend_from_slice(self.signature.as_bytes());
+        v.extend_from_slice(self.node_id.as_bytes());
+        v.extend_from_slice(&self.block_count.to_be_bytes());
+        v.extend_from_slice(&self.cemented_count.to_be_bytes());
+        v.extend_from_slice(&self.unchecked_count.to_be_bytes());
+        v.extend_from_slice(&self.account_count.to_be_bytes());
+        v.extend_from_slice(&self.bandwidth_cap.to_be_bytes());
+        v.extend_from_slice(&self.peer_count.to_be_bytes());
+        v.push(self.protocol_version);
+        v.extend_from_slice(&self.uptime.to_be_bytes());
+        v.extend_from_slice(self.genesis_block.as_bytes());
+        v.push(self.major_version);
+        v.push(self.minor_version);
+        v.push(self.patch_version);
+        v.push(self.prerelease_version);
+        v.push(self.maker);
+        v.extend_from_slice(&self.timestamp.to_be_bytes());
+        v.extend_from_slice(&self.active_difficulty.to_be_bytes());
+        v
     }
 
     fn deserialize(_header: Option<&Header>, data: &[u8]) -> Result<Self, anyhow::Error>
@@ -44,61 +71,50 @@ impl Wire for TelemetryAck {
     {
         let mut bytes = Bytes::new(data);
 
-        let mut s = Self {
-            signature: Signature::try_from(bytes.slice(Signature::LEN)?)
-                .context("Telemetry ack decoding signature")?,
-            node_id: Public::try_from(bytes.slice(Public::LEN)?)
-                .context("Telemetry ack decoding node_id")?,
-            block_count: 0,
-            cemented_count: 0,
-            unchecked_count: 0,
-            account_count: 0,
-            bandwidth_cap: 0,
-            uptime: 0,
-            peer_count: 0,
-            protocol_version: 0,
-            genesis_block: BlockHash::zero(),
-            major_version: 0,
-            minor_version: 0,
-            patch_version: 0,
-            prerelease_version: 0,
-            maker: 0,
-            timestamp: [0u8; 8],
-            active_difficulty: [0u8; 8],
-        };
+        let signature = Signature::try_from(bytes.slice(Signature::LEN)?)
+            .context("Telemetry ack decoding signature")?;
+        let node_id = Public::try_from(bytes.slice(Public::LEN)?)
+            .context("Telemetry ack decoding node_id")?;
 
         let mut s32 = [0u8; 4];
         let mut s64 = [0u8; 8];
+        let mut u64_be = |bytes: &mut Bytes| -> anyhow::Result<u64> {
+            s64.copy_from_slice(bytes.slice(8)?);
+            Ok(u64::from_be_bytes(s64))
+        };
 
-        // TODO s.block_count = bytes.read_be_u64() ?
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.block_count = u64::from_be_bytes(s64);
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.cemented_count = u64::from_be_bytes(s64);
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.unchecked_count = u64::from_be_bytes(s64);
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.account_count = u64::from_be_bytes(s64);
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.bandwidth_cap = u64::from_be_bytes(s64);
-        s64.copy_from_slice(bytes.slice(8)?);
-        s.uptime = u64::from_be_bytes(s64);
+        let block_count = u64_be(&mut bytes)?;
+        let cemented_count = u64_be(&mut bytes)?;
+        let unchecked_count = u64_be(&mut bytes)?;
+        let account_count = u64_be(&mut bytes)?;
+        let bandwidth_cap = u64_be(&mut bytes)?;
         s32.copy_from_slice(bytes.slice(4)?);
-        s.peer_count = u32::from_be_bytes(s32);
-        s.protocol_version = bytes.u8()?;
-        s.genesis_block = BlockHash::try_from(bytes.slice(BlockHash::LEN)?)
+        let peer_count = u32::from_be_bytes(s32);
+        let protocol_version = bytes.u8()?;
+        let uptime = u64_be(&mut bytes)?;
+        let genesis_block = BlockHash::try_from(bytes.slice(BlockHash::LEN)?)
             .context("Telemetry ack decoding genesis block")?;
 
-        s.major_version = bytes.u8()?;
-        s.minor_version = bytes.u8()?;
-        s.patch_version = bytes.u8()?;
-        s.prerelease_version = bytes.u8()?;
-        s.maker = bytes.u8()?;
-
-        warn!("TODO: telemetry ack timestamp");
-        warn!("TODO: telemetry ack active_difficulty");
-
-        Ok(s)
+        Ok(Self {
+            signature,
+            node_id,
+            block_count,
+            cemented_count,
+            unchecked_count,
+            account_count,
+            bandwidth_cap,
+            peer_count,
+            protocol_version,
+            uptime,
+            genesis_block,
+            major_version: bytes.u8()?,
+            minor_version: bytes.u8()?,
+            patch_version: bytes.u8()?,
+            prerelease_version: bytes.u8()?,
+            maker: bytes.u8()?,
+            timestamp: u64_be(&mut bytes)?,
+            active_difficulty: u64_be(&mut bytes)?,
+        })
     }
 
     fn len(_header: Option<&Header>) -> Result<usize, anyhow::Error>
@@ -108,3 +124,66 @@ impl Wire for TelemetryAck {
         Ok(TelemetryAck::LEN)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_hex;
+    use crate::network::Network;
+    use crate::Private;
+    use std::str::FromStr;
+
+    /// A telemetry ack signed by the node id of the private key `1111…11`.
+    ///
+    /// We don't have a capture of a telemetry ack from a nano-node peer, so this was made with our
+    /// own encoder. It only checks that decoding, encoding and signing agree with each other, not
+    /// that they agree with nano-node. It should be replaced with a capture, e.g. from
+    /// `feeless pcap`, once there is one.
+    const TELEMETRY_ACK: &str = concat!(
+        "51AB5447D33308F698381350E58C01EC2091343A8869DA0718B1CAB1A550C71E",
+        "07378C534590BC4E587937DA3E43C4D15E8788C060D54AA706AF5DF9153C260C",
+        "ACA68A2D52FE17BAB36D48456569FE7F91F23CB57B971B13FAF236EBBCC7FA94",
+        "0000000006F22032",
+        "0000000006F21FA7",
+        "00000000000004D2",
+        "0000000001AD2CD8",
+        "0000000000A00000",
+        "000000B8",
+        "12",
+        "0000000000000E10",
+        "991CF190094C00F0B68E2E5F75F6BEE95A2E0BD93CEAA4A6734DB9F19B728948",
+        "1600000000",
+        "00000177D5DC99AA",
+        "FFFFFFF800000000",
+    );
+
+    #[test]
+    fn fixture() {
+        let data = hex::decode(TELEMETRY_ACK).unwrap();
+        assert_eq!(TelemetryAck::len(None).unwrap(), data.len());
+
+        let ack = TelemetryAck::deserialize(None, &data).unwrap();
+        assert_eq!(ack.block_count, 116531250);
+        assert_eq!(ack.cemented_count, 116531111);
+        assert_eq!(ack.unchecked_count, 1234);
+        assert_eq!(ack.account_count, 28126424);
+        assert_eq!(ack.bandwidth_cap, 10485760);
+        assert_eq!(ack.peer_count, 184);
+        assert_eq!(ack.protocol_version, 18);
+        assert_eq!(ack.uptime, 3600);
+        assert_eq!(ack.genesis_block, Network::Live.genesis_hash());
+        assert_eq!(ack.major_version, 22);
+        assert_eq!(ack.timestamp, 1614200740266);
+        assert_eq!(ack.active_difficulty, 0xfffffff800000000);
+
+        assert_eq!(to_hex(&ack.serialize()), TELEMETRY_ACK);
+
+        let private = Private::from_str(&"11".repeat(32)).unwrap();
+        assert_eq!(ack.node_id, private.to_public().unwrap());
+        assert!(ack.verify_signature().is_ok());
+
+        let mut tampered = TelemetryAck::deserialize(None, &data).unwrap();
+        tampered.block_count += 1;
+        assert!(tampered.verify_signature().is_err());
+    }
+}
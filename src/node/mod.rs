@@ -1,24 +1,35 @@
 mod channel;
 mod controller;
 mod cookie;
-mod header;
+mod election;
+pub(crate) mod header;
+mod import;
+mod listener;
 mod messages;
 mod peer;
+#[cfg(any(test, feature = "simulation"))]
+pub mod simulation;
+mod snapshot;
 mod state;
 mod timestamp;
 mod wire;
 
 use crate::network::Network;
 use crate::rpc::server::{RPCMessage, RPCServer};
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use channel::network_channel;
 pub use controller::{Controller, Packet};
+use controller::{Flood, FLOOD_CAPACITY};
 pub use header::Header;
+pub use import::import_ldb;
+use listener::listen;
+pub use snapshot::{export_snapshot, import_snapshot};
 pub use state::{ArcState, MemoryState, SledDiskState};
-use std::net::SocketAddr;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::Path;
 use std::sync::Arc;
-use tokio::net::TcpStream;
-use tokio::sync::{mpsc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, Mutex};
 use tracing::{debug, info};
 pub use wire::Wire;
 
@@ -28,20 +39,62 @@ pub struct Node {
 
     /// If an RPC server is running, this is where messages from it arrive to.
     rpc_rx: Option<mpsc::Receiver<RPCMessage>>,
+
+    /// Run a legacy bootstrap with the first peer on a separate connection.
+    bootstrap: bool,
+
+    /// Where to accept connections from other peers. `None` disables the listener.
+    listen_addr: Option<SocketAddr>,
+
+    /// Maximum number of peers connected to us at the same time.
+    max_inbound: usize,
+
+    /// Shared by the realtime connections, to pass new blocks and votes between their peers.
+    flood: broadcast::Sender<Flood>,
 }
 
 impl Node {
+    /// A node that keeps everything in memory, so the ledger is gone once it stops.
     pub fn new(network: Network) -> Self {
-        // let state = SledDiskState::new(Network::Live);
-        let state = MemoryState::new(network);
-        let state = Arc::new(Mutex::new(state));
+        Self::with_state(network, Arc::new(Mutex::new(MemoryState::new(network))))
+    }
+
+    /// A node that keeps the ledger in a [SledDiskState] database inside `data_dir`, so it
+    /// carries on from where it stopped.
+    pub fn with_data_dir(network: Network, data_dir: &Path) -> anyhow::Result<Self> {
+        let state = SledDiskState::new(network, data_dir)?;
+        Ok(Self::with_state(network, Arc::new(Mutex::new(state))))
+    }
+
+    pub fn with_state(network: Network, state: ArcState) -> Self {
         Self {
             state,
             network,
             rpc_rx: None,
+            bootstrap: false,
+            listen_addr: Some(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::UNSPECIFIED,
+                network.default_port(),
+            ))),
+            max_inbound: Self::DEFAULT_MAX_INBOUND,
+            flood: broadcast::channel(FLOOD_CAPACITY).0,
         }
     }
 
+    pub const DEFAULT_MAX_INBOUND: usize = 64;
+
+    pub fn set_listen_addr(&mut self, listen_addr: Option<SocketAddr>) {
+        self.listen_addr = listen_addr;
+    }
+
+    pub fn set_max_inbound(&mut self, max_inbound: usize) {
+        self.max_inbound = max_inbound;
+    }
+
+    pub fn enable_bootstrap(&mut self) {
+        self.bootstrap = true;
+    }
+
     // TODO: I think result will be needed here to make sure the RPC server can bind.
     pub async fn enable_rpc_server(&mut self) -> anyhow::Result<()> {
         let (rpc_server, rx) = RPCServer::new_with_rx(self.state.clone());
@@ -53,13 +106,48 @@ impl Node {
     pub async fn run(self) -> anyhow::Result<()> {
         let mut handles = vec![];
         let initial_peers = self.state.lock().await.peers().await?;
+
+        if let Some(listen_addr) = self.listen_addr {
+            // Bind here so failing to bind is an error straight away.
+            let listener = TcpListener::bind(listen_addr)
+                .await
+                .with_context(|| format!("Could not listen on {:?}", listen_addr))?;
+            let state = self.state.clone();
+            let network = self.network;
+            let max_inbound = self.max_inbound;
+            let flood = self.flood.clone();
+            let handle = tokio::spawn(async move {
+                listen(network, state, listener, max_inbound, flood)
+                    .await
+                    .expect("Error in listener")
+            });
+            handles.push(handle)
+        }
+
+        if self.bootstrap {
+            if let Some(socket_addr) = initial_peers.iter().next().cloned() {
+                info!("Spawning a bootstrap channel to {:?}", socket_addr);
+                let state = self.state.clone();
+                let network = self.network;
+                let flood = self.flood.clone();
+                let handle = tokio::spawn(async move {
+                    let stream = TcpStream::connect(socket_addr).await.unwrap();
+                    network_channel(network, state, stream, true, false, flood)
+                        .await
+                        .expect("Error in bootstrap network_channel")
+                });
+                handles.push(handle)
+            }
+        }
+
         for socket_addr in initial_peers {
             info!("Spawning a channel to {:?}", socket_addr);
             let state = self.state.clone();
             let network = self.network.clone();
+            let flood = self.flood.clone();
             let handle = tokio::spawn(async move {
                 let stream = TcpStream::connect(socket_addr).await.unwrap();
-                network_channel(network, state, stream)
+                network_channel(network, state, stream, false, false, flood)
                     .await
                     .expect("Error in network_channel")
             });
@@ -81,9 +169,14 @@ impl Node {
     }
 
     pub async fn peer_autodiscovery(&mut self) -> anyhow::Result<()> {
-        let host = self.network.peering_host();
+        let host = self.network.peering_host().ok_or_else(|| {
+            anyhow!(
+                "The {:?} network has no peering host, peers need to be given",
+                self.network
+            )
+        })?;
         info!("Peer autodiscovery initiated with {}", host);
-        let socket_addrs: Vec<SocketAddr> = tokio::net::lookup_host(host)
+        let socket_addrs: Vec<SocketAddr> = tokio::net::lookup_host(&host)
             .await
             .context("Error while trying to lookup default peers")?
             .collect();
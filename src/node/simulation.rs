@@ -0,0 +1,434 @@
+//! Several nodes running in one runtime, connected with in-memory streams instead of TCP.
+//!
+//! The nodes don't vote, so the simulation also connects to every node as a peer of its own.
+//! Through that connection it publishes blocks, and votes for them with the genesis account,
+//! which holds all of the voting weight of the dev network. The node it sends them to floods
+//! them on to the nodes it's connected to.
+//!
+//! Waiting is done by polling the ledgers of the nodes. Run simulations with tokio's clock
+//! paused, e.g. `#[tokio::test(start_paused = true)]`, so that time only moves on once every
+//! node is idle. A timeout then means the nodes have stopped making progress, rather than that
+//! they were slow.
+
+use crate::blocks::{Block, BlockHash, BlockType, Link, Previous, Subtype, ValidationState};
+use crate::network::{DevGenesis, Network};
+use crate::node::channel::stream_channel;
+use crate::node::controller::Controller;
+use crate::node::header::{Header, MessageType};
+use crate::node::messages::confirm_ack::{Confirm, ConfirmAck};
+use crate::node::messages::publish::Publish;
+use crate::node::timestamp::Timestamp;
+use crate::node::wire::Wire;
+use crate::node::Node;
+use crate::{Private, Rai, Seed, Signature, Subject, Work};
+use anyhow::{anyhow, Context};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, WriteHalf};
+use tokio::task::JoinHandle;
+
+/// How much can be written to an in-memory stream before the writer waits for the reader.
+const STREAM_BUFFER: usize = 64 * 1024;
+
+/// How often the ledger of a node is checked while waiting for it to change.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub struct Simulation {
+    network: Network,
+    genesis_private: Private,
+    nodes: Vec<Node>,
+
+    /// Our own connection to each node, in the same order as `nodes`.
+    peers: Vec<WriteHalf<DuplexStream>>,
+
+    /// Votes need increasing timestamps to not be treated as replays.
+    vote_timestamp: u64,
+}
+
+impl Simulation {
+    /// Start `count` nodes on a new dev network, with every node connected to every other node.
+    pub async fn new(count: usize) -> anyhow::Result<Self> {
+        let genesis_private = Seed::random().derive(0);
+        let network = Network::Dev(DevGenesis::new(&genesis_private)?);
+        let mut simulation = Self {
+            network,
+            genesis_private,
+            nodes: vec![],
+            peers: vec![],
+            vote_timestamp: 0,
+        };
+        for _ in 0..count {
+            simulation.add_node().await?;
+        }
+        for a in 0..count {
+            for b in (a + 1)..count {
+                simulation.connect(a, b).await;
+            }
+        }
+        Ok(simulation)
+    }
+
+    /// Start a node that only has the genesis block, without connecting it to the other nodes.
+    pub async fn add_node(&mut self) -> anyhow::Result<usize> {
+        let index = self.nodes.len();
+        let node = Node::new(self.network);
+        self.controller(&node, Self::ADDR)
+            .init()
+            .await
+            .with_context(|| format!("Initialising node {}", index))?;
+
+        let (ours, theirs) = tokio::io::duplex(STREAM_BUFFER);
+        self.spawn_channel(&node, theirs, Self::ADDR, false, true);
+        let (mut reader, writer) = tokio::io::split(ours);
+        // Nothing the node sends us is needed, but it has to be read so the node doesn't block.
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 1024];
+            while let Ok(bytes) = reader.read(&mut buffer).await {
+                if bytes == 0 {
+                    break;
+                }
+            }
+        });
+
+        self.nodes.push(node);
+        self.peers.push(writer);
+        Ok(index)
+    }
+
+    /// Connect node `a` to node `b` with a realtime channel, like `a` dialling out to `b`.
+    ///
+    /// Returns once `b` has had the handshake from `a`, so both nodes flood to each other from
+    /// then on.
+    pub async fn connect(&self, a: usize, b: usize) {
+        let (a_stream, b_stream) = tokio::io::duplex(STREAM_BUFFER);
+        self.spawn_channel(&self.nodes[a], a_stream, Self::addr(b), false, false);
+        self.spawn_channel(&self.nodes[b], b_stream, Self::addr(a), false, true);
+        // With the clock paused this only returns once the nodes are idle.
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    /// Bootstrap node `client` from node `server` on a new connection, which closes once the
+    /// bootstrap has finished.
+    pub async fn bootstrap(&self, client: usize, server: usize) -> anyhow::Result<()> {
+        let (client_stream, server_stream) = tokio::io::duplex(STREAM_BUFFER);
+        let handle = self.spawn_channel(
+            &self.nodes[client],
+            client_stream,
+            Self::addr(server),
+            true,
+            false,
+        );
+        self.spawn_channel(
+            &self.nodes[server],
+            server_stream,
+            Self::addr(client),
+            false,
+            true,
+        );
+        handle.await?
+    }
+
+    /// Publish a block to node `index`, which starts an election for it.
+    pub async fn publish(&mut self, index: usize, block: &Block) -> anyhow::Result<()> {
+        let publish = Publish::new(block.to_holder()?);
+        let header = Header::new(self.network, MessageType::Publish, publish.extensions());
+        self.send(index, &header, &publish).await
+    }
+
+    /// Vote for a block on node `index` with the genesis account, which is enough for quorum.
+    pub async fn vote(&mut self, index: usize, hash: &BlockHash) -> anyhow::Result<()> {
+        self.vote_timestamp += 1;
+        let mut confirm_ack = ConfirmAck::new(
+            self.network.genesis_block().account().to_owned(),
+            Signature::zero(),
+            Timestamp::from_u64(self.vote_timestamp),
+            Confirm::VoteByHash(vec![hash.to_owned()]),
+        );
+        confirm_ack.signature = self.genesis_private.sign(&confirm_ack.inner_hash())?;
+        let header = Header::new(
+            self.network,
+            MessageType::ConfirmAck,
+            confirm_ack.extensions(),
+        );
+        self.send(index, &header, &confirm_ack).await
+    }
+
+    /// Wait until node `index` has `hash` in its ledger, see the module docs about `timeout`.
+    pub async fn wait_for_block(
+        &self,
+        index: usize,
+        hash: &BlockHash,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        let state = self.nodes[index].state.clone();
+        let poll = async {
+            loop {
+                if state.lock().await.get_block_by_hash(hash).await?.is_some() {
+                    return Ok(());
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        };
+        tokio::time::timeout(timeout, poll)
+            .await
+            .map_err(|_| anyhow!("Node {} did not receive {:?} in time", index, hash))?
+    }
+
+    /// Wait until node `index` has confirmed and cemented `hash`, see the module docs about
+    /// `timeout`.
+    pub async fn wait_for_confirmation(
+        &self,
+        index: usize,
+        hash: &BlockHash,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        let node = &self.nodes[index];
+        let controller = self.controller(node, Self::ADDR);
+        let poll = async {
+            loop {
+                let in_ledger = node
+                    .state
+                    .lock()
+                    .await
+                    .get_block_by_hash(hash)
+                    .await?
+                    .is_some();
+                if in_ledger && controller.is_cemented(hash).await? {
+                    return Ok(());
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        };
+        tokio::time::timeout(timeout, poll)
+            .await
+            .map_err(|_| anyhow!("Node {} did not confirm {:?} in time", index, hash))?
+    }
+
+    /// A controller for inspecting the ledger of node `index`, which isn't connected to anything.
+    pub fn ledger(&self, index: usize) -> Controller {
+        self.controller(&self.nodes[index], Self::ADDR)
+    }
+
+    /// A signed state block with enough work, on top of the latest block of `private`'s account
+    /// on node `index`.
+    pub async fn state_block(
+        &self,
+        index: usize,
+        private: &Private,
+        balance: Rai,
+        link: Link,
+    ) -> anyhow::Result<Block> {
+        let account = private.to_public()?;
+        let latest = self.ledger(index).get_latest_block(&account).await?;
+        let (previous, representative) = match &latest {
+            Some(latest) => (
+                Previous::Block(latest.hash()?.to_owned()),
+                latest.representative().to_owned(),
+            ),
+            None => (Previous::Open, account.clone()),
+        };
+        let subtype = match (&link, &latest) {
+            (Link::DestinationAccount(_), _) => Subtype::Send,
+            (Link::Source(_), None) => Subtype::Open,
+            (Link::Source(_), Some(_)) => Subtype::Receive,
+            _ => Subtype::Change,
+        };
+
+        let mut block = Block::new(
+            BlockType::State,
+            account,
+            previous,
+            representative,
+            balance,
+            link,
+            ValidationState::Published,
+        );
+        block.sign(private.to_owned())?;
+        let threshold = self.network.work_threshold(
+            self.ledger(index).account_epoch(block.account()).await?,
+            &subtype,
+        );
+        block.set_work(Work::generate(&Subject::Hash(block.root()), &threshold)?);
+        Ok(block)
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn genesis_private(&self) -> &Private {
+        &self.genesis_private
+    }
+
+    /// Where the simulation connects to the nodes from.
+    const ADDR: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 254), 0));
+
+    /// A made up address for node `index`, so each node can tell its peers apart.
+    fn addr(index: usize) -> SocketAddr {
+        SocketAddr::V4(SocketAddrV4::new(
+            Ipv4Addr::new(10, 0, 0, index as u8 + 1),
+            0,
+        ))
+    }
+
+    fn controller(&self, node: &Node, peer_addr: SocketAddr) -> Controller {
+        let (controller, _tx, _rx) =
+            Controller::new_with_channels(node.network, node.state.clone(), peer_addr);
+        controller
+    }
+
+    fn spawn_channel(
+        &self,
+        node: &Node,
+        stream: DuplexStream,
+        peer_addr: SocketAddr,
+        bootstrap: bool,
+        inbound: bool,
+    ) -> JoinHandle<anyhow::Result<()>> {
+        let (mut controller, tx, rx) =
+            Controller::new_with_channels(node.network, node.state.clone(), peer_addr);
+        controller.bootstrap = bootstrap;
+        controller.inbound = inbound;
+        // Like a node, inbound connections only flood once they turn out to be realtime.
+        if !bootstrap {
+            controller.set_flood(&node.flood);
+        }
+        tokio::spawn(stream_channel(controller, tx, rx, stream))
+    }
+
+    async fn send<T: Wire>(
+        &mut self,
+        index: usize,
+        header: &Header,
+        message: &T,
+    ) -> anyhow::Result<()> {
+        let peer = &mut self.peers[index];
+        peer.write_all(&header.serialize()).await?;
+        peer.write_all(&message.serialize()).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// A send from the genesis account to a new account.
+    async fn genesis_send(simulation: &Simulation, amount: u128) -> (Block, Private) {
+        let private = Seed::random().derive(0);
+        let genesis = simulation.network().genesis_block();
+        let balance = simulation
+            .ledger(0)
+            .account_balance(genesis.account())
+            .await
+            .unwrap();
+        let send = simulation
+            .state_block(
+                0,
+                simulation.genesis_private(),
+                balance.checked_sub(&Rai::from(amount)).unwrap(),
+                Link::DestinationAccount(private.to_public().unwrap()),
+            )
+            .await
+            .unwrap();
+        (send, private)
+    }
+
+    /// A block and its vote only go to one node, and reach the others by flooding.
+    #[tokio::test(start_paused = true)]
+    async fn election() {
+        let mut simulation = Simulation::new(3).await.unwrap();
+        let (send, private) = genesis_send(&simulation, 10).await;
+        let hash = send.hash().unwrap().to_owned();
+
+        simulation.publish(0, &send).await.unwrap();
+        simulation.vote(0, &hash).await.unwrap();
+        for index in 0..3 {
+            simulation
+                .wait_for_confirmation(index, &hash, TIMEOUT)
+                .await
+                .unwrap();
+        }
+
+        // The new account opens itself by receiving the send, this time through another node.
+        let open = simulation
+            .state_block(2, &private, Rai::from(10), Link::Source(hash))
+            .await
+            .unwrap();
+        let open_hash = open.hash().unwrap().to_owned();
+        simulation.publish(2, &open).await.unwrap();
+        simulation.vote(2, &open_hash).await.unwrap();
+        for index in 0..3 {
+            simulation
+                .wait_for_confirmation(index, &open_hash, TIMEOUT)
+                .await
+                .unwrap();
+            let balance = simulation
+                .ledger(index)
+                .account_balance(&private.to_public().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(balance, Rai::from(10));
+        }
+    }
+
+    /// Two nodes each get a different side of a fork and flood it to the other, and they both
+    /// end up with the one that was voted for on only one of them.
+    #[tokio::test(start_paused = true)]
+    async fn fork() {
+        let mut simulation = Simulation::new(2).await.unwrap();
+        let (first, _) = genesis_send(&simulation, 10).await;
+        let (second, _) = genesis_send(&simulation, 20).await;
+        let second_hash = second.hash().unwrap().to_owned();
+
+        simulation.publish(0, &first).await.unwrap();
+        simulation.publish(1, &second).await.unwrap();
+        simulation.vote(0, &second_hash).await.unwrap();
+
+        let genesis = simulation.network().genesis_block();
+        for index in 0..2 {
+            simulation
+                .wait_for_confirmation(index, &second_hash, TIMEOUT)
+                .await
+                .unwrap();
+            let latest = simulation
+                .ledger(index)
+                .get_latest_block(genesis.account())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(latest.hash().unwrap(), &second_hash);
+        }
+    }
+
+    /// A node that joins later catches up on confirmed blocks by bootstrapping.
+    #[tokio::test(start_paused = true)]
+    async fn bootstrap() {
+        let mut simulation = Simulation::new(1).await.unwrap();
+        let (send, _) = genesis_send(&simulation, 10).await;
+        let hash = send.hash().unwrap().to_owned();
+        simulation.publish(0, &send).await.unwrap();
+        simulation.vote(0, &hash).await.unwrap();
+        simulation
+            .wait_for_confirmation(0, &hash, TIMEOUT)
+            .await
+            .unwrap();
+
+        let late = simulation.add_node().await.unwrap();
+        simulation.bootstrap(late, 0).await.unwrap();
+        simulation
+            .wait_for_block(late, &hash, TIMEOUT)
+            .await
+            .unwrap();
+
+        let genesis = simulation.network().genesis_block();
+        let balance = simulation
+            .ledger(late)
+            .account_balance(genesis.account())
+            .await
+            .unwrap();
+        assert_eq!(&balance, send.balance());
+    }
+}
@@ -0,0 +1,564 @@
+//! A portable copy of a ledger, to start nodes from a known state.
+//!
+//! A snapshot starts with [MAGIC], [SNAPSHOT_VERSION] and the hash of the genesis block of its
+//! network, followed by records that each start with a tag byte, an end record, and a blake2b
+//! checksum of everything before it:
+//!
+//! * Account: the account, its frontier, the number of blocks, then each block from the open
+//!   block to the frontier. Blocks are in the same format as they are stored on disk, followed by
+//!   their epoch.
+//! * Confirmation height: the account, its confirmation height and its confirmed frontier. This
+//!   follows the account record of an account that has cemented blocks.
+//! * Pending: the destination, send hash, source and amount of a send that hasn't been received.
+//! * Rep weight: a representative and its weight.
+//!
+//! Accounts are in account order, so the same ledger always gives the same snapshot.
+//!
+//! The whole snapshot is kept in memory while it's written or read.
+use crate::blocks::{Block, BlockHash, Epoch, Link, Previous};
+use crate::bytes::Bytes;
+use crate::encoding::blake2b;
+use crate::network::Network;
+use crate::node::state::{codec, ArcState, ConfirmationHeight, Pending};
+use crate::{Public, Rai};
+use anyhow::{anyhow, Context};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use tracing::info;
+
+pub const MAGIC: &[u8; 8] = b"FEELESS\0";
+
+/// The version of the snapshot format written by this build.
+pub const SNAPSHOT_VERSION: u32 = 4;
+
+const CHECKSUM_LEN: usize = 32;
+
+const TAG_END: u8 = 0;
+const TAG_ACCOUNT: u8 = 1;
+const TAG_PENDING: u8 = 2;
+const TAG_REP_WEIGHT: u8 = 3;
+const TAG_CONFIRMATION_HEIGHT: u8 = 4;
+
+/// How many frontiers to ask the state for at a time.
+const FRONTIERS_BATCH: usize = 1000;
+
+/// How many of each thing a snapshot has.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SnapshotSummary {
+    pub accounts: usize,
+    pub blocks: usize,
+    pub pending: usize,
+    pub rep_weights: usize,
+    pub confirmation_heights: usize,
+}
+
+/// Write the ledger of `state`, which belongs to `network`, to `writer`.
+pub async fn export_snapshot<W: Write>(
+    network: Network,
+    state: &ArcState,
+    mut writer: W,
+) -> anyhow::Result<SnapshotSummary> {
+    let context = || "Exporting snapshot";
+    let state = state.lock().await;
+    let mut summary = SnapshotSummary::default();
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&SNAPSHOT_VERSION.to_be_bytes());
+    data.extend_from_slice(network.genesis_hash().as_bytes());
+
+    let mut representatives: Vec<Public> = vec![];
+    let mut pending = vec![];
+    let mut start = Public::zero();
+    let mut first_batch = true;
+    loop {
+        let frontiers = state
+            .frontiers(&start, None, FRONTIERS_BATCH + 1)
+            .await
+            .with_context(context)?;
+        // The first frontier is the last one of the previous batch, apart from the first batch.
+        let skip = if first_batch { 0 } else { 1 };
+        for (account, frontier) in frontiers.iter().skip(skip) {
+            // Walk back from the frontier to the open block.
+            let mut chain: Vec<Block> = vec![];
+            let mut hash = frontier.to_owned();
+            loop {
+                let block = state
+                    .get_block_by_hash(&hash)
+                    .await?
+                    .ok_or_else(|| anyhow!("Missing block {:?}", hash))
+                    .with_context(context)?;
+                let previous = block.previous().to_owned();
+                chain.push(block);
+                match previous {
+                    Previous::Block(previous) => hash = previous,
+                    Previous::Open => break,
+                }
+            }
+            chain.reverse();
+
+            data.push(TAG_ACCOUNT);
+            data.extend_from_slice(account.as_bytes());
+            data.extend_from_slice(frontier.as_bytes());
+            data.extend_from_slice(&(chain.len() as u32).to_be_bytes());
+            for block in &chain {
+                let encoded = codec::encode_block(block);
+                data.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+                data.extend_from_slice(&encoded);
+                let epoch = state.block_epoch(block.hash()?).await?;
+                data.push(epoch.unwrap_or(Epoch::V0) as u8);
+
+                // Sends that haven't been received yet.
+                if let Link::DestinationAccount(destination) = block.link() {
+                    let send_hash = block.hash()?;
+                    if let Some(entry) = state.get_pending(destination, send_hash).await? {
+                        pending.push((destination.to_owned(), entry));
+                    }
+                }
+            }
+            representatives.push(chain.last().unwrap().representative().to_owned());
+            summary.accounts += 1;
+            summary.blocks += chain.len();
+
+            if let Some(confirmation_height) = state.confirmation_height(account).await? {
+                data.push(TAG_CONFIRMATION_HEIGHT);
+                data.extend_from_slice(account.as_bytes());
+                data.extend_from_slice(&confirmation_height.height.to_be_bytes());
+                data.extend_from_slice(confirmation_height.frontier.as_bytes());
+                summary.confirmation_heights += 1;
+            }
+        }
+
+        if frontiers.len() <= FRONTIERS_BATCH {
+            break;
+        }
+        start = frontiers.last().unwrap().0.to_owned();
+        first_batch = false;
+    }
+
+    pending.sort_by(|a, b| {
+        (a.0.as_bytes(), a.1.send_hash.as_bytes()).cmp(&(b.0.as_bytes(), b.1.send_hash.as_bytes()))
+    });
+    for (destination, entry) in &pending {
+        data.push(TAG_PENDING);
+        data.extend_from_slice(destination.as_bytes());
+        data.extend_from_slice(entry.send_hash.as_bytes());
+        data.extend_from_slice(entry.source.as_bytes());
+        data.extend_from_slice(&entry.amount.to_vec());
+        summary.pending += 1;
+    }
+
+    representatives.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+    representatives.dedup();
+    for representative in representatives.iter() {
+        let weight = state.rep_weight(representative).await?;
+        if weight == Rai::zero() {
+            continue;
+        }
+        data.push(TAG_REP_WEIGHT);
+        data.extend_from_slice(representative.as_bytes());
+        data.extend_from_slice(&weight.to_vec());
+        summary.rep_weights += 1;
+    }
+
+    data.push(TAG_END);
+    let checksum = blake2b(CHECKSUM_LEN, &data);
+    data.extend_from_slice(&checksum);
+    writer.write_all(&data).with_context(context)?;
+    info!("Exported snapshot {:?}", summary);
+    Ok(summary)
+}
+
+/// Everything in a snapshot, read and checked before any of it is added to a ledger.
+#[derive(Default)]
+struct Records {
+    /// Each account chain from its open block, with the epoch of each block.
+    chains: Vec<Vec<(Block, Epoch)>>,
+    confirmation_heights: Vec<(Public, ConfirmationHeight)>,
+    pending: Vec<(Public, Pending)>,
+    rep_weights: Vec<(Public, Rai)>,
+}
+
+/// Load a snapshot of `network` from `reader` into `state`, which needs to have an empty ledger.
+///
+/// The whole snapshot is checked before anything is added: the checksum, the genesis block of its
+/// network, that each account chain leads up to its frontier, and that each confirmation height is
+/// a block of its account chain. A broken snapshot leaves the ledger empty.
+pub async fn import_snapshot<R: Read>(
+    network: Network,
+    state: &ArcState,
+    mut reader: R,
+) -> anyhow::Result<SnapshotSummary> {
+    let context = || "Importing snapshot";
+    let mut data = vec![];
+    reader.read_to_end(&mut data).with_context(context)?;
+    if data.len() < MAGIC.len() + 4 + BlockHash::LEN + CHECKSUM_LEN || &data[..MAGIC.len()] != MAGIC
+    {
+        return Err(anyhow!("Not a snapshot")).with_context(context);
+    }
+    let (data, checksum) = data.split_at(data.len() - CHECKSUM_LEN);
+    if blake2b(CHECKSUM_LEN, data).as_ref() != checksum {
+        return Err(anyhow!("Checksum mismatch")).with_context(context);
+    }
+
+    let mut data = Bytes::new(data);
+    data.slice(MAGIC.len())?;
+    let version = u32::from_be_bytes(<[u8; 4]>::try_from(data.slice(4)?)?);
+    if version != SNAPSHOT_VERSION {
+        return Err(anyhow!(
+            "Snapshot version {} is not supported, expected {}",
+            version,
+            SNAPSHOT_VERSION
+        ))
+        .with_context(context);
+    }
+    let genesis_hash = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
+    if genesis_hash != network.genesis_hash() {
+        return Err(anyhow!(
+            "Snapshot has genesis block {:?}, which isn't the genesis block of {:?}",
+            genesis_hash,
+            network
+        ))
+        .with_context(context);
+    }
+
+    let records = read_records(&mut data).with_context(context)?;
+
+    let mut state = state.lock().await;
+    let is_empty = state.frontiers(&Public::zero(), None, 1).await?.is_empty();
+    if !is_empty {
+        return Err(anyhow!("The ledger to import into is not empty")).with_context(context);
+    }
+
+    let mut summary = SnapshotSummary::default();
+    for chain in &records.chains {
+        for (block, epoch) in chain {
+            state.add_block(block).await.with_context(context)?;
+            state
+                .set_block_epoch(block.hash()?, *epoch)
+                .await
+                .with_context(context)?;
+        }
+        summary.accounts += 1;
+        summary.blocks += chain.len();
+    }
+    for (account, confirmation_height) in &records.confirmation_heights {
+        state
+            .set_confirmation_height(account, confirmation_height)
+            .await
+            .with_context(context)?;
+        summary.confirmation_heights += 1;
+    }
+    for (destination, pending) in &records.pending {
+        state
+            .add_pending(destination, pending)
+            .await
+            .with_context(context)?;
+        summary.pending += 1;
+    }
+    for (representative, weight) in &records.rep_weights {
+        state
+            .set_rep_weight(representative, weight)
+            .await
+            .with_context(context)?;
+        summary.rep_weights += 1;
+    }
+
+    info!("Imported snapshot {:?}", summary);
+    Ok(summary)
+}
+
+/// Read and check the records of a snapshot, up to its end record.
+fn read_records(data: &mut Bytes) -> anyhow::Result<Records> {
+    let mut records = Records::default();
+
+    // The account and height of every block, to check the confirmation heights against.
+    let mut heights: HashMap<BlockHash, (Public, u64)> = HashMap::new();
+    loop {
+        match data.u8()? {
+            TAG_END => break,
+            TAG_ACCOUNT => {
+                let account = Public::try_from(data.slice(Public::LEN)?)?;
+                let frontier = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
+                let count = u32::from_be_bytes(<[u8; 4]>::try_from(data.slice(4)?)?);
+                let mut chain = vec![];
+                let mut previous = Previous::Open;
+                for height in 1..=count as u64 {
+                    let len = u32::from_be_bytes(<[u8; 4]>::try_from(data.slice(4)?)?);
+                    let block = codec::decode_block(data.slice(len as usize)?)?;
+                    if block.account() != &account || block.previous() != &previous {
+                        return Err(anyhow!("Account chain of {:?} is broken", account));
+                    }
+                    let epoch = Epoch::try_from(data.u8()?)?;
+                    let hash = block.hash()?.to_owned();
+                    heights.insert(hash.to_owned(), (account.to_owned(), height));
+                    previous = Previous::Block(hash);
+                    chain.push((block, epoch));
+                }
+                if previous != Previous::Block(frontier.to_owned()) {
+                    return Err(anyhow!(
+                        "Account chain of {:?} doesn't end at {:?}",
+                        account,
+                        frontier
+                    ));
+                }
+                records.chains.push(chain);
+            }
+            TAG_CONFIRMATION_HEIGHT => {
+                let account = Public::try_from(data.slice(Public::LEN)?)?;
+                let confirmation_height = ConfirmationHeight {
+                    height: u64::from_be_bytes(<[u8; 8]>::try_from(data.slice(8)?)?),
+                    frontier: BlockHash::try_from(data.slice(BlockHash::LEN)?)?,
+                };
+                if heights.get(&confirmation_height.frontier)
+                    != Some(&(account.to_owned(), confirmation_height.height))
+                {
+                    return Err(anyhow!(
+                        "Confirmed frontier of {:?} isn't block {} of its chain",
+                        account,
+                        confirmation_height.height
+                    ));
+                }
+                records
+                    .confirmation_heights
+                    .push((account, confirmation_height));
+            }
+            TAG_PENDING => {
+                let destination = Public::try_from(data.slice(Public::LEN)?)?;
+                let pending = Pending {
+                    send_hash: BlockHash::try_from(data.slice(BlockHash::LEN)?)?,
+                    source: Public::try_from(data.slice(Public::LEN)?)?,
+                    amount: Rai::try_from(data.slice(Rai::LEN)?)?,
+                };
+                records.pending.push((destination, pending));
+            }
+            TAG_REP_WEIGHT => {
+                let representative = Public::try_from(data.slice(Public::LEN)?)?;
+                let weight = Rai::try_from(data.slice(Rai::LEN)?)?;
+                records.rep_weights.push((representative, weight));
+            }
+            tag => return Err(anyhow!("Unknown record {}", tag)),
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::SendBlock;
+    use crate::network::Network;
+    use crate::node::controller::Controller;
+    use crate::node::state::MemoryState;
+    use crate::Seed;
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn round_trip() {
+        let network = Network::Live;
+        let state: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        let peer_addr = SocketAddr::from_str("127.0.0.1:7075").unwrap();
+        let mut controller = Controller::new_with_channels(network, state.clone(), peer_addr).0;
+        controller.init().await.unwrap();
+        let genesis = network.genesis_block();
+
+        // A send from genesis that hasn't been received.
+        let destination = Seed::random().derive(0).to_public().unwrap();
+        let send = Block::from_send_block(
+            &SendBlock::new(
+                genesis.hash().unwrap().to_owned(),
+                destination.to_owned(),
+                Rai::from(u128::MAX - 5),
+            ),
+            genesis.account(),
+            genesis.representative(),
+        );
+        let pending = Pending {
+            send_hash: send.hash().unwrap().to_owned(),
+            source: genesis.account().to_owned(),
+            amount: Rai::from(5),
+        };
+        {
+            let mut state = state.lock().await;
+            state.add_block(&send).await.unwrap();
+            state
+                .set_block_epoch(send.hash().unwrap(), Epoch::V1)
+                .await
+                .unwrap();
+            state.add_pending(&destination, &pending).await.unwrap();
+            state
+                .set_confirmation_height(
+                    genesis.account(),
+                    &ConfirmationHeight {
+                        height: 1,
+                        frontier: genesis.hash().unwrap().to_owned(),
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        let mut exported = vec![];
+        let summary = export_snapshot(network, &state, &mut exported)
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            SnapshotSummary {
+                accounts: 1,
+                blocks: 2,
+                pending: 1,
+                rep_weights: 1,
+                confirmation_heights: 1,
+            }
+        );
+
+        // The same ledger gives the same snapshot.
+        let mut again = vec![];
+        export_snapshot(network, &state, &mut again).await.unwrap();
+        assert_eq!(exported, again);
+
+        let loaded: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        assert_eq!(
+            import_snapshot(network, &loaded, exported.as_slice())
+                .await
+                .unwrap(),
+            summary
+        );
+        let loaded = loaded.lock().await;
+        assert_eq!(
+            loaded
+                .get_block_by_hash(genesis.hash().unwrap())
+                .await
+                .unwrap(),
+            Some(genesis.to_owned())
+        );
+        assert_eq!(
+            loaded
+                .get_latest_block_hash_for_account(genesis.account())
+                .await
+                .unwrap(),
+            Some(pending.send_hash.to_owned())
+        );
+        assert_eq!(
+            loaded.block_epoch(&pending.send_hash).await.unwrap(),
+            Some(Epoch::V1)
+        );
+        assert_eq!(
+            loaded
+                .get_pending(&destination, &pending.send_hash)
+                .await
+                .unwrap(),
+            Some(pending)
+        );
+        assert_eq!(
+            loaded.rep_weight(genesis.representative()).await.unwrap(),
+            Rai::max()
+        );
+        assert_eq!(
+            loaded
+                .confirmation_height(genesis.account())
+                .await
+                .unwrap()
+                .map(|c| c.height),
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn corrupt() {
+        let network = Network::Live;
+        let state: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        state
+            .lock()
+            .await
+            .add_block(&network.genesis_block())
+            .await
+            .unwrap();
+        let mut exported = vec![];
+        export_snapshot(network, &state, &mut exported)
+            .await
+            .unwrap();
+
+        let middle = exported.len() / 2;
+        exported[middle] ^= 1;
+        let loaded: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        assert!(import_snapshot(network, &loaded, exported.as_slice())
+            .await
+            .is_err());
+        assert!(loaded
+            .lock()
+            .await
+            .get_block_by_hash(&network.genesis_hash())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn other_network() {
+        let network = Network::Live;
+        let state: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        state
+            .lock()
+            .await
+            .add_block(&network.genesis_block())
+            .await
+            .unwrap();
+        let mut exported = vec![];
+        export_snapshot(network, &state, &mut exported)
+            .await
+            .unwrap();
+
+        let loaded: ArcState = Arc::new(Mutex::new(MemoryState::new(Network::Beta)));
+        assert!(import_snapshot(Network::Beta, &loaded, exported.as_slice())
+            .await
+            .is_err());
+        assert!(loaded
+            .lock()
+            .await
+            .get_block_by_hash(&network.genesis_hash())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    /// A bad record after the account chains leaves nothing behind, so the import can be retried.
+    #[tokio::test]
+    async fn invalid_record() {
+        let network = Network::Live;
+        let genesis = network.genesis_block();
+        let state: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        state.lock().await.add_block(&genesis).await.unwrap();
+        state
+            .lock()
+            .await
+            .set_confirmation_height(
+                genesis.account(),
+                &ConfirmationHeight {
+                    height: 2,
+                    frontier: genesis.hash().unwrap().to_owned(),
+                },
+            )
+            .await
+            .unwrap();
+        let mut exported = vec![];
+        export_snapshot(network, &state, &mut exported)
+            .await
+            .unwrap();
+
+        let loaded: ArcState = Arc::new(Mutex::new(MemoryState::new(network)));
+        assert!(import_snapshot(network, &loaded, exported.as_slice())
+            .await
+            .is_err());
+        assert!(loaded
+            .lock()
+            .await
+            .frontiers(&Public::zero(), None, 1)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
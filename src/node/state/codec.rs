@@ -0,0 +1,93 @@
+//! The storage format of a [Block], shared by the disk state and snapshots.
+use crate::blocks::{Block, BlockHash, BlockType, Link, Previous, UnsureLink, ValidationState};
+use crate::bytes::Bytes;
+use crate::{Public, Rai, Signature, Work};
+use anyhow::anyhow;
+use std::convert::TryFrom;
+
+/// Blocks are stored with every field of [Block], including the signature and work if there
+/// are any, so the block can be restored without looking at any other block.
+pub(crate) fn encode_block(block: &Block) -> Vec<u8> {
+    let mut data = vec![block.block_type().as_u8()];
+    data.extend_from_slice(block.account().as_bytes());
+    data.extend_from_slice(&block.previous().to_bytes());
+    data.extend_from_slice(block.representative().as_bytes());
+    data.extend_from_slice(&block.balance().to_vec());
+    data.push(match block.link() {
+        Link::Nothing => 0,
+        Link::Unsure(_) => 1,
+        Link::Source(_) => 2,
+        Link::DestinationAccount(_) => 3,
+    });
+    data.extend_from_slice(block.link().as_bytes());
+    data.push(match block.validation_state() {
+        ValidationState::Published => 0,
+        ValidationState::PresumedValid => 1,
+        ValidationState::Valid => 2,
+        ValidationState::SignatureFailed => 3,
+        ValidationState::WorkFailed => 4,
+    });
+    match block.signature() {
+        Some(signature) => {
+            data.push(1);
+            data.extend_from_slice(signature.as_bytes());
+        }
+        None => data.push(0),
+    }
+    match block.work() {
+        Some(work) => {
+            data.push(1);
+            data.extend_from_slice(work.as_bytes());
+        }
+        None => data.push(0),
+    }
+    data
+}
+
+pub(crate) fn decode_block(data: &[u8]) -> anyhow::Result<Block> {
+    let mut data = Bytes::new(data);
+    let block_type = BlockType::try_from(data.u8()?)?;
+    let account = Public::try_from(data.slice(Public::LEN)?)?;
+    let previous = BlockHash::try_from(data.slice(BlockHash::LEN)?)?;
+    let previous = if previous == BlockHash::zero() {
+        Previous::Open
+    } else {
+        Previous::Block(previous)
+    };
+    let representative = Public::try_from(data.slice(Public::LEN)?)?;
+    let balance = Rai::try_from(data.slice(Rai::LEN)?)?;
+    let link_tag = data.u8()?;
+    let link = data.slice(Link::LEN)?;
+    let link = match link_tag {
+        0 => Link::Nothing,
+        1 => Link::Unsure(UnsureLink::try_from(link)?),
+        2 => Link::Source(BlockHash::try_from(link)?),
+        3 => Link::DestinationAccount(Public::try_from(link)?),
+        tag => return Err(anyhow!("Unknown link tag: {}", tag)),
+    };
+    let state = match data.u8()? {
+        0 => ValidationState::Published,
+        1 => ValidationState::PresumedValid,
+        2 => ValidationState::Valid,
+        3 => ValidationState::SignatureFailed,
+        4 => ValidationState::WorkFailed,
+        state => return Err(anyhow!("Unknown validation state: {}", state)),
+    };
+
+    let mut block = Block::new(
+        block_type,
+        account,
+        previous,
+        representative,
+        balance,
+        link,
+        state,
+    );
+    if data.u8()? == 1 {
+        block.set_signature(Signature::try_from(data.slice(Signature::LEN)?)?);
+    }
+    if data.u8()? == 1 {
+        block.set_work(Work::try_from(data.slice(Work::LEN)?)?);
+    }
+    Ok(block)
+}
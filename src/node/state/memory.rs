@@ -1,12 +1,15 @@
-use crate::blocks::{Block, BlockHash};
+use crate::blocks::{Block, BlockHash, BlockHolder, Epoch, Link, Previous};
 use crate::network::Network;
 use crate::node::cookie::Cookie;
-use crate::node::state::State;
-use crate::Public;
+use crate::node::election::Election;
+use crate::node::state::{ConfirmationHeight, Pending, State, MAX_UNCHECKED};
+use crate::node::timestamp::Timestamp;
+use crate::{Public, Rai};
 use anyhow::Context;
 use async_trait::async_trait;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug)]
 pub struct MemoryState {
@@ -14,8 +17,26 @@ pub struct MemoryState {
     cookies: HashMap<SocketAddr, Cookie>,
     blocks: HashMap<BlockHash, Block>,
     block_hash_to_account: HashMap<BlockHash, Public>,
+    block_heights: HashMap<BlockHash, u64>,
+
+    /// Hash of a send block to the hash of the block that received it.
+    receivers: HashMap<BlockHash, BlockHash>,
     latest_block_hash: HashMap<Public, BlockHash>,
-    votes: HashMap<BlockHash, HashSet<Public>>,
+    account_modified: HashMap<Public, SystemTime>,
+    confirmation_heights: HashMap<Public, ConfirmationHeight>,
+    block_epochs: HashMap<BlockHash, Epoch>,
+    pending: HashMap<Public, HashMap<BlockHash, Pending>>,
+    /// Dependency to the blocks waiting for it, with when each was added.
+    unchecked: HashMap<BlockHash, Vec<(u64, BlockHolder)>>,
+
+    /// When an unchecked block was added to its dependency, oldest first.
+    unchecked_age: BTreeMap<u64, BlockHash>,
+    next_unchecked: u64,
+    max_unchecked: usize,
+    votes: HashMap<BlockHash, HashMap<Public, Timestamp>>,
+    elections: HashMap<BlockHash, Election>,
+    rep_weights: HashMap<Public, Rai>,
+    rep_last_vote: HashMap<Public, SystemTime>,
     peers: HashSet<SocketAddr>,
 }
 
@@ -26,8 +47,21 @@ impl MemoryState {
             cookies: HashMap::new(),
             blocks: HashMap::new(),
             block_hash_to_account: HashMap::new(),
+            block_heights: HashMap::new(),
+            receivers: HashMap::new(),
             latest_block_hash: HashMap::new(),
+            account_modified: HashMap::new(),
+            confirmation_heights: HashMap::new(),
+            block_epochs: HashMap::new(),
+            pending: HashMap::new(),
+            unchecked: HashMap::new(),
+            unchecked_age: BTreeMap::new(),
+            next_unchecked: 0,
+            max_unchecked: MAX_UNCHECKED,
             votes: HashMap::new(),
+            elections: HashMap::new(),
+            rep_weights: HashMap::new(),
+            rep_last_vote: HashMap::new(),
             peers: HashSet::new(),
         }
     }
@@ -36,14 +70,54 @@ impl MemoryState {
 #[async_trait]
 impl State for MemoryState {
     async fn add_block(&mut self, block: &Block) -> anyhow::Result<()> {
+        let height = match block.previous() {
+            Previous::Block(previous) => {
+                self.block_heights
+                    .get(previous)
+                    .with_context(|| format!("Add block: missing previous block {:?}", previous))?
+                    + 1
+            }
+            Previous::Open => 1,
+        };
+        self.block_heights.insert(block.hash()?.to_owned(), height);
         self.blocks.insert(
             block.hash().context("Add block")?.to_owned(),
             block.to_owned(),
         );
         self.block_hash_to_account
             .insert(block.hash()?.to_owned(), block.account().to_owned());
+        if let Link::Source(source) = block.link() {
+            self.receivers
+                .insert(source.to_owned(), block.hash()?.to_owned());
+        }
         self.latest_block_hash
             .insert(block.account().to_owned(), block.hash()?.to_owned());
+        self.account_modified
+            .insert(block.account().to_owned(), SystemTime::now());
+        Ok(())
+    }
+
+    async fn remove_block(&mut self, block: &Block) -> anyhow::Result<()> {
+        let hash = block.hash().context("Remove block")?;
+        self.blocks.remove(hash);
+        self.block_hash_to_account.remove(hash);
+        self.block_heights.remove(hash);
+        self.block_epochs.remove(hash);
+        if let Link::Source(source) = block.link() {
+            self.receivers.remove(source);
+        }
+        match block.previous() {
+            Previous::Block(previous) => {
+                self.latest_block_hash
+                    .insert(block.account().to_owned(), previous.to_owned());
+                self.account_modified
+                    .insert(block.account().to_owned(), SystemTime::now());
+            }
+            Previous::Open => {
+                self.latest_block_hash.remove(block.account());
+                self.account_modified.remove(block.account());
+            }
+        }
         Ok(())
     }
 
@@ -51,6 +125,10 @@ impl State for MemoryState {
         Ok(self.blocks.get(hash).map(|b| b.to_owned()))
     }
 
+    async fn block_height(&self, hash: &BlockHash) -> anyhow::Result<Option<u64>> {
+        Ok(self.block_heights.get(hash).copied())
+    }
+
     async fn get_latest_block_hash_for_account(
         &self,
         account: &Public,
@@ -68,26 +146,277 @@ impl State for MemoryState {
             .map(|a| a.to_owned()))
     }
 
-    async fn add_vote(&mut self, hash: &BlockHash, representative: &Public) -> anyhow::Result<()> {
-        let entry = self
+    async fn block_hash_receiving(
+        &self,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<BlockHash>> {
+        Ok(self.receivers.get(send_hash).map(|h| h.to_owned()))
+    }
+
+    async fn add_pending(&mut self, destination: &Public, pending: &Pending) -> anyhow::Result<()> {
+        self.pending
+            .entry(destination.to_owned())
+            .or_default()
+            .insert(pending.send_hash.to_owned(), pending.to_owned());
+        Ok(())
+    }
+
+    async fn remove_pending(
+        &mut self,
+        destination: &Public,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<Pending>> {
+        let entries = match self.pending.get_mut(destination) {
+            Some(entries) => entries,
+            None => return Ok(None),
+        };
+        let removed = entries.remove(send_hash);
+        if entries.is_empty() {
+            self.pending.remove(destination);
+        }
+        Ok(removed)
+    }
+
+    async fn get_pending(
+        &self,
+        destination: &Public,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<Pending>> {
+        Ok(self
+            .pending
+            .get(destination)
+            .and_then(|entries| entries.get(send_hash))
+            .map(|p| p.to_owned()))
+    }
+
+    async fn pending(
+        &self,
+        destination: &Public,
+        minimum_amount: &Rai,
+    ) -> anyhow::Result<Vec<Pending>> {
+        let mut pending: Vec<Pending> = self
+            .pending
+            .get(destination)
+            .map(|entries| {
+                entries
+                    .values()
+                    .filter(|p| &p.amount >= minimum_amount)
+                    .map(|p| p.to_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        pending.sort_by(|a, b| a.send_hash.as_bytes().cmp(b.send_hash.as_bytes()));
+        Ok(pending)
+    }
+
+    async fn frontiers(
+        &self,
+        start: &Public,
+        max_age: Option<Duration>,
+        count: usize,
+    ) -> anyhow::Result<Vec<(Public, BlockHash)>> {
+        let now = SystemTime::now();
+        let is_recent = |account: &Public| match max_age {
+            None => true,
+            Some(max_age) => self
+                .account_modified
+                .get(account)
+                .map(|modified| now.duration_since(*modified).unwrap_or_default() <= max_age)
+                .unwrap_or(false),
+        };
+
+        let mut frontiers: Vec<(Public, BlockHash)> = self
+            .latest_block_hash
+            .iter()
+            .filter(|(account, _)| account.as_bytes() >= start.as_bytes() && is_recent(account))
+            .map(|(account, hash)| (account.to_owned(), hash.to_owned()))
+            .collect();
+        frontiers.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+        frontiers.truncate(count);
+        Ok(frontiers)
+    }
+
+    async fn confirmation_height(
+        &self,
+        account: &Public,
+    ) -> anyhow::Result<Option<ConfirmationHeight>> {
+        Ok(self.confirmation_heights.get(account).map(|c| c.to_owned()))
+    }
+
+    async fn set_confirmation_height(
+        &mut self,
+        account: &Public,
+        confirmation_height: &ConfirmationHeight,
+    ) -> anyhow::Result<()> {
+        self.confirmation_heights
+            .insert(account.to_owned(), confirmation_height.to_owned());
+        Ok(())
+    }
+
+    async fn block_epoch(&self, hash: &BlockHash) -> anyhow::Result<Option<Epoch>> {
+        Ok(self.block_epochs.get(hash).copied())
+    }
+
+    async fn set_block_epoch(&mut self, hash: &BlockHash, epoch: Epoch) -> anyhow::Result<()> {
+        self.block_epochs.insert(hash.to_owned(), epoch);
+        Ok(())
+    }
+
+    async fn add_unchecked(
+        &mut self,
+        dependency: &BlockHash,
+        block: &BlockHolder,
+    ) -> anyhow::Result<()> {
+        let blocks = self.unchecked.entry(dependency.to_owned()).or_default();
+        if blocks.iter().any(|(_, b)| b == block) {
+            return Ok(());
+        }
+        let age = self.next_unchecked;
+        self.next_unchecked += 1;
+        blocks.push((age, block.to_owned()));
+        self.unchecked_age.insert(age, dependency.to_owned());
+
+        while self.unchecked_age.len() > self.max_unchecked {
+            let (age, dependency) = match self.unchecked_age.iter().next() {
+                Some((age, dependency)) => (*age, dependency.to_owned()),
+                None => break,
+            };
+            self.unchecked_age.remove(&age);
+            if let Some(blocks) = self.unchecked.get_mut(&dependency) {
+                blocks.retain(|(a, _)| *a != age);
+                if blocks.is_empty() {
+                    self.unchecked.remove(&dependency);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn take_unchecked(&mut self, dependency: &BlockHash) -> anyhow::Result<Vec<BlockHolder>> {
+        let blocks = self.unchecked.remove(dependency).unwrap_or_default();
+        for (age, _) in &blocks {
+            self.unchecked_age.remove(age);
+        }
+        Ok(blocks.into_iter().map(|(_, block)| block).collect())
+    }
+
+    async fn unchecked_count(&self) -> anyhow::Result<usize> {
+        Ok(self.unchecked_age.len())
+    }
+
+    async fn add_vote(
+        &mut self,
+        hash: &BlockHash,
+        representative: &Public,
+        timestamp: &Timestamp,
+    ) -> anyhow::Result<()> {
+        let entry = self.votes.entry(hash.to_owned()).or_default();
+        entry.insert(representative.to_owned(), timestamp.to_owned());
+        Ok(())
+    }
+
+    async fn latest_vote_timestamp(
+        &self,
+        hash: &BlockHash,
+        representative: &Public,
+    ) -> anyhow::Result<Option<Timestamp>> {
+        Ok(self
             .votes
-            .entry(hash.to_owned())
-            .or_insert_with(|| HashSet::new());
-        entry.insert(representative.to_owned());
+            .get(hash)
+            .and_then(|votes| votes.get(representative))
+            .map(|t| t.to_owned()))
+    }
 
-        // dbg!(&self
-        //     .votes
-        //     .iter()
-        //     .map(|v| format!("{} {}", v.0, v.1.len()))
-        //     .collect::<Vec<_>>());
+    async fn votes_for_block(&self, hash: &BlockHash) -> anyhow::Result<Vec<(Public, Timestamp)>> {
+        Ok(self
+            .votes
+            .get(hash)
+            .map(|votes| {
+                votes
+                    .iter()
+                    .map(|(rep, timestamp)| (rep.to_owned(), timestamp.to_owned()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
 
-        // dbg!(&self.votes);
+    async fn remove_votes(&mut self, hash: &BlockHash) -> anyhow::Result<()> {
+        self.votes.remove(hash);
+        Ok(())
+    }
 
-        // println!("XXXXXX {:?} {:?}", hash, representative);
+    async fn election(&self, root: &BlockHash) -> anyhow::Result<Option<Election>> {
+        Ok(self.elections.get(root).map(|e| e.to_owned()))
+    }
 
+    async fn election_root_for_block(&self, hash: &BlockHash) -> anyhow::Result<Option<BlockHash>> {
+        Ok(self
+            .elections
+            .values()
+            .find(|election| election.candidate(hash).is_some())
+            .map(|election| election.root.to_owned()))
+    }
+
+    async fn set_election(&mut self, election: &Election) -> anyhow::Result<()> {
+        self.elections
+            .insert(election.root.to_owned(), election.to_owned());
+        Ok(())
+    }
+
+    async fn remove_election(&mut self, root: &BlockHash) -> anyhow::Result<()> {
+        self.elections.remove(root);
         Ok(())
     }
 
+    async fn expired_elections(&self, now: SystemTime) -> anyhow::Result<Vec<BlockHash>> {
+        Ok(self
+            .elections
+            .values()
+            .filter(|election| election.is_expired(now))
+            .map(|election| election.root.to_owned())
+            .collect())
+    }
+
+    async fn rep_weight(&self, representative: &Public) -> anyhow::Result<Rai> {
+        Ok(self
+            .rep_weights
+            .get(representative)
+            .map(|w| w.to_owned())
+            .unwrap_or_else(Rai::zero))
+    }
+
+    async fn set_rep_weight(
+        &mut self,
+        representative: &Public,
+        weight: &Rai,
+    ) -> anyhow::Result<()> {
+        if weight == &Rai::zero() {
+            self.rep_weights.remove(representative);
+        } else {
+            self.rep_weights
+                .insert(representative.to_owned(), weight.to_owned());
+        }
+        Ok(())
+    }
+
+    async fn set_rep_last_vote(
+        &mut self,
+        representative: &Public,
+        time: SystemTime,
+    ) -> anyhow::Result<()> {
+        self.rep_last_vote.insert(representative.to_owned(), time);
+        Ok(())
+    }
+
+    async fn reps_voted_since(&self, since: SystemTime) -> anyhow::Result<Vec<Public>> {
+        Ok(self
+            .rep_last_vote
+            .iter()
+            .filter(|(_, time)| **time >= since)
+            .map(|(rep, _)| rep.to_owned())
+            .collect())
+    }
+
     async fn set_cookie(
         &mut self,
         socket_addr: SocketAddr,
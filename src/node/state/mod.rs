@@ -1,9 +1,13 @@
+pub(crate) mod codec;
 mod memory;
+mod schema;
 mod sled_disk;
 
-use crate::blocks::{Block, BlockHash};
+use crate::blocks::{Block, BlockHash, BlockHolder, Epoch};
 use crate::node::cookie::Cookie;
-use crate::Public;
+use crate::node::election::Election;
+use crate::node::timestamp::Timestamp;
+use crate::{Public, Rai};
 use async_trait::async_trait;
 pub use memory::MemoryState;
 pub use sled_disk::SledDiskState;
@@ -11,8 +15,59 @@ use std::collections::HashSet;
 use std::fmt::Debug;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::sync::Mutex;
 
+/// The most unchecked blocks kept at a time. When there are more, the oldest are dropped.
+pub const MAX_UNCHECKED: usize = 65536;
+
+/// A send that hasn't been received by its destination account yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pending {
+    pub send_hash: BlockHash,
+
+    /// The account that sent the amount.
+    pub source: Public,
+
+    pub amount: Rai,
+}
+
+/// How much of an account chain has been confirmed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfirmationHeight {
+    /// The number of confirmed blocks, where the open block is 1.
+    pub height: u64,
+
+    /// The hash of the confirmed block at `height`.
+    pub frontier: BlockHash,
+}
+
+/// The block an [LedgerUpdate] adds to or removes from the ledger.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockChange {
+    /// Add a block, with the epoch of its account chain as of that block.
+    Add(Block, Epoch),
+
+    /// Remove the latest block of an account, see [State::remove_block].
+    Remove(Block),
+}
+
+/// A block being added or removed, together with the pending entries and representative weights
+/// that change with it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerUpdate {
+    pub block: BlockChange,
+
+    /// Sends that become pending, by destination.
+    pub add_pending: Vec<(Public, Pending)>,
+
+    /// Sends that are no longer pending, by destination.
+    pub remove_pending: Vec<(Public, BlockHash)>,
+
+    /// The new weight of every representative whose weight changes.
+    pub rep_weights: Vec<(Public, Rai)>,
+}
+
 pub type DynState = dyn State + Send + Sync;
 pub type ArcState = Arc<Mutex<DynState>>;
 
@@ -20,10 +75,44 @@ pub type ArcState = Arc<Mutex<DynState>>;
 /// it also contains ephemeral information like peers.
 #[async_trait]
 pub trait State: Debug + Sync + Send + 'static {
+    /// Add a block after its previous block, which has to be in the ledger already so the height
+    /// of the block can be recorded.
     async fn add_block(&mut self, block: &Block) -> anyhow::Result<()>;
 
+    /// Remove the latest block of an account, e.g. when rolling back a fork. Its previous block
+    /// becomes the latest block of the account again. The epoch of the block is removed with it.
+    async fn remove_block(&mut self, block: &Block) -> anyhow::Result<()>;
+
+    /// Apply every change in `update`.
+    ///
+    /// Backends that write to disk should apply them atomically, so the ledger is never left
+    /// with a block but without its pending entries or weights, e.g. when the node is stopped
+    /// part way through.
+    async fn apply_update(&mut self, update: &LedgerUpdate) -> anyhow::Result<()> {
+        match &update.block {
+            BlockChange::Add(block, epoch) => {
+                self.add_block(block).await?;
+                self.set_block_epoch(block.hash()?, *epoch).await?;
+            }
+            BlockChange::Remove(block) => self.remove_block(block).await?,
+        }
+        for (destination, send_hash) in &update.remove_pending {
+            self.remove_pending(destination, send_hash).await?;
+        }
+        for (destination, pending) in &update.add_pending {
+            self.add_pending(destination, pending).await?;
+        }
+        for (representative, weight) in &update.rep_weights {
+            self.set_rep_weight(representative, weight).await?;
+        }
+        Ok(())
+    }
+
     async fn get_block_by_hash(&self, hash: &BlockHash) -> anyhow::Result<Option<Block>>;
 
+    /// The position of a block in its account chain, where the open block is 1.
+    async fn block_height(&self, hash: &BlockHash) -> anyhow::Result<Option<u64>>;
+
     async fn get_latest_block_hash_for_account(
         &self,
         account: &Public,
@@ -34,7 +123,126 @@ pub trait State: Debug + Sync + Send + 'static {
         block_hash: &BlockHash,
     ) -> anyhow::Result<Option<Public>>;
 
-    async fn add_vote(&mut self, hash: &BlockHash, representative: &Public) -> anyhow::Result<()>;
+    /// The block that received the given send block, if it has been received.
+    async fn block_hash_receiving(
+        &self,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<BlockHash>>;
+
+    async fn add_pending(&mut self, destination: &Public, pending: &Pending) -> anyhow::Result<()>;
+
+    /// Returns the removed entry, if it was pending.
+    async fn remove_pending(
+        &mut self,
+        destination: &Public,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<Pending>>;
+
+    async fn get_pending(
+        &self,
+        destination: &Public,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<Pending>>;
+
+    /// Every pending send to `destination` of at least `minimum_amount`, ordered by send hash.
+    async fn pending(
+        &self,
+        destination: &Public,
+        minimum_amount: &Rai,
+    ) -> anyhow::Result<Vec<Pending>>;
+
+    /// Accounts and their latest block hash in ascending account order, starting at `start`.
+    ///
+    /// When `max_age` is set, only accounts that have been modified within that time are included.
+    async fn frontiers(
+        &self,
+        start: &Public,
+        max_age: Option<Duration>,
+        count: usize,
+    ) -> anyhow::Result<Vec<(Public, BlockHash)>>;
+
+    async fn confirmation_height(
+        &self,
+        account: &Public,
+    ) -> anyhow::Result<Option<ConfirmationHeight>>;
+
+    async fn set_confirmation_height(
+        &mut self,
+        account: &Public,
+        confirmation_height: &ConfirmationHeight,
+    ) -> anyhow::Result<()>;
+
+    /// The epoch of an account chain as of the given block, if one was recorded for it.
+    async fn block_epoch(&self, hash: &BlockHash) -> anyhow::Result<Option<Epoch>>;
+
+    async fn set_block_epoch(&mut self, hash: &BlockHash, epoch: Epoch) -> anyhow::Result<()>;
+
+    /// Park a block that can't be processed until `dependency` (its previous or source block)
+    /// is in the ledger.
+    ///
+    /// Once there are more than [MAX_UNCHECKED] blocks, the oldest are dropped, so peers can't
+    /// fill up the node with blocks that will never be processed.
+    async fn add_unchecked(
+        &mut self,
+        dependency: &BlockHash,
+        block: &BlockHolder,
+    ) -> anyhow::Result<()>;
+
+    /// Remove and return the blocks waiting for `dependency`, in the order they were added.
+    async fn take_unchecked(&mut self, dependency: &BlockHash) -> anyhow::Result<Vec<BlockHolder>>;
+
+    async fn unchecked_count(&self) -> anyhow::Result<usize>;
+
+    /// Record a representative's vote for a block. A newer vote from the same representative
+    /// replaces the older one.
+    async fn add_vote(
+        &mut self,
+        hash: &BlockHash,
+        representative: &Public,
+        timestamp: &Timestamp,
+    ) -> anyhow::Result<()>;
+
+    /// The timestamp of the latest vote by `representative` for this block, if any.
+    async fn latest_vote_timestamp(
+        &self,
+        hash: &BlockHash,
+        representative: &Public,
+    ) -> anyhow::Result<Option<Timestamp>>;
+
+    /// Every representative that has voted for this block, with the timestamp of their vote.
+    async fn votes_for_block(&self, hash: &BlockHash) -> anyhow::Result<Vec<(Public, Timestamp)>>;
+
+    /// Forget the votes for a block, once the election it was in is over.
+    async fn remove_votes(&mut self, hash: &BlockHash) -> anyhow::Result<()>;
+
+    async fn election(&self, root: &BlockHash) -> anyhow::Result<Option<Election>>;
+
+    /// The root of the active election that has this block as a candidate.
+    async fn election_root_for_block(&self, hash: &BlockHash) -> anyhow::Result<Option<BlockHash>>;
+
+    /// Add or replace the active election for the root of `election`.
+    async fn set_election(&mut self, election: &Election) -> anyhow::Result<()>;
+
+    async fn remove_election(&mut self, root: &BlockHash) -> anyhow::Result<()>;
+
+    /// The roots of the active elections that have expired by `now`, see [Election::is_expired].
+    async fn expired_elections(&self, now: SystemTime) -> anyhow::Result<Vec<BlockHash>>;
+
+    /// The total balance of the accounts delegating to this representative.
+    async fn rep_weight(&self, representative: &Public) -> anyhow::Result<Rai>;
+
+    async fn set_rep_weight(&mut self, representative: &Public, weight: &Rai)
+        -> anyhow::Result<()>;
+
+    /// Remember when a representative was last seen voting, to estimate the online weight.
+    async fn set_rep_last_vote(
+        &mut self,
+        representative: &Public,
+        time: SystemTime,
+    ) -> anyhow::Result<()>;
+
+    /// Representatives that have voted at or after `since`.
+    async fn reps_voted_since(&self, since: SystemTime) -> anyhow::Result<Vec<Public>>;
 
     async fn set_cookie(&mut self, socket_addr: SocketAddr, cookie: Cookie) -> anyhow::Result<()>;
 
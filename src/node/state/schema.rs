@@ -0,0 +1,377 @@
+//! Versioning of the on disk layout of [SledDiskState](super::SledDiskState).
+//!
+//! The version is stored in the default tree of the database. When a database with an older
+//! version is opened, each migration step up to [SCHEMA_VERSION] is run in order, and the version
+//! is stored after every step so an interrupted migration carries on where it stopped.
+//!
+//! To change the layout: bump [SCHEMA_VERSION] and add a step to the end of [MIGRATIONS].
+use crate::blocks::{BlockHash, BlockHolder, Epoch, Link, Previous};
+use crate::node::state::codec;
+use crate::Rai;
+use anyhow::{anyhow, Context};
+use sled::transaction::{ConflictableTransactionResult, Transactional};
+use std::convert::TryFrom;
+use tracing::info;
+
+/// The version of the layout this build reads and writes.
+pub const SCHEMA_VERSION: u32 = 4;
+
+const VERSION_KEY: &[u8] = b"schema_version";
+
+type Migration = fn(&sled::Db) -> anyhow::Result<()>;
+
+/// The step at each index migrates a database from that version to the next one.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [
+    unversioned_to_1,
+    add_block_epochs,
+    add_block_heights,
+    unchecked_by_age,
+];
+
+/// Bring the database up to [SCHEMA_VERSION], refusing databases written by a newer version.
+///
+/// Returns the version the database had when it was opened.
+pub fn migrate(db: &sled::Db) -> anyhow::Result<u32> {
+    let original = match stored_version(db)? {
+        Some(version) => version,
+        None if is_empty(db)? => {
+            set_version(db, SCHEMA_VERSION)?;
+            return Ok(SCHEMA_VERSION);
+        }
+        // Written before the version was stored.
+        None => 0,
+    };
+    if original > SCHEMA_VERSION {
+        return Err(anyhow!(
+            "Database schema version {} is newer than version {} supported by this build",
+            original,
+            SCHEMA_VERSION
+        ));
+    }
+
+    for version in original..SCHEMA_VERSION {
+        info!(
+            "Migrating database schema from version {} to {}",
+            version,
+            version + 1
+        );
+        MIGRATIONS[version as usize](db)
+            .with_context(|| format!("Migrating database schema from version {}", version))?;
+        set_version(db, version + 1)?;
+    }
+    Ok(original)
+}
+
+pub fn stored_version(db: &sled::Db) -> anyhow::Result<Option<u32>> {
+    Ok(match db.get(VERSION_KEY)? {
+        Some(value) => Some(u32::from_be_bytes(
+            <[u8; 4]>::try_from(value.as_ref()).context("Database schema version")?,
+        )),
+        None => None,
+    })
+}
+
+fn set_version(db: &sled::Db, version: u32) -> anyhow::Result<()> {
+    db.insert(VERSION_KEY, &version.to_be_bytes())?;
+    db.flush()?;
+    Ok(())
+}
+
+fn is_empty(db: &sled::Db) -> anyhow::Result<bool> {
+    for name in db.tree_names() {
+        if !db.open_tree(name)?.is_empty() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Databases from before the version was stored already have the version 1 layout, they just need
+/// to be marked as such.
+fn unversioned_to_1(_db: &sled::Db) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Version 2 keeps the epoch of every block. Blocks that are already stored get the epoch they
+/// would have had if they were added now: an epoch block sets it, and other blocks take the highest
+/// epoch of their previous block and the send block they receive.
+fn add_block_epochs(db: &sled::Db) -> anyhow::Result<()> {
+    let blocks = db.open_tree("blocks")?;
+    let epochs = db.open_tree("block_epochs")?;
+    for entry in blocks.iter() {
+        let (key, _) = entry?;
+        let mut stack = vec![BlockHash::try_from(key.as_ref())?];
+        while let Some(hash) = stack.last().cloned() {
+            if epochs.contains_key(hash.as_bytes())? {
+                stack.pop();
+                continue;
+            }
+            let data = blocks
+                .get(hash.as_bytes())?
+                .with_context(|| format!("Missing block {:?}", hash))?;
+            let block = codec::decode_block(&data)?;
+
+            let mut dependencies = vec![];
+            let mut previous_balance = Rai::zero();
+            if let Previous::Block(previous) = block.previous() {
+                dependencies.push(previous.to_owned());
+                if let Some(data) = blocks.get(previous.as_bytes())? {
+                    previous_balance = codec::decode_block(&data)?.balance().to_owned();
+                }
+            }
+            if let Link::Source(source) = block.link() {
+                dependencies.push(source.to_owned());
+            }
+            let mut epoch = Epoch::V0;
+            let mut missing = vec![];
+            for dependency in dependencies {
+                match epochs.get(dependency.as_bytes())? {
+                    Some(value) => epoch = epoch.max(Epoch::try_from(value[0])?),
+                    // E.g. the source of the genesis block, which isn't a block in the ledger.
+                    None if !blocks.contains_key(dependency.as_bytes())? => {}
+                    None => missing.push(dependency),
+                }
+            }
+            if !missing.is_empty() {
+                stack.extend(missing);
+                continue;
+            }
+            let epoch = Epoch::upgrade_of(&block, &previous_balance).unwrap_or(epoch);
+            epochs.insert(hash.as_bytes(), &[epoch as u8])?;
+            stack.pop();
+        }
+    }
+    Ok(())
+}
+
+/// Version 3 keeps the height of every block in its account chain, so it doesn't have to be
+/// counted by walking the chain. Each chain is walked once, from its latest block to its open block.
+fn add_block_heights(db: &sled::Db) -> anyhow::Result<()> {
+    let blocks = db.open_tree("blocks")?;
+    let latest = db.open_tree("latest")?;
+    let heights = db.open_tree("block_heights")?;
+    // Counting the blocks scans the whole tree, so it's only done once.
+    let block_count = blocks.len();
+    for entry in latest.iter() {
+        let (account, frontier) = entry?;
+        let mut chain = vec![BlockHash::try_from(frontier.as_ref())?];
+        loop {
+            let hash = &chain[chain.len() - 1];
+            let data = blocks
+                .get(hash.as_bytes())?
+                .with_context(|| format!("Missing block {:?}", hash))?;
+            match codec::decode_block(&data)?.previous() {
+                Previous::Block(previous) => chain.push(previous.to_owned()),
+                Previous::Open => break,
+            }
+            if chain.len() > block_count {
+                return Err(anyhow!("Account chain of {:?} has a loop", account));
+            }
+        }
+        for (height, hash) in chain.iter().rev().enumerate() {
+            heights.insert(hash.as_bytes(), &(height as u64 + 1).to_be_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Version 4 keeps each unchecked block under its own key of the dependency then when it was
+/// added, instead of a list of blocks per dependency, so the oldest can be dropped. Blocks that are
+/// already unchecked keep their order.
+///
+/// The blocks of each dependency are moved in one transaction, so an interrupted migration never
+/// leaves them under both layouts.
+fn unchecked_by_age(db: &sled::Db) -> anyhow::Result<()> {
+    let unchecked = db.open_tree("unchecked")?;
+    let ages = db.open_tree("unchecked_age")?;
+    for entry in unchecked.iter() {
+        let (dependency, value) = entry?;
+        // Keys of the new layout are longer, e.g. when an interrupted migration is run again.
+        if dependency.len() != BlockHash::LEN {
+            continue;
+        }
+        let mut unchecked_batch = sled::Batch::default();
+        let mut age_batch = sled::Batch::default();
+        let blocks = serde_json::from_slice::<Vec<BlockHolder>>(&value)?;
+        for block in blocks {
+            let age = db.generate_id()?;
+            let mut key = dependency.to_vec();
+            key.extend_from_slice(&age.to_be_bytes());
+            unchecked_batch.insert(key, serde_json::to_vec(&block)?);
+            age_batch.insert(&age.to_be_bytes(), dependency.as_ref());
+        }
+        unchecked_batch.remove(&dependency);
+        (&unchecked, &ages)
+            .transaction(|(unchecked, ages)| -> ConflictableTransactionResult<()> {
+                unchecked.apply_batch(&unchecked_batch)?;
+                ages.apply_batch(&age_batch)?;
+                Ok(())
+            })
+            .map_err(|err| anyhow!("Moving unchecked blocks of {:?}: {:?}", dependency, err))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary() -> sled::Db {
+        sled::Config::new().temporary(true).open().unwrap()
+    }
+
+    #[test]
+    fn fresh() {
+        let db = temporary();
+        assert_eq!(migrate(&db).unwrap(), SCHEMA_VERSION);
+        assert_eq!(stored_version(&db).unwrap(), Some(SCHEMA_VERSION));
+        // Opening again doesn't migrate anything.
+        assert_eq!(migrate(&db).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn unversioned() {
+        let db = temporary();
+        db.open_tree("cookies").unwrap().insert("a", "b").unwrap();
+        assert_eq!(migrate(&db).unwrap(), 0);
+        assert_eq!(stored_version(&db).unwrap(), Some(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn block_epochs() {
+        use crate::blocks::{Block, BlockType, ValidationState};
+        use crate::network::Network;
+        use crate::Public;
+        use std::str::FromStr;
+
+        let db = temporary();
+        set_version(&db, 1).unwrap();
+        let genesis = Network::Live.genesis_block();
+        let upgrade = Block::new(
+            BlockType::State,
+            genesis.account().to_owned(),
+            Previous::Block(genesis.hash().unwrap().to_owned()),
+            genesis.representative().to_owned(),
+            Rai::max(),
+            Epoch::V1.link().unwrap(),
+            ValidationState::Valid,
+        );
+        let change = Block::new(
+            BlockType::State,
+            genesis.account().to_owned(),
+            Previous::Block(upgrade.hash().unwrap().to_owned()),
+            Public::from_str(&"11".repeat(32)).unwrap(),
+            Rai::max(),
+            Link::Nothing,
+            ValidationState::Valid,
+        );
+        // A send to the account matching the epoch 2 link doesn't upgrade anything.
+        let send = Block::new(
+            BlockType::State,
+            genesis.account().to_owned(),
+            Previous::Block(change.hash().unwrap().to_owned()),
+            change.representative().to_owned(),
+            Rai::from(1),
+            Link::DestinationAccount(
+                Public::try_from(Epoch::V2.link().unwrap().as_bytes()).unwrap(),
+            ),
+            ValidationState::Valid,
+        );
+        let tree = db.open_tree("blocks").unwrap();
+        for block in &[&genesis, &upgrade, &change, &send] {
+            tree.insert(block.hash().unwrap().as_bytes(), codec::encode_block(block))
+                .unwrap();
+        }
+
+        assert_eq!(migrate(&db).unwrap(), 1);
+        let epochs = db.open_tree("block_epochs").unwrap();
+        let epoch = |block: &Block| epochs.get(block.hash().unwrap().as_bytes()).unwrap();
+        assert_eq!(epoch(&genesis).unwrap().as_ref(), &[0]);
+        assert_eq!(epoch(&upgrade).unwrap().as_ref(), &[1]);
+        assert_eq!(epoch(&change).unwrap().as_ref(), &[1]);
+        assert_eq!(epoch(&send).unwrap().as_ref(), &[1]);
+    }
+
+    #[test]
+    fn block_heights() {
+        use crate::blocks::{Block, BlockType, ValidationState};
+        use crate::network::Network;
+
+        let db = temporary();
+        set_version(&db, 2).unwrap();
+        let genesis = Network::Live.genesis_block();
+        let change = Block::new(
+            BlockType::State,
+            genesis.account().to_owned(),
+            Previous::Block(genesis.hash().unwrap().to_owned()),
+            genesis.representative().to_owned(),
+            Rai::max(),
+            Link::Nothing,
+            ValidationState::Valid,
+        );
+        let tree = db.open_tree("blocks").unwrap();
+        for block in &[&genesis, &change] {
+            tree.insert(block.hash().unwrap().as_bytes(), codec::encode_block(block))
+                .unwrap();
+        }
+        db.open_tree("latest")
+            .unwrap()
+            .insert(
+                genesis.account().as_bytes(),
+                change.hash().unwrap().as_bytes(),
+            )
+            .unwrap();
+
+        assert_eq!(migrate(&db).unwrap(), 2);
+        let heights = db.open_tree("block_heights").unwrap();
+        let height = |block: &Block| heights.get(block.hash().unwrap().as_bytes()).unwrap();
+        assert_eq!(height(&genesis).unwrap().as_ref(), &1u64.to_be_bytes());
+        assert_eq!(height(&change).unwrap().as_ref(), &2u64.to_be_bytes());
+    }
+
+    #[test]
+    fn unchecked_by_age() {
+        use crate::blocks::SendBlock;
+
+        let db = temporary();
+        set_version(&db, 3).unwrap();
+        let dependency = BlockHash::zero();
+        let blocks: Vec<BlockHolder> = (1..=2)
+            .map(|balance| {
+                BlockHolder::Send(SendBlock::new(
+                    dependency.to_owned(),
+                    crate::Public::zero(),
+                    Rai::from(balance),
+                ))
+            })
+            .collect();
+        db.open_tree("unchecked")
+            .unwrap()
+            .insert(dependency.as_bytes(), serde_json::to_vec(&blocks).unwrap())
+            .unwrap();
+
+        assert_eq!(migrate(&db).unwrap(), 3);
+        let unchecked = db.open_tree("unchecked").unwrap();
+        let migrated: Vec<BlockHolder> = unchecked
+            .scan_prefix(dependency.as_bytes())
+            .map(|entry| serde_json::from_slice(&entry.unwrap().1).unwrap())
+            .collect();
+        assert_eq!(migrated, blocks);
+        assert!(unchecked.get(dependency.as_bytes()).unwrap().is_none());
+        assert_eq!(db.open_tree("unchecked_age").unwrap().len(), 2);
+
+        // Running the step again, as after an interruption, doesn't move anything twice.
+        super::unchecked_by_age(&db).unwrap();
+        assert_eq!(unchecked.len(), 2);
+        assert_eq!(db.open_tree("unchecked_age").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn newer() {
+        let db = temporary();
+        set_version(&db, SCHEMA_VERSION + 1).unwrap();
+        assert!(migrate(&db).is_err());
+        // The version is left alone.
+        assert_eq!(stored_version(&db).unwrap(), Some(SCHEMA_VERSION + 1));
+    }
+}
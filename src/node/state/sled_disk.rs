@@ -1,68 +1,719 @@
-use crate::blocks::{Block, BlockHash};
+use crate::blocks::{Block, BlockHash, BlockHolder, Epoch, Link, Previous};
+use crate::bytes::Bytes;
 use crate::network::Network;
 use crate::node::cookie::Cookie;
-use crate::node::state::State;
-use crate::Public;
+use crate::node::election::Election;
+use crate::node::state::{codec, schema};
+use crate::node::state::{
+    BlockChange, ConfirmationHeight, LedgerUpdate, Pending, State, MAX_UNCHECKED,
+};
+use crate::node::timestamp::Timestamp;
+use crate::{Public, Rai};
+use anyhow::{anyhow, Context};
 use async_trait::async_trait;
-use std::collections::HashSet;
+use sled::transaction::{ConflictableTransactionResult, Transactional};
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The hash of the genesis block of the network, in the default tree.
+const GENESIS_KEY: &[u8] = b"genesis_hash";
 
 /// Sled is an on disk key value pair.
+///
+/// The ledger, pending entries, unchecked blocks, votes, representative weights and peers are
+/// kept on disk. Active elections and when representatives last voted only make sense while the
+/// node is running, so they are kept in memory.
 #[derive(Clone, Debug)]
 pub struct SledDiskState {
     network: Network,
     db: sled::Db,
     cookies: sled::Tree,
     peers: sled::Tree,
+    pending: sled::Tree,
+
+    /// Block hash to the encoded block.
+    blocks: sled::Tree,
+
+    /// Account to the hash of its latest block.
+    latest: sled::Tree,
+
+    /// Account to when it was last modified, in milliseconds since the unix epoch.
+    account_modified: sled::Tree,
+
+    /// Hash of a send block to the hash of the block that received it.
+    receivers: sled::Tree,
+
+    /// Account to its confirmation height then the confirmed frontier.
+    confirmation_heights: sled::Tree,
+
+    /// Block hash to the epoch of its account chain as of that block.
+    block_epochs: sled::Tree,
+
+    /// Block hash to its height in its account chain.
+    block_heights: sled::Tree,
+
+    /// Hash of a missing dependency then when the block was added, to a block waiting for it, as
+    /// JSON.
+    unchecked: sled::Tree,
+
+    /// When an unchecked block was added, to the hash of its dependency.
+    unchecked_age: sled::Tree,
+
+    /// The number of unchecked blocks, so it doesn't have to be counted on every add.
+    unchecked_len: usize,
+    max_unchecked: usize,
+
+    /// Block hash then representative to the timestamp of the vote.
+    votes: sled::Tree,
+
+    /// Representative to its weight.
+    rep_weights: sled::Tree,
+
+    elections: HashMap<BlockHash, Election>,
+    rep_last_vote: HashMap<Public, SystemTime>,
 }
 
 impl SledDiskState {
-    pub fn new(network: Network) -> Self {
-        let path = format!("{:?}.db", network).to_ascii_lowercase();
-        let db: sled::Db =
-            sled::open(&path).unwrap_or_else(|_| panic!("Could not open database: {}", &path));
-        let cookies = db.open_tree("cookies").unwrap();
-        let peers = db.open_tree("peers").unwrap();
-        Self {
+    /// Open (or create) the database for `network` inside `data_dir`.
+    ///
+    /// Every dev network has its own genesis block, so their databases are named after the hash
+    /// of it.
+    pub fn new(network: Network, data_dir: &Path) -> anyhow::Result<Self> {
+        let name = match network {
+            Network::Dev(_) => format!("{}-{}", network, network.genesis_hash().as_hex()),
+            _ => network.to_string(),
+        };
+        let path = data_dir.join(format!("{}.db", name));
+        let db =
+            sled::open(&path).with_context(|| format!("Could not open database: {:?}", &path))?;
+        Self::with_db(network, db)
+    }
+
+    /// The database is migrated to the current [schema::SCHEMA_VERSION] before anything else touches it.
+    fn with_db(network: Network, db: sled::Db) -> anyhow::Result<Self> {
+        schema::migrate(&db).context("Opening database")?;
+        Self::check_genesis(&network, &db).context("Opening database")?;
+        let tree = |name: &str| {
+            db.open_tree(name)
+                .with_context(|| format!("Could not open tree: {}", name))
+        };
+        Ok(Self {
             network,
+            cookies: tree("cookies")?,
+            peers: tree("peers")?,
+            pending: tree("pending")?,
+            blocks: tree("blocks")?,
+            latest: tree("latest")?,
+            account_modified: tree("account_modified")?,
+            receivers: tree("receivers")?,
+            confirmation_heights: tree("confirmation_heights")?,
+            block_epochs: tree("block_epochs")?,
+            block_heights: tree("block_heights")?,
+            unchecked: tree("unchecked")?,
+            unchecked_len: tree("unchecked_age")?.len(),
+            unchecked_age: tree("unchecked_age")?,
+            max_unchecked: MAX_UNCHECKED,
+            votes: tree("votes")?,
+            rep_weights: tree("rep_weights")?,
             db,
-            cookies,
-            peers,
+            elections: HashMap::new(),
+            rep_last_vote: HashMap::new(),
+        })
+    }
+
+    /// Refuse a database holding the ledger of another network, remembering the network of a
+    /// database that doesn't know yet.
+    fn check_genesis(network: &Network, db: &sled::Db) -> anyhow::Result<()> {
+        let genesis_hash = network.genesis_hash();
+        match db.get(GENESIS_KEY)? {
+            Some(stored) if stored.as_ref() == genesis_hash.as_bytes() => Ok(()),
+            Some(stored) => Err(anyhow!(
+                "Database belongs to the network with genesis block {:?} instead of {:?}",
+                BlockHash::try_from(stored.as_ref())?,
+                genesis_hash
+            )),
+            None => {
+                db.insert(GENESIS_KEY, genesis_hash.as_bytes())?;
+                Ok(())
+            }
         }
     }
+
+    /// The height a block gets when it's added after its previous block.
+    fn next_height(&self, block: &Block) -> anyhow::Result<u64> {
+        Ok(match block.previous() {
+            Previous::Block(previous) => {
+                let value = self
+                    .block_heights
+                    .get(previous.as_bytes())?
+                    .with_context(|| format!("Missing previous block {:?}", previous))?;
+                u64::from_be_bytes(<[u8; 8]>::try_from(value.as_ref())?) + 1
+            }
+            Previous::Open => 1,
+        })
+    }
+
+    /// Pending entries are keyed by destination then send hash, so they can be scanned by
+    /// destination in send hash order.
+    fn pending_key(destination: &Public, send_hash: &BlockHash) -> Vec<u8> {
+        let mut key = Vec::with_capacity(Public::LEN + BlockHash::LEN);
+        key.extend_from_slice(destination.as_bytes());
+        key.extend_from_slice(send_hash.as_bytes());
+        key
+    }
+
+    fn encode_pending(pending: &Pending) -> Vec<u8> {
+        let mut value = Vec::with_capacity(Public::LEN + Rai::LEN);
+        value.extend_from_slice(pending.source.as_bytes());
+        value.extend_from_slice(&pending.amount.to_vec());
+        value
+    }
+
+    fn decode_pending(key: &[u8], value: &[u8]) -> anyhow::Result<Pending> {
+        let mut key = Bytes::new(key);
+        key.slice(Public::LEN)?;
+        let send_hash = BlockHash::try_from(key.slice(BlockHash::LEN)?)?;
+        let mut value = Bytes::new(value);
+        let source = Public::try_from(value.slice(Public::LEN)?)?;
+        let amount = Rai::try_from(value.slice(Rai::LEN)?)?;
+        Ok(Pending {
+            send_hash,
+            source,
+            amount,
+        })
+    }
+
+    /// Unchecked blocks are keyed by dependency then age, so the blocks waiting for a dependency
+    /// can be scanned in the order they were added.
+    fn unchecked_key(dependency: &BlockHash, age: u64) -> Vec<u8> {
+        let mut key = Vec::with_capacity(BlockHash::LEN + 8);
+        key.extend_from_slice(dependency.as_bytes());
+        key.extend_from_slice(&age.to_be_bytes());
+        key
+    }
+
+    /// Votes are keyed by block hash then representative, so they can be scanned by block.
+    fn vote_key(hash: &BlockHash, representative: &Public) -> Vec<u8> {
+        let mut key = Vec::with_capacity(BlockHash::LEN + Public::LEN);
+        key.extend_from_slice(hash.as_bytes());
+        key.extend_from_slice(representative.as_bytes());
+        key
+    }
+
+    fn account_modified(&self, account: &Public) -> anyhow::Result<Option<SystemTime>> {
+        Ok(match self.account_modified.get(account.as_bytes())? {
+            Some(value) => {
+                let millis = u64::from_be_bytes(<[u8; 8]>::try_from(value.as_ref())?);
+                Some(UNIX_EPOCH + Duration::from_millis(millis))
+            }
+            None => None,
+        })
+    }
+}
+
+/// Writes to the ledger trees, collected so they can be applied in one transaction.
+#[derive(Default)]
+struct LedgerBatches {
+    blocks: sled::Batch,
+    latest: sled::Batch,
+    account_modified: sled::Batch,
+    receivers: sled::Batch,
+    block_epochs: sled::Batch,
+    block_heights: sled::Batch,
+    pending: sled::Batch,
+    rep_weights: sled::Batch,
+}
+
+impl LedgerBatches {
+    fn add_block(&mut self, block: &Block, height: u64) -> anyhow::Result<()> {
+        let hash = block.hash()?;
+        self.blocks
+            .insert(hash.as_bytes(), codec::encode_block(block));
+        self.block_heights
+            .insert(hash.as_bytes(), &height.to_be_bytes());
+        self.latest
+            .insert(block.account().as_bytes(), hash.as_bytes());
+        if let Link::Source(source) = block.link() {
+            self.receivers.insert(source.as_bytes(), hash.as_bytes());
+        }
+        self.set_account_modified(block.account());
+        Ok(())
+    }
+
+    fn remove_block(&mut self, block: &Block) -> anyhow::Result<()> {
+        let hash = block.hash()?;
+        self.blocks.remove(hash.as_bytes());
+        self.block_epochs.remove(hash.as_bytes());
+        self.block_heights.remove(hash.as_bytes());
+        if let Link::Source(source) = block.link() {
+            self.receivers.remove(source.as_bytes());
+        }
+        match block.previous() {
+            Previous::Block(previous) => {
+                self.latest
+                    .insert(block.account().as_bytes(), previous.as_bytes());
+                self.set_account_modified(block.account());
+            }
+            Previous::Open => {
+                self.latest.remove(block.account().as_bytes());
+                self.account_modified.remove(block.account().as_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    fn set_account_modified(&mut self, account: &Public) {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.account_modified
+            .insert(account.as_bytes(), &millis.to_be_bytes());
+    }
+
+    fn apply(self, state: &SledDiskState) -> anyhow::Result<()> {
+        (
+            &state.blocks,
+            &state.latest,
+            &state.account_modified,
+            &state.receivers,
+            &state.block_epochs,
+            &state.block_heights,
+            &state.pending,
+            &state.rep_weights,
+        )
+            .transaction(
+                |(
+                    blocks,
+                    latest,
+                    account_modified,
+                    receivers,
+                    block_epochs,
+                    block_heights,
+                    pending,
+                    rep_weights,
+                )|
+                 -> ConflictableTransactionResult<()> {
+                    blocks.apply_batch(&self.blocks)?;
+                    latest.apply_batch(&self.latest)?;
+                    account_modified.apply_batch(&self.account_modified)?;
+                    receivers.apply_batch(&self.receivers)?;
+                    block_epochs.apply_batch(&self.block_epochs)?;
+                    block_heights.apply_batch(&self.block_heights)?;
+                    pending.apply_batch(&self.pending)?;
+                    rep_weights.apply_batch(&self.rep_weights)?;
+                    Ok(())
+                },
+            )
+            .map_err(|err| anyhow!("Writing to the ledger: {:?}", err))
+    }
 }
 
 #[async_trait]
 impl State for SledDiskState {
-    async fn add_block(&mut self, _block_holder: &Block) -> anyhow::Result<()> {
-        unimplemented!()
+    async fn add_block(&mut self, block: &Block) -> anyhow::Result<()> {
+        let mut batches = LedgerBatches::default();
+        let height = self.next_height(block).context("Add block")?;
+        batches.add_block(block, height).context("Add block")?;
+        batches.apply(self)
     }
 
-    async fn get_block_by_hash(&self, _hash: &BlockHash) -> anyhow::Result<Option<Block>> {
-        unimplemented!()
+    async fn remove_block(&mut self, block: &Block) -> anyhow::Result<()> {
+        let mut batches = LedgerBatches::default();
+        batches.remove_block(block).context("Remove block")?;
+        batches.apply(self)
+    }
+
+    /// Every change is written in one transaction over the trees involved.
+    async fn apply_update(&mut self, update: &LedgerUpdate) -> anyhow::Result<()> {
+        let mut batches = LedgerBatches::default();
+        match &update.block {
+            BlockChange::Add(block, epoch) => {
+                batches.add_block(block, self.next_height(block)?)?;
+                batches
+                    .block_epochs
+                    .insert(block.hash()?.as_bytes(), &[*epoch as u8]);
+            }
+            BlockChange::Remove(block) => batches.remove_block(block)?,
+        }
+        for (destination, send_hash) in &update.remove_pending {
+            batches
+                .pending
+                .remove(Self::pending_key(destination, send_hash));
+        }
+        for (destination, pending) in &update.add_pending {
+            batches.pending.insert(
+                Self::pending_key(destination, &pending.send_hash),
+                Self::encode_pending(pending),
+            );
+        }
+        for (representative, weight) in &update.rep_weights {
+            if weight == &Rai::zero() {
+                batches.rep_weights.remove(representative.as_bytes());
+            } else {
+                batches
+                    .rep_weights
+                    .insert(representative.as_bytes(), weight.to_vec());
+            }
+        }
+        batches.apply(self)
+    }
+
+    async fn get_block_by_hash(&self, hash: &BlockHash) -> anyhow::Result<Option<Block>> {
+        Ok(match self.blocks.get(hash.as_bytes())? {
+            Some(data) => Some(
+                codec::decode_block(&data).with_context(|| format!("Decoding block {:?}", hash))?,
+            ),
+            None => None,
+        })
+    }
+
+    async fn block_height(&self, hash: &BlockHash) -> anyhow::Result<Option<u64>> {
+        Ok(match self.block_heights.get(hash.as_bytes())? {
+            Some(value) => Some(u64::from_be_bytes(<[u8; 8]>::try_from(value.as_ref())?)),
+            None => None,
+        })
     }
 
     async fn get_latest_block_hash_for_account(
         &self,
-        _account: &Public,
+        account: &Public,
     ) -> anyhow::Result<Option<BlockHash>> {
-        unimplemented!()
+        Ok(match self.latest.get(account.as_bytes())? {
+            Some(hash) => Some(BlockHash::try_from(hash.as_ref())?),
+            None => None,
+        })
     }
 
     async fn account_for_block_hash(
         &mut self,
-        _block_hash: &BlockHash,
+        block_hash: &BlockHash,
     ) -> Result<Option<Public>, anyhow::Error> {
-        unimplemented!()
+        Ok(self
+            .get_block_by_hash(block_hash)
+            .await?
+            .map(|block| block.account().to_owned()))
+    }
+
+    async fn block_hash_receiving(
+        &self,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<BlockHash>> {
+        Ok(match self.receivers.get(send_hash.as_bytes())? {
+            Some(hash) => Some(BlockHash::try_from(hash.as_ref())?),
+            None => None,
+        })
+    }
+
+    async fn add_pending(&mut self, destination: &Public, pending: &Pending) -> anyhow::Result<()> {
+        self.pending.insert(
+            Self::pending_key(destination, &pending.send_hash),
+            Self::encode_pending(pending),
+        )?;
+        Ok(())
+    }
+
+    async fn remove_pending(
+        &mut self,
+        destination: &Public,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<Pending>> {
+        let key = Self::pending_key(destination, send_hash);
+        Ok(match self.pending.remove(&key)? {
+            Some(value) => Some(Self::decode_pending(&key, &value)?),
+            None => None,
+        })
+    }
+
+    async fn get_pending(
+        &self,
+        destination: &Public,
+        send_hash: &BlockHash,
+    ) -> anyhow::Result<Option<Pending>> {
+        let key = Self::pending_key(destination, send_hash);
+        Ok(match self.pending.get(&key)? {
+            Some(value) => Some(Self::decode_pending(&key, &value)?),
+            None => None,
+        })
+    }
+
+    async fn pending(
+        &self,
+        destination: &Public,
+        minimum_amount: &Rai,
+    ) -> anyhow::Result<Vec<Pending>> {
+        let mut pending = vec![];
+        for entry in self.pending.scan_prefix(destination.as_bytes()) {
+            let (key, value) = entry?;
+            let entry = Self::decode_pending(&key, &value)?;
+            if &entry.amount >= minimum_amount {
+                pending.push(entry);
+            }
+        }
+        Ok(pending)
+    }
+
+    async fn frontiers(
+        &self,
+        start: &Public,
+        max_age: Option<Duration>,
+        count: usize,
+    ) -> anyhow::Result<Vec<(Public, BlockHash)>> {
+        let now = SystemTime::now();
+        let mut frontiers = vec![];
+        // Sled keeps keys in byte order, which is the order frontiers are sent in.
+        for entry in self.latest.range(start.as_bytes()..) {
+            if frontiers.len() >= count {
+                break;
+            }
+            let (account, hash) = entry?;
+            let account = Public::try_from(account.as_ref())?;
+            if let Some(max_age) = max_age {
+                let is_recent = match self.account_modified(&account)? {
+                    Some(modified) => now.duration_since(modified).unwrap_or_default() <= max_age,
+                    None => false,
+                };
+                if !is_recent {
+                    continue;
+                }
+            }
+            frontiers.push((account, BlockHash::try_from(hash.as_ref())?));
+        }
+        Ok(frontiers)
+    }
+
+    async fn confirmation_height(
+        &self,
+        account: &Public,
+    ) -> anyhow::Result<Option<ConfirmationHeight>> {
+        Ok(match self.confirmation_heights.get(account.as_bytes())? {
+            Some(value) => {
+                let mut value = Bytes::new(&value);
+                let height = u64::from_be_bytes(<[u8; 8]>::try_from(value.slice(8)?)?);
+                let frontier = BlockHash::try_from(value.slice(BlockHash::LEN)?)?;
+                Some(ConfirmationHeight { height, frontier })
+            }
+            None => None,
+        })
+    }
+
+    async fn set_confirmation_height(
+        &mut self,
+        account: &Public,
+        confirmation_height: &ConfirmationHeight,
+    ) -> anyhow::Result<()> {
+        let mut value = Vec::with_capacity(8 + BlockHash::LEN);
+        value.extend_from_slice(&confirmation_height.height.to_be_bytes());
+        value.extend_from_slice(confirmation_height.frontier.as_bytes());
+        self.confirmation_heights
+            .insert(account.as_bytes(), value)?;
+        Ok(())
+    }
+
+    async fn block_epoch(&self, hash: &BlockHash) -> anyhow::Result<Option<Epoch>> {
+        Ok(match self.block_epochs.get(hash.as_bytes())? {
+            Some(value) => Some(Epoch::try_from(
+                *value.first().context("Empty block epoch")?,
+            )?),
+            None => None,
+        })
+    }
+
+    async fn set_block_epoch(&mut self, hash: &BlockHash, epoch: Epoch) -> anyhow::Result<()> {
+        self.block_epochs.insert(hash.as_bytes(), &[epoch as u8])?;
+        Ok(())
+    }
+
+    async fn add_unchecked(
+        &mut self,
+        dependency: &BlockHash,
+        block: &BlockHolder,
+    ) -> anyhow::Result<()> {
+        for entry in self.unchecked.scan_prefix(dependency.as_bytes()) {
+            let (_, value) = entry?;
+            if &serde_json::from_slice::<BlockHolder>(&value)? == block {
+                return Ok(());
+            }
+        }
+        let age = self.db.generate_id()?;
+        let key = Self::unchecked_key(dependency, age);
+        let value = serde_json::to_vec(block)?;
+        (&self.unchecked, &self.unchecked_age)
+            .transaction(
+                |(unchecked, unchecked_age)| -> ConflictableTransactionResult<()> {
+                    unchecked.insert(key.as_slice(), value.as_slice())?;
+                    unchecked_age.insert(&age.to_be_bytes(), dependency.as_bytes())?;
+                    Ok(())
+                },
+            )
+            .map_err(|err| anyhow!("Adding unchecked block: {:?}", err))?;
+        self.unchecked_len += 1;
+
+        while self.unchecked_len > self.max_unchecked {
+            let (age, dependency) = match self.unchecked_age.first()? {
+                Some(entry) => entry,
+                None => break,
+            };
+            let dependency = BlockHash::try_from(dependency.as_ref())?;
+            let age = u64::from_be_bytes(<[u8; 8]>::try_from(age.as_ref())?);
+            let key = Self::unchecked_key(&dependency, age);
+            (&self.unchecked, &self.unchecked_age)
+                .transaction(
+                    |(unchecked, unchecked_age)| -> ConflictableTransactionResult<()> {
+                        unchecked.remove(key.as_slice())?;
+                        unchecked_age.remove(&age.to_be_bytes())?;
+                        Ok(())
+                    },
+                )
+                .map_err(|err| anyhow!("Dropping unchecked block: {:?}", err))?;
+            self.unchecked_len -= 1;
+        }
+        Ok(())
+    }
+
+    async fn take_unchecked(&mut self, dependency: &BlockHash) -> anyhow::Result<Vec<BlockHolder>> {
+        let mut blocks = vec![];
+        let mut unchecked_batch = sled::Batch::default();
+        let mut age_batch = sled::Batch::default();
+        for entry in self.unchecked.scan_prefix(dependency.as_bytes()) {
+            let (key, value) = entry?;
+            blocks.push(serde_json::from_slice(&value)?);
+            age_batch.remove(&key[BlockHash::LEN..]);
+            unchecked_batch.remove(key);
+        }
+        (&self.unchecked, &self.unchecked_age)
+            .transaction(
+                |(unchecked, unchecked_age)| -> ConflictableTransactionResult<()> {
+                    unchecked.apply_batch(&unchecked_batch)?;
+                    unchecked_age.apply_batch(&age_batch)?;
+                    Ok(())
+                },
+            )
+            .map_err(|err| anyhow!("Taking unchecked blocks: {:?}", err))?;
+        self.unchecked_len = self.unchecked_len.saturating_sub(blocks.len());
+        Ok(blocks)
+    }
+
+    async fn unchecked_count(&self) -> anyhow::Result<usize> {
+        Ok(self.unchecked_len)
     }
 
     async fn add_vote(
         &mut self,
-        _hash: &BlockHash,
-        _representative: &Public,
+        hash: &BlockHash,
+        representative: &Public,
+        timestamp: &Timestamp,
     ) -> anyhow::Result<()> {
-        unimplemented!()
+        self.votes
+            .insert(Self::vote_key(hash, representative), &timestamp.to_bytes())?;
+        Ok(())
+    }
+
+    async fn latest_vote_timestamp(
+        &self,
+        hash: &BlockHash,
+        representative: &Public,
+    ) -> anyhow::Result<Option<Timestamp>> {
+        Ok(
+            match self.votes.get(Self::vote_key(hash, representative))? {
+                Some(value) => Some(Timestamp::try_from(value.as_ref())?),
+                None => None,
+            },
+        )
+    }
+
+    async fn votes_for_block(&self, hash: &BlockHash) -> anyhow::Result<Vec<(Public, Timestamp)>> {
+        let mut votes = vec![];
+        for entry in self.votes.scan_prefix(hash.as_bytes()) {
+            let (key, value) = entry?;
+            let representative = Public::try_from(&key[BlockHash::LEN..])?;
+            votes.push((representative, Timestamp::try_from(value.as_ref())?));
+        }
+        Ok(votes)
+    }
+
+    async fn remove_votes(&mut self, hash: &BlockHash) -> anyhow::Result<()> {
+        let mut batch = sled::Batch::default();
+        for key in self.votes.scan_prefix(hash.as_bytes()).keys() {
+            batch.remove(key?);
+        }
+        self.votes.apply_batch(batch)?;
+        Ok(())
+    }
+
+    async fn election(&self, root: &BlockHash) -> anyhow::Result<Option<Election>> {
+        Ok(self.elections.get(root).map(|e| e.to_owned()))
+    }
+
+    async fn election_root_for_block(&self, hash: &BlockHash) -> anyhow::Result<Option<BlockHash>> {
+        Ok(self
+            .elections
+            .values()
+            .find(|election| election.candidate(hash).is_some())
+            .map(|election| election.root.to_owned()))
+    }
+
+    async fn set_election(&mut self, election: &Election) -> anyhow::Result<()> {
+        self.elections
+            .insert(election.root.to_owned(), election.to_owned());
+        Ok(())
+    }
+
+    async fn remove_election(&mut self, root: &BlockHash) -> anyhow::Result<()> {
+        self.elections.remove(root);
+        Ok(())
+    }
+
+    async fn expired_elections(&self, now: SystemTime) -> anyhow::Result<Vec<BlockHash>> {
+        Ok(self
+            .elections
+            .values()
+            .filter(|election| election.is_expired(now))
+            .map(|election| election.root.to_owned())
+            .collect())
+    }
+
+    async fn rep_weight(&self, representative: &Public) -> anyhow::Result<Rai> {
+        Ok(match self.rep_weights.get(representative.as_bytes())? {
+            Some(value) => Rai::try_from(value.as_ref())?,
+            None => Rai::zero(),
+        })
+    }
+
+    async fn set_rep_weight(
+        &mut self,
+        representative: &Public,
+        weight: &Rai,
+    ) -> anyhow::Result<()> {
+        if weight == &Rai::zero() {
+            self.rep_weights.remove(representative.as_bytes())?;
+        } else {
+            self.rep_weights
+                .insert(representative.as_bytes(), weight.to_vec())?;
+        }
+        Ok(())
+    }
+
+    async fn set_rep_last_vote(
+        &mut self,
+        representative: &Public,
+        time: SystemTime,
+    ) -> anyhow::Result<()> {
+        self.rep_last_vote.insert(representative.to_owned(), time);
+        Ok(())
+    }
+
+    async fn reps_voted_since(&self, since: SystemTime) -> anyhow::Result<Vec<Public>> {
+        Ok(self
+            .rep_last_vote
+            .iter()
+            .filter(|(_, time)| **time >= since)
+            .map(|(rep, _)| rep.to_owned())
+            .collect())
     }
 
     async fn set_cookie(&mut self, socket_addr: SocketAddr, cookie: Cookie) -> anyhow::Result<()> {
@@ -82,11 +733,314 @@ impl State for SledDiskState {
         })
     }
 
-    async fn add_peers(&mut self, _addresses: &[SocketAddr]) -> Result<(), anyhow::Error> {
-        unimplemented!()
+    async fn add_peers(&mut self, addresses: &[SocketAddr]) -> Result<(), anyhow::Error> {
+        for address in addresses {
+            self.peers.insert(format!("{}", address), &[])?;
+        }
+        Ok(())
     }
 
     async fn peers(&self) -> Result<HashSet<SocketAddr>, anyhow::Error> {
-        unimplemented!()
+        let mut peers = HashSet::new();
+        for entry in self.peers.iter() {
+            let (key, _) = entry?;
+            let address = std::str::from_utf8(&key)?;
+            peers.insert(
+                SocketAddr::from_str(address)
+                    .with_context(|| format!("Bad peer address: {}", address))?,
+            );
+        }
+        Ok(peers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn temporary() -> SledDiskState {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        SledDiskState::with_db(Network::Live, db).unwrap()
+    }
+
+    #[tokio::test]
+    async fn pending() {
+        let mut state = temporary();
+        let destination =
+            Public::from_str("E89208DD038FBB269987689621D52292AE9C35941A7484756ECCED92A65093BA")
+                .unwrap();
+        let small = Pending {
+            send_hash: BlockHash::from_str(
+                "0000000000000000000000000000000000000000000000000000000000000001",
+            )
+            .unwrap(),
+            source: Public::zero(),
+            amount: Rai::from(5),
+        };
+        let large = Pending {
+            send_hash: BlockHash::from_str(
+                "0000000000000000000000000000000000000000000000000000000000000002",
+            )
+            .unwrap(),
+            source: Public::zero(),
+            amount: Rai::from(500),
+        };
+        state.add_pending(&destination, &large).await.unwrap();
+        state.add_pending(&destination, &small).await.unwrap();
+        state.add_pending(&Public::zero(), &small).await.unwrap();
+
+        let all = state.pending(&destination, &Rai::zero()).await.unwrap();
+        assert_eq!(all, vec![small.clone(), large.clone()]);
+        let over = state.pending(&destination, &Rai::from(6)).await.unwrap();
+        assert_eq!(over, vec![large.clone()]);
+
+        let removed = state
+            .remove_pending(&destination, &small.send_hash)
+            .await
+            .unwrap();
+        assert_eq!(removed, Some(small.clone()));
+        assert!(state
+            .get_pending(&destination, &small.send_hash)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            state
+                .get_pending(&Public::zero(), &small.send_hash)
+                .await
+                .unwrap(),
+            Some(small)
+        );
+    }
+
+    #[tokio::test]
+    async fn blocks() {
+        let mut state = temporary();
+        let genesis = Network::Live.genesis_block();
+        let hash = genesis.hash().unwrap().to_owned();
+        let account = genesis.account().to_owned();
+        state.add_block(&genesis).await.unwrap();
+
+        // Every field survives the round trip, including the signature and work.
+        let stored = state.get_block_by_hash(&hash).await.unwrap().unwrap();
+        assert_eq!(stored, genesis);
+        assert_eq!(
+            state
+                .get_latest_block_hash_for_account(&account)
+                .await
+                .unwrap(),
+            Some(hash.to_owned())
+        );
+        assert_eq!(
+            state.account_for_block_hash(&hash).await.unwrap(),
+            Some(account.to_owned())
+        );
+        assert_eq!(
+            state.frontiers(&Public::zero(), None, 10).await.unwrap(),
+            vec![(account.to_owned(), hash.to_owned())]
+        );
+        assert_eq!(
+            state
+                .frontiers(&Public::zero(), Some(Duration::from_secs(60)), 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        state.remove_block(&genesis).await.unwrap();
+        assert!(state.get_block_by_hash(&hash).await.unwrap().is_none());
+        assert!(state
+            .get_latest_block_hash_for_account(&account)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(state
+            .frontiers(&Public::zero(), None, 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn votes_and_weights() {
+        let mut state = temporary();
+        let hash = Network::Live.genesis_hash();
+        let rep = Public::zero();
+        state
+            .add_vote(&hash, &rep, &Timestamp::from_u64(5))
+            .await
+            .unwrap();
+        state
+            .add_vote(&hash, &rep, &Timestamp::from_u64(6))
+            .await
+            .unwrap();
+        assert_eq!(
+            state.latest_vote_timestamp(&hash, &rep).await.unwrap(),
+            Some(Timestamp::from_u64(6))
+        );
+        assert_eq!(
+            state.votes_for_block(&hash).await.unwrap(),
+            vec![(rep.to_owned(), Timestamp::from_u64(6))]
+        );
+
+        // Only the votes for the block are removed.
+        let other = BlockHash::from_str(&"1".repeat(64)).unwrap();
+        state
+            .add_vote(&other, &rep, &Timestamp::from_u64(7))
+            .await
+            .unwrap();
+        state.remove_votes(&hash).await.unwrap();
+        assert!(state.votes_for_block(&hash).await.unwrap().is_empty());
+        assert_eq!(state.votes_for_block(&other).await.unwrap().len(), 1);
+
+        state.set_rep_weight(&rep, &Rai::from(10)).await.unwrap();
+        assert_eq!(state.rep_weight(&rep).await.unwrap(), Rai::from(10));
+        state.set_rep_weight(&rep, &Rai::zero()).await.unwrap();
+        assert_eq!(state.rep_weight(&rep).await.unwrap(), Rai::zero());
+    }
+
+    /// A block is written together with its epoch, pending entries and weights, and removed the
+    /// same way.
+    #[tokio::test]
+    async fn ledger_update() {
+        let mut state = temporary();
+        let genesis = Network::Live.genesis_block();
+        let hash = genesis.hash().unwrap().to_owned();
+        let destination = Public::zero();
+        let pending = Pending {
+            send_hash: hash.to_owned(),
+            source: genesis.account().to_owned(),
+            amount: Rai::from(5),
+        };
+        let rep = genesis.account().to_owned();
+
+        state
+            .apply_update(&LedgerUpdate {
+                block: BlockChange::Add(genesis.to_owned(), Epoch::V1),
+                add_pending: vec![(destination.to_owned(), pending.to_owned())],
+                remove_pending: vec![],
+                rep_weights: vec![(rep.to_owned(), Rai::from(10))],
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            state.get_block_by_hash(&hash).await.unwrap(),
+            Some(genesis.to_owned())
+        );
+        assert_eq!(state.block_epoch(&hash).await.unwrap(), Some(Epoch::V1));
+        assert_eq!(
+            state.get_pending(&destination, &hash).await.unwrap(),
+            Some(pending)
+        );
+        assert_eq!(state.rep_weight(&rep).await.unwrap(), Rai::from(10));
+
+        state
+            .apply_update(&LedgerUpdate {
+                block: BlockChange::Remove(genesis.to_owned()),
+                add_pending: vec![],
+                remove_pending: vec![(destination.to_owned(), hash.to_owned())],
+                rep_weights: vec![(rep.to_owned(), Rai::zero())],
+            })
+            .await
+            .unwrap();
+        assert!(state.get_block_by_hash(&hash).await.unwrap().is_none());
+        assert!(state.block_epoch(&hash).await.unwrap().is_none());
+        assert!(state
+            .get_pending(&destination, &hash)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(state.rep_weight(&rep).await.unwrap(), Rai::zero());
+        assert!(state
+            .get_latest_block_hash_for_account(genesis.account())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    /// The oldest unchecked blocks are dropped once there are too many.
+    #[tokio::test]
+    async fn unchecked() {
+        use crate::blocks::SendBlock;
+
+        let mut state = temporary();
+        state.max_unchecked = 2;
+        let first = BlockHash::zero();
+        let second = Network::Live.genesis_hash();
+        let block = |balance: u128| {
+            BlockHolder::Send(SendBlock::new(
+                BlockHash::zero(),
+                Public::zero(),
+                Rai::from(balance),
+            ))
+        };
+
+        state.add_unchecked(&first, &block(1)).await.unwrap();
+        state.add_unchecked(&first, &block(2)).await.unwrap();
+        // Already waiting, so it doesn't count twice.
+        state.add_unchecked(&first, &block(2)).await.unwrap();
+        assert_eq!(state.unchecked_count().await.unwrap(), 2);
+
+        state.add_unchecked(&second, &block(3)).await.unwrap();
+        assert_eq!(state.unchecked_count().await.unwrap(), 2);
+        assert_eq!(state.take_unchecked(&first).await.unwrap(), vec![block(2)]);
+        assert_eq!(state.take_unchecked(&second).await.unwrap(), vec![block(3)]);
+        assert_eq!(state.unchecked_count().await.unwrap(), 0);
+        assert!(state.unchecked_age.is_empty());
+    }
+
+    #[test]
+    fn other_network() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        SledDiskState::with_db(Network::Live, db.clone()).unwrap();
+        SledDiskState::with_db(Network::Live, db.clone()).unwrap();
+        assert!(SledDiskState::with_db(Network::Beta, db).is_err());
+    }
+
+    /// The ledger is still there after the database is closed and opened again.
+    #[tokio::test]
+    async fn reopen() {
+        let dir = std::env::temp_dir().join(format!("feeless-test-{}", rand::random::<u64>()));
+        let written = dir.join("written");
+        let reopened = dir.join("reopened");
+        std::fs::create_dir_all(&written).unwrap();
+        let genesis = Network::Live.genesis_block();
+        let peer = SocketAddr::from_str("127.0.0.1:7075").unwrap();
+        {
+            let mut state = SledDiskState::new(Network::Live, &written).unwrap();
+            state.add_block(&genesis).await.unwrap();
+            state.add_peers(&[peer]).await.unwrap();
+            state.db.flush().unwrap();
+        }
+
+        // Everything is on disk once the last handle is dropped, but sled's background threads can
+        // hold the lock on the directory for a moment after that, so a copy is opened instead.
+        copy_dir(&written, &reopened);
+        let state = SledDiskState::new(Network::Live, &reopened).unwrap();
+        assert_eq!(
+            state
+                .get_block_by_hash(genesis.hash().unwrap())
+                .await
+                .unwrap(),
+            Some(genesis.to_owned())
+        );
+        assert!(state.peers().await.unwrap().contains(&peer));
+        drop(state);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn copy_dir(from: &Path, to: &Path) {
+        std::fs::create_dir_all(to).unwrap();
+        for entry in std::fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            let target = to.join(entry.file_name());
+            if entry.file_type().unwrap().is_dir() {
+                copy_dir(&entry.path(), &target);
+            } else {
+                std::fs::copy(entry.path(), target).unwrap();
+            }
+        }
     }
 }
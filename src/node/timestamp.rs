@@ -3,7 +3,7 @@ use anyhow::Context;
 use std::convert::TryFrom;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Eq, PartialEq, Debug, Clone)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Clone)]
 pub struct Timestamp(u64);
 
 impl Timestamp {
@@ -21,6 +21,12 @@ impl Timestamp {
         Self(s)
     }
 
+    /// A final vote has the largest timestamp, so no later vote from the representative can
+    /// replace it.
+    pub fn is_final(&self) -> bool {
+        self.0 == u64::MAX
+    }
+
     fn to_u64(&self) -> u64 {
         self.0
     }
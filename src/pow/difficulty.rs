@@ -26,6 +26,11 @@ impl Difficulty {
         Self::from_str("FFFFFFF800000000").unwrap()
     }
 
+    /// The threshold for every block before the epoch 2 upgrade.
+    pub fn epoch_1() -> Self {
+        Self::from_str("FFFFFFC000000000").unwrap()
+    }
+
     pub fn from_fixed_slice(s: &[u8; Self::LEN]) -> Result<Self> {
         Ok(Difficulty(u64::from_le_bytes(*s)))
     }
@@ -83,6 +83,19 @@ impl Work {
         return Ok(work);
     }
 
+    /// Legacy blocks are sent over the network with the work in little endian.
+    pub fn from_le_slice(s: &[u8]) -> crate::Result<Self> {
+        let mut work = Self::try_from(s)?;
+        work.0.reverse();
+        Ok(work)
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = self.0;
+        bytes.reverse();
+        bytes
+    }
+
     pub fn hash(work_and_subject: &[u8]) -> Box<[u8]> {
         blake2b(Self::LEN, work_and_subject)
     }
@@ -0,0 +1,44 @@
+//! Runs the simulation the way another crate would, through the public API only. It needs the
+//! `simulation` feature, e.g. `cargo test --features simulation --test simulation`.
+use feeless::blocks::Link;
+use feeless::simulation::Simulation;
+use feeless::{Rai, Seed};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A node that is added and connected after the others takes part in the next election, with the
+/// block and its vote reaching it through another node.
+#[tokio::test(start_paused = true)]
+async fn late_node_follows_elections() {
+    let mut simulation = Simulation::new(2).await.unwrap();
+    let late = simulation.add_node().await.unwrap();
+    simulation.connect(late, 0).await;
+
+    let genesis = simulation.network().genesis_block();
+    let receiver = Seed::random().derive(0).to_public().unwrap();
+    let balance = simulation
+        .ledger(1)
+        .account_balance(genesis.account())
+        .await
+        .unwrap();
+    let send = simulation
+        .state_block(
+            1,
+            simulation.genesis_private(),
+            balance.checked_sub(&Rai::from(10)).unwrap(),
+            Link::DestinationAccount(receiver),
+        )
+        .await
+        .unwrap();
+    let hash = send.hash().unwrap().to_owned();
+
+    simulation.publish(1, &send).await.unwrap();
+    simulation.vote(1, &hash).await.unwrap();
+    for index in 0..=late {
+        simulation
+            .wait_for_confirmation(index, &hash, TIMEOUT)
+            .await
+            .unwrap();
+    }
+}